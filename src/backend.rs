@@ -0,0 +1,275 @@
+//! Measurement backends: how the metrics of a benchmark run are collected.
+
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    child::Outcome,
+    metrics::{self, Metric, Reading},
+    perf_stat, qemu, valgrind, RunOptions,
+};
+#[cfg(target_os = "linux")]
+use crate::{container, metrics::Counters, roi};
+
+/// Collects the metrics of successive runs of benchmark processes.
+pub trait Backend {
+    /// Turns the benchmark process into the one to spawn, for backends running it under a
+    /// tool.
+    fn wrap(&mut self, command: Command) -> Command {
+        command
+    }
+    /// Called right before a benchmark process is spawned.
+    fn start(&mut self) -> std::io::Result<()>;
+    /// Called right after the benchmark process has been reaped, with how it ended.
+    fn finish(&mut self, outcome: &Outcome) -> std::io::Result<Reading>;
+    /// Requested metrics that cannot be measured on this machine.
+    fn unavailable(&self) -> &[&'static str];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// Hardware and software counters of perf events, Linux only.
+    Perf,
+    /// The same counters read by running the benchmarks under `perf stat`, for environments
+    /// where the perf binary is allowed to open them but other processes are not.
+    PerfStat,
+    /// Wall-clock time and the resource usage reported by the operating system.
+    Portable,
+    /// Valgrind's Cachegrind, slow but deterministic and independent of the hardware.
+    Cachegrind,
+    /// Valgrind's Callgrind, like Cachegrind but also counting the instructions of each
+    /// function.
+    Callgrind,
+    /// A QEMU user-mode emulator with its instruction-counting plugin, for reproducible counts
+    /// of binaries of any architecture.
+    Qemu,
+}
+
+impl Kind {
+    pub fn platform_default() -> Self {
+        if cfg!(target_os = "linux") {
+            Kind::Perf
+        } else {
+            Kind::Portable
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Perf => "perf",
+            Kind::PerfStat => "perf-stat",
+            Kind::Portable => "portable",
+            Kind::Cachegrind => "cachegrind",
+            Kind::Callgrind => "callgrind",
+            Kind::Qemu => "qemu",
+        }
+    }
+
+    pub fn default_metrics(self) -> &'static [&'static str] {
+        match self {
+            Kind::Perf | Kind::PerfStat => metrics::DEFAULT_METRICS,
+            Kind::Portable => metrics::PORTABLE_METRICS,
+            Kind::Cachegrind => metrics::VALGRIND_METRICS,
+            Kind::Callgrind => &["cg_instructions"],
+            Kind::Qemu => &["qemu_instructions"],
+        }
+    }
+}
+
+pub fn open(opts: &RunOptions) -> anyhow::Result<Box<dyn Backend>> {
+    let kind = opts.backend();
+    let mut metrics = Vec::new();
+    for name in opts.metrics() {
+        let metric = metrics::lookup(&name)?;
+        if metric.is_from_backend() {
+            metrics.push(metric);
+        }
+    }
+    if let Some(metric) = metrics.iter().find(|m| !m.supported_by(kind)) {
+        anyhow::bail!(
+            "{} cannot be measured by the {} backend",
+            metric.name,
+            kind.name()
+        );
+    }
+    #[cfg(not(target_os = "linux"))]
+    if opts.container.is_some() {
+        anyhow::bail!("--container measures in a cgroup, only available on Linux");
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(image) = &opts.container {
+        if kind != Kind::Perf {
+            anyhow::bail!(
+                "--container measures with the perf backend, not the {} one",
+                kind.name()
+            );
+        }
+        if !opts.rlimits().is_empty() {
+            anyhow::bail!("resource limits cannot be applied with --container");
+        }
+        if opts.roi || opts.skip_start.is_some() {
+            anyhow::bail!("--roi and --skip-start cannot be used with --container");
+        }
+        return Ok(Box::new(container::Container::new(
+            image,
+            opts.container_runtime.as_deref(),
+            metrics,
+            (&opts.pin, opts.numa_node),
+        )?));
+    }
+    if opts.roi && opts.skip_start.is_some() {
+        anyhow::bail!("--roi and --skip-start cannot be combined");
+    }
+    if (opts.roi || opts.skip_start.is_some()) && !matches!(kind, Kind::Perf | Kind::PerfStat) {
+        anyhow::bail!(
+            "--roi and --skip-start need the perf or perf-stat backend, not the {} one",
+            kind.name()
+        );
+    }
+    Ok(match kind {
+        #[cfg(not(target_os = "linux"))]
+        Kind::Perf => {
+            anyhow::bail!("the perf backend counts with perf events, only available on Linux")
+        }
+        #[cfg(target_os = "linux")]
+        Kind::Perf => {
+            // Started before the counters are opened, for them not to count their threads.
+            let mut control = opts.roi.then(roi::Control::new).transpose()?;
+            let mut delay = opts.skip_start.map(roi::Delay::new);
+            let names: Vec<String> = metrics.iter().map(|m| m.name.to_owned()).collect();
+            let mut counters = Counters::open(&names, control.is_none() && delay.is_none())?;
+            if let Some(control) = &mut control {
+                control.attach(counters.fds());
+            }
+            if let Some(delay) = &mut delay {
+                delay.attach(counters.fds());
+            }
+            Box::new(Perf {
+                counters,
+                from_outcome: metrics.into_iter().filter(|m| m.is_outcome()).collect(),
+                control,
+                delay,
+                started: Instant::now(),
+            })
+        }
+        Kind::PerfStat => Box::new(perf_stat::PerfStat::new(
+            metrics,
+            opts.roi,
+            opts.skip_start,
+        )?),
+        Kind::Portable => Box::new(Portable {
+            metrics,
+            started: Instant::now(),
+        }),
+        Kind::Cachegrind => Box::new(valgrind::Valgrind::new(
+            valgrind::Tool::Cachegrind,
+            metrics,
+        )?),
+        Kind::Callgrind => Box::new(valgrind::Valgrind::new(valgrind::Tool::Callgrind, metrics)?),
+        Kind::Qemu => Box::new(qemu::Qemu::new(
+            metrics,
+            opts.qemu.as_deref(),
+            opts.qemu_plugin.as_deref(),
+        )?),
+    })
+}
+
+fn insert_outcome_values(
+    reading: &mut Reading,
+    metrics: &[&Metric],
+    outcome: &Outcome,
+    wall_time: Duration,
+) {
+    for metric in metrics {
+        if let Some(value) = metric.outcome_value(outcome, wall_time) {
+            reading.metrics.insert(metric.name.to_owned(), value);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct Perf {
+    counters: Counters,
+    /// Metrics not counted by perf events.
+    from_outcome: Vec<&'static Metric>,
+    /// FIFO through which the benchmarks enable the counters, with --roi.
+    control: Option<roi::Control>,
+    /// Timer enabling the counters, with --skip-start.
+    delay: Option<roi::Delay>,
+    started: Instant,
+}
+
+#[cfg(target_os = "linux")]
+impl Backend for Perf {
+    fn wrap(&mut self, mut command: Command) -> Command {
+        if let Some(control) = &self.control {
+            command.env(roi::ENV, control.path());
+        }
+        command
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        self.counters.reopen()?;
+        if let Some(control) = &mut self.control {
+            control.attach(self.counters.fds());
+        }
+        if let Some(delay) = &mut self.delay {
+            delay.attach(self.counters.fds());
+            delay.start();
+        }
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    fn finish(&mut self, outcome: &Outcome) -> std::io::Result<Reading> {
+        let mut wall_time = self.started.elapsed();
+        if let Some(control) = &mut self.control {
+            let regions = control.finish()?;
+            if regions.count == 0 {
+                tracing::warn!("the benchmark enabled no counter through {}", roi::ENV);
+            }
+            wall_time = regions.wall_time;
+        }
+        if let Some(delay) = &mut self.delay {
+            let regions = delay.finish()?;
+            if regions.count == 0 {
+                tracing::warn!("the benchmark exited before --skip-start, counting nothing");
+            }
+            wall_time = regions.wall_time;
+        }
+        self.counters.disable()?;
+        let mut reading = self.counters.read()?;
+        insert_outcome_values(&mut reading, &self.from_outcome, outcome, wall_time);
+        Ok(reading)
+    }
+
+    fn unavailable(&self) -> &[&'static str] {
+        &self.counters.unavailable
+    }
+}
+
+struct Portable {
+    metrics: Vec<&'static Metric>,
+    started: Instant,
+}
+
+impl Backend for Portable {
+    fn start(&mut self) -> std::io::Result<()> {
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    fn finish(&mut self, outcome: &Outcome) -> std::io::Result<Reading> {
+        let wall_time = self.started.elapsed();
+        let mut reading = Reading::default();
+        insert_outcome_values(&mut reading, &self.metrics, outcome, wall_time);
+        Ok(reading)
+    }
+
+    fn unavailable(&self) -> &[&'static str] {
+        &[]
+    }
+}
@@ -0,0 +1,21 @@
+//! Classification of the commits tested by `bisect`.
+
+use crate::{compare, results::Results};
+
+/// Change in percent of `metric` from `base` to `candidate`, as the geometric mean of the
+/// ratios over the benchmarks measured in both.
+pub fn change(base: &Results, candidate: &Results, metric: &str) -> anyhow::Result<f64> {
+    let ratios: Vec<f64> = base
+        .iter()
+        .filter_map(|(key, measure)| {
+            let base = *measure.metrics.get(metric)?;
+            let candidate = *candidate.get(key)?.metrics.get(metric)?;
+            (base > 0. && candidate > 0.).then(|| candidate / base)
+        })
+        .collect();
+    if ratios.is_empty() {
+        anyhow::bail!("no benchmark has a nonzero {metric} on both commits");
+    }
+    let mean = (ratios.iter().map(|r| r.ln()).sum::<f64>() / ratios.len() as f64).exp();
+    Ok(compare::rel_diff(1., mean))
+}
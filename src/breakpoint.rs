@@ -0,0 +1,186 @@
+//! Hit counts of hardware breakpoints and watchpoints, to count how often the benchmarks run
+//! the code or access the data at an address, such as a function of a runtime or a global.
+
+#[cfg(target_os = "linux")]
+use std::process::Command;
+
+#[cfg(target_os = "linux")]
+use perf_event as prf;
+#[cfg(target_os = "linux")]
+use prf::events::Breakpoint as Event;
+
+#[cfg(target_os = "linux")]
+use crate::{child::Outcome, collector::Collector, metrics::Metrics};
+
+/// What accesses to the address are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Exec,
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn name(self) -> &'static str {
+        match self {
+            Access::Exec => "exec",
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::ReadWrite => "rw",
+        }
+    }
+}
+
+/// A breakpoint counted as the metric `name`, given as `NAME=KIND:ADDRESS[/LENGTH]`, e.g.
+/// `gc_calls=exec:0x401a20` or `counter_writes=write:0x604040/8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub name: String,
+    pub access: Access,
+    pub address: u64,
+    /// Bytes watched from the address by a data breakpoint: 1, 2, 4 or 8.
+    pub len: u64,
+}
+
+impl std::str::FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("expected NAME=KIND:ADDRESS[/LENGTH], got {s:?}");
+        let (name, spec) = s.split_once('=').ok_or_else(usage)?;
+        let (kind, location) = spec.split_once(':').ok_or_else(usage)?;
+        if name.is_empty() {
+            return Err(usage());
+        }
+        let access = match kind {
+            "exec" => Access::Exec,
+            "read" => Access::Read,
+            "write" => Access::Write,
+            "rw" => Access::ReadWrite,
+            _ => {
+                return Err(format!(
+                    "unknown breakpoint kind {kind:?}, expected exec, read, write or rw"
+                ))
+            }
+        };
+        let (address, len) = match location.split_once('/') {
+            Some((address, len)) => (address, Some(len)),
+            None => (location, None),
+        };
+        let address = match address.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => address.parse(),
+        }
+        .map_err(|e| format!("invalid address {address:?}: {e}"))?;
+        let len = match (access, len) {
+            (Access::Exec, Some(_)) => {
+                return Err(format!("{name}: execution breakpoints have no length"))
+            }
+            (Access::Exec, None) => 0,
+            (_, None) => 8,
+            (_, Some(len)) => match len.parse() {
+                Ok(len @ (1 | 2 | 4 | 8)) => len,
+                _ => {
+                    return Err(format!(
+                        "{name}: invalid length {len:?}, expected 1, 2, 4 or 8"
+                    ))
+                }
+            },
+        };
+        Ok(Breakpoint {
+            name: name.to_owned(),
+            access,
+            address,
+            len,
+        })
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={}:{:#x}",
+            self.name,
+            self.access.name(),
+            self.address
+        )?;
+        if self.access != Access::Exec {
+            write!(f, "/{}", self.len)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Breakpoint {
+    fn event(&self) -> Event {
+        match self.access {
+            Access::Exec => Event::execute(self.address),
+            Access::Read => Event::read(self.address, self.len),
+            Access::Write => Event::write(self.address, self.len),
+            Access::ReadWrite => Event::read_write(self.address, self.len),
+        }
+    }
+}
+
+/// Counts the hits of breakpoints in the benchmark process tree, with counters opened afresh
+/// before each run, inherited by the benchmark and enabled when it execs.
+#[cfg(target_os = "linux")]
+pub struct Breakpoints {
+    breakpoints: Vec<Breakpoint>,
+    counters: Vec<prf::Counter>,
+    values: Metrics,
+}
+
+#[cfg(target_os = "linux")]
+impl Breakpoints {
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+        Breakpoints {
+            breakpoints,
+            counters: Vec::new(),
+            values: Metrics::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Collector for Breakpoints {
+    fn metrics(&self) -> Vec<String> {
+        self.breakpoints.iter().map(|b| b.name.clone()).collect()
+    }
+
+    fn start(&mut self, _command: &mut Command) -> anyhow::Result<()> {
+        self.counters = self
+            .breakpoints
+            .iter()
+            .map(|breakpoint| {
+                prf::Builder::new(breakpoint.event())
+                    .inherit(true)
+                    .enable_on_exec(true)
+                    .build()
+                    .map_err(|e| {
+                        anyhow::anyhow!(
+                            "cannot set the hardware breakpoint {breakpoint} ({e}), the CPU has \
+                             few debug registers and some kinds are not supported everywhere"
+                        )
+                    })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(())
+    }
+
+    fn stop(&mut self, _outcome: &Outcome) -> anyhow::Result<()> {
+        for (breakpoint, counter) in self.breakpoints.iter().zip(&mut self.counters) {
+            self.values
+                .insert(breakpoint.name.clone(), counter.read()? as f64);
+        }
+        self.counters.clear();
+        Ok(())
+    }
+
+    fn read(&mut self) -> anyhow::Result<Metrics> {
+        Ok(std::mem::take(&mut self.values))
+    }
+}
@@ -0,0 +1,54 @@
+//! On-disk cache of measurements, keyed by what was run.
+
+use std::path::{Path, PathBuf};
+
+use crate::hash::{sha256, sha256_file};
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+pub fn default_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".cache")))?;
+    Some(base.join("perf-bench-orchestrator"))
+}
+
+impl Cache {
+    pub fn open(dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Computes the key of a run of `command`, on `input` if any.
+    ///
+    /// `binary_hash` is the hash of the program `command` runs, and `fingerprint` must capture
+    /// every other option that can influence the measurement.
+    pub fn key(
+        command: &str,
+        binary_hash: &str,
+        input: Option<&Path>,
+        fingerprint: &str,
+    ) -> std::io::Result<String> {
+        let input_hash = input.map(sha256_file).transpose()?.unwrap_or_default();
+        Ok(sha256(
+            [command, binary_hash, &input_hash, fingerprint]
+                .join("\0")
+                .as_bytes(),
+        ))
+    }
+
+    pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let file = std::fs::read_to_string(self.dir.join(key).with_extension("json")).ok()?;
+        serde_json::from_str(&file).ok()
+    }
+
+    pub fn put<T: serde::Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        let path = self.dir.join(key).with_extension("json");
+        let tmp = path.with_extension("tmp");
+        serde_json::to_writer(std::fs::File::create(&tmp)?, value)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+//! Detection of the runs of the history at which a metric stepped to a new level, with PELT
+//! (pruned exact linear time) over the mean of the segments.
+
+use crate::{compare, history::Run, metrics, results, trend};
+
+/// A step of a metric of a benchmark between two segments of the history.
+pub struct Step {
+    pub benchmark: String,
+    pub metric: String,
+    /// Revision, or else date, of the first run of the new level.
+    pub label: String,
+    pub date: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+impl Step {
+    pub fn change(&self) -> f64 {
+        compare::rel_diff(self.before, self.after)
+    }
+}
+
+/// Cost of fitting `values[start..end]` with their mean: the sum of their squared deviations,
+/// from the prefix sums of the values and of their squares.
+fn cost(sums: &[f64], squares: &[f64], start: usize, end: usize) -> f64 {
+    let n = (end - start) as f64;
+    let sum = sums[end] - sums[start];
+    (squares[end] - squares[start]) - sum * sum / n
+}
+
+/// Indexes at which a new segment starts in the optimal segmentation of `values` with a cost of
+/// `penalty` per changepoint.
+fn pelt(values: &[f64], penalty: f64) -> Vec<usize> {
+    let n = values.len();
+    let mut sums = vec![0.; n + 1];
+    let mut squares = vec![0.; n + 1];
+    for (i, value) in values.iter().enumerate() {
+        sums[i + 1] = sums[i] + value;
+        squares[i + 1] = squares[i] + value * value;
+    }
+    // best[t] is the cost of the optimal segmentation of values[..t], whose last segment starts
+    // at last[t].
+    let mut best = vec![-penalty; n + 1];
+    let mut last = vec![0; n + 1];
+    let mut candidates = vec![0];
+    for t in 1..=n {
+        let (start, total) = candidates
+            .iter()
+            .map(|&s| (s, best[s] + cost(&sums, &squares, s, t) + penalty))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("there is always a candidate");
+        best[t] = total;
+        last[t] = start;
+        candidates.retain(|&s| best[s] + cost(&sums, &squares, s, t) <= best[t]);
+        candidates.push(t);
+    }
+    let mut starts = Vec::new();
+    let mut t = n;
+    while last[t] > 0 {
+        starts.push(last[t]);
+        t = last[t];
+    }
+    starts.reverse();
+    starts
+}
+
+/// Changepoints of `values` with a change of at least `min_change` percent, as the index of
+/// their first value and the means of the segments around them.
+pub fn detect(values: &[f64], min_change: f64) -> Vec<(usize, f64, f64)> {
+    if values.len() < 2 {
+        return Vec::new();
+    }
+    // Relative values, so that the penalty doesn't depend on the magnitude of the metric.
+    let scale = metrics::median_of(values.to_vec());
+    if scale == 0. {
+        return Vec::new();
+    }
+    let relative: Vec<f64> = values.iter().map(|value| value / scale).collect();
+    // Robust estimate of the noise from the differences between consecutive runs, which steps
+    // barely affect.
+    let diffs = relative.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    let sigma = metrics::median_of(diffs) / (0.6745 * std::f64::consts::SQRT_2);
+    let noise_penalty = 2. * sigma * sigma * (values.len() as f64).ln();
+    // A step of min_change percent over a single run still pays off.
+    let step_penalty = (min_change / 100.).powi(2) / 4.;
+    let starts = pelt(&relative, noise_penalty.max(step_penalty));
+    let mut bounds = vec![0];
+    bounds.extend(&starts);
+    bounds.push(values.len());
+    let mean = |segment: &[f64]| segment.iter().sum::<f64>() / segment.len() as f64;
+    bounds
+        .windows(3)
+        .map(|w| (w[1], mean(&values[w[0]..w[1]]), mean(&values[w[1]..w[2]])))
+        .filter(|&(_, before, after)| compare::rel_diff(before, after).abs() >= min_change)
+        .collect()
+}
+
+/// The steps of at least `min_change` percent of `metrics` of `benchmarks` over `runs`, all the
+/// recorded ones if empty.
+pub fn scan(runs: &[Run], benchmarks: &[String], metrics: &[String], min_change: f64) -> Vec<Step> {
+    let mut keys: Vec<&String> = Vec::new();
+    for run in runs {
+        for key in run.file.results.keys() {
+            if (benchmarks.is_empty() || benchmarks.contains(key)) && !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys.sort();
+    let names = if metrics.is_empty() {
+        results::metric_names(runs.iter().map(|run| &run.file.results))
+    } else {
+        metrics.to_vec()
+    };
+    let mut steps = Vec::new();
+    for key in keys {
+        for metric in &names {
+            let points = trend::points(runs, key, metric);
+            let values: Vec<f64> = points.iter().map(|point| point.value).collect();
+            for (index, before, after) in detect(&values, min_change) {
+                steps.push(Step {
+                    benchmark: key.clone(),
+                    metric: metric.clone(),
+                    label: points[index].label.clone(),
+                    date: points[index].date.clone(),
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_a_step() {
+        let values = [100., 101., 99., 100., 100., 120., 121., 119., 120., 120.];
+        let steps = detect(&values, 5.);
+        assert_eq!(steps.len(), 1);
+        let (index, before, after) = steps[0];
+        assert_eq!(index, 5);
+        assert_eq!(before, 100.);
+        assert_eq!(after, 120.);
+    }
+
+    #[test]
+    fn ignores_noise_and_small_steps() {
+        assert!(detect(&[100., 103., 98., 101., 99., 102., 97., 100.], 5.).is_empty());
+        assert!(detect(&[100., 100., 100., 102., 102., 102.], 5.).is_empty());
+    }
+
+    #[test]
+    fn detects_two_steps() {
+        let values = [10., 10., 10., 10., 20., 20., 20., 20., 10., 10., 10., 10.];
+        let indexes: Vec<usize> = detect(&values, 5.).iter().map(|step| step.0).collect();
+        assert_eq!(indexes, [4, 8]);
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        assert!(detect(&[], 5.).is_empty());
+        assert!(detect(&[1.], 5.).is_empty());
+        assert!(detect(&[0., 0., 0.], 5.).is_empty());
+        assert!(detect(&[3., 3., 3., 3.], 5.).is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+//! Running a benchmark process and collecting how it ended.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus},
+    sync::mpsc,
+    time::Duration,
+};
+
+use crate::{monitor, numa};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    pub resource: Resource,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    As,
+    Core,
+    Cpu,
+    Data,
+    Fsize,
+    Memlock,
+    Nofile,
+    Nproc,
+    Stack,
+}
+
+/// The type of the resources of `setrlimit`, which glibc makes an enum.
+#[cfg(target_env = "gnu")]
+type RawResource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type RawResource = libc::c_int;
+
+impl Resource {
+    fn raw(self) -> RawResource {
+        match self {
+            Resource::As => libc::RLIMIT_AS,
+            Resource::Core => libc::RLIMIT_CORE,
+            Resource::Cpu => libc::RLIMIT_CPU,
+            Resource::Data => libc::RLIMIT_DATA,
+            Resource::Fsize => libc::RLIMIT_FSIZE,
+            Resource::Memlock => libc::RLIMIT_MEMLOCK,
+            Resource::Nofile => libc::RLIMIT_NOFILE,
+            Resource::Nproc => libc::RLIMIT_NPROC,
+            Resource::Stack => libc::RLIMIT_STACK,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Resource::As => "as",
+            Resource::Core => "core",
+            Resource::Cpu => "cpu",
+            Resource::Data => "data",
+            Resource::Fsize => "fsize",
+            Resource::Memlock => "memlock",
+            Resource::Nofile => "nofile",
+            Resource::Nproc => "nproc",
+            Resource::Stack => "stack",
+        }
+    }
+}
+
+/// Parses a byte count with an optional binary K, M, G or T suffix.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 30),
+        Some((i, 't' | 'T')) => (&s[..i], 40),
+        _ => (s, 0),
+    };
+    let n: u64 = digits.parse().map_err(|_| format!("invalid size {s:?}"))?;
+    n.checked_mul(1 << shift)
+        .ok_or_else(|| format!("size {s:?} is too large"))
+}
+
+/// Parses a duration in seconds, or with an ns, us, ms, s, m or h suffix.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let seconds = match unit {
+        "ns" => 1e-9,
+        "us" => 1e-6,
+        "ms" => 1e-3,
+        "" | "s" => 1.,
+        "m" => 60.,
+        "h" => 3600.,
+        _ => return Err(format!("invalid duration unit {unit:?}")),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {s:?}"))?;
+    Duration::try_from_secs_f64(number * seconds).map_err(|_| format!("invalid duration {s:?}"))
+}
+
+impl std::fmt::Display for Rlimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}={}", self.resource.name(), self.value)
+    }
+}
+
+impl std::str::FromStr for Rlimit {
+    type Err = String;
+
+    /// Parses `RESOURCE=VALUE`, e.g. `nofile=1024` or `as=4G`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected RESOURCE=VALUE, got {s:?}"))?;
+        let resource = match name.to_ascii_lowercase().as_str() {
+            "as" => Resource::As,
+            "core" => Resource::Core,
+            "cpu" => Resource::Cpu,
+            "data" => Resource::Data,
+            "fsize" => Resource::Fsize,
+            "memlock" => Resource::Memlock,
+            "nofile" => Resource::Nofile,
+            "nproc" => Resource::Nproc,
+            "stack" => Resource::Stack,
+            _ => return Err(format!("unknown resource {name:?}")),
+        };
+        Ok(Rlimit {
+            resource,
+            value: parse_size(value)?,
+        })
+    }
+}
+
+pub struct Outcome {
+    pub pid: u32,
+    pub status: ExitStatus,
+    pub rusage: libc::rusage,
+    /// Fields of `/proc/<pid>/io` read when the process exited, including the I/O of its
+    /// reaped descendants.
+    pub io: HashMap<String, u64>,
+    /// CPUs its threads last ran on, read when it exited.
+    pub last_cpus: BTreeSet<usize>,
+    /// Whether it was killed for running past the timeout.
+    pub timed_out: bool,
+}
+
+impl Outcome {
+    pub fn cpu_time(&self) -> Duration {
+        let tv = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        tv(self.rusage.ru_utime) + tv(self.rusage.ru_stime)
+    }
+
+    pub fn max_rss_bytes(&self) -> u64 {
+        self.rusage.ru_maxrss as u64 * 1024
+    }
+
+    /// Best-effort guess of which of `limits` made the run fail. Hitting a memory limit only
+    /// makes allocations fail, which programs handle in their own way, so it is not guessed.
+    pub fn limit_hit(&self, limits: &[Rlimit]) -> Option<&'static str> {
+        let limit = |resource| limits.iter().find(|l| l.resource == resource);
+        match self.status.signal() {
+            Some(libc::SIGKILL) if self.timed_out => Some("time"),
+            Some(libc::SIGXCPU) => Some("cpu"),
+            Some(libc::SIGXFSZ) => Some("fsize"),
+            Some(libc::SIGKILL)
+                if limit(Resource::Cpu)
+                    .is_some_and(|l| self.cpu_time() >= Duration::from_secs(l.value)) =>
+            {
+                Some("cpu")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The name of `signal`, e.g. `SIGSEGV`.
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => return format!("signal {signal}"),
+    };
+    name.to_owned()
+}
+
+/// Runs `command` under `limits`, killed after `timeout` if given, pinned to the CPUs `pin` if
+/// it isn't empty and with its memory on the NUMA node `membind` if given, calling `on_spawn`
+/// with its pid once spawned.
+pub fn run(
+    command: &mut Command,
+    limits: &[Rlimit],
+    timeout: Option<Duration>,
+    pin: &[usize],
+    membind: Option<usize>,
+    on_spawn: impl FnOnce(u32),
+) -> std::io::Result<Outcome> {
+    let limits = limits.to_vec();
+    let membind = membind.map(numa::mask).transpose()?;
+    let cpu_set = (!pin.is_empty()).then(|| cpu_set(pin)).transpose()?;
+    if timeout.is_some() {
+        // In its own process group, for the timeout to kill the processes it started too.
+        command.process_group(0);
+    }
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(cpu_set) = &cpu_set {
+                set_affinity(cpu_set)?;
+            }
+            if let Some(mask) = &membind {
+                numa::bind_memory(mask)?;
+            }
+            for limit in &limits {
+                let rlimit = libc::rlimit {
+                    rlim_cur: limit.value,
+                    rlim_max: limit.value,
+                };
+                if libc::setrlimit(limit.resource.raw(), &rlimit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let child = command.spawn()?;
+    on_spawn(child.id());
+    let watchdog = timeout.map(|timeout| Watchdog::start(child.id(), timeout));
+    let (io, last_cpus) = wait_exited(child.id())
+        .map(|()| (read_io(child.id()), monitor::read_cpus(child.id())))
+        .unwrap_or_default();
+    // Stopped before reaping the process, so that its pid can't be reused by then.
+    let timed_out = watchdog.is_some_and(Watchdog::stop);
+    let mut wstatus = 0;
+    let mut rusage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    loop {
+        let ret = unsafe { libc::wait4(child.id() as _, &mut wstatus, 0, rusage.as_mut_ptr()) };
+        if ret >= 0 {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    Ok(Outcome {
+        pid: child.id(),
+        status: ExitStatus::from_raw(wstatus),
+        rusage: unsafe { rusage.assume_init() },
+        io,
+        last_cpus,
+        timed_out,
+    })
+}
+
+/// Thread killing a process once it has run for too long.
+struct Watchdog {
+    exited: mpsc::Sender<()>,
+    thread: std::thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn start(pid: u32, timeout: Duration) -> Self {
+        let (exited, exit) = mpsc::channel();
+        let thread = std::thread::spawn(move || match exit.recv_timeout(timeout) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+                true
+            }
+            _ => false,
+        });
+        Watchdog { exited, thread }
+    }
+
+    /// Stops watching the process, returning whether it was killed.
+    fn stop(self) -> bool {
+        drop(self.exited);
+        self.thread.join().expect("the watchdog doesn't panic")
+    }
+}
+
+/// The set of the CPUs `pin`, for [`set_affinity`].
+#[cfg(target_os = "linux")]
+fn cpu_set(pin: &[usize]) -> std::io::Result<libc::cpu_set_t> {
+    let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in pin {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(std::io::Error::other(format!("invalid CPU {cpu}")));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+    }
+    Ok(cpu_set)
+}
+
+/// Pins the calling thread, and the processes it then executes, to `cpu_set`.
+/// Async-signal-safe, to be called between fork and exec.
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu_set: &libc::cpu_set_t) -> std::io::Result<()> {
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(cpu_set), cpu_set) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Pinning isn't possible elsewhere than on Linux.
+#[cfg(not(target_os = "linux"))]
+enum CpuSet {}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_set(_pin: &[usize]) -> std::io::Result<CpuSet> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "pinning to CPUs is only available on Linux",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(cpu_set: &CpuSet) -> std::io::Result<()> {
+    match *cpu_set {}
+}
+
+/// Waits for the process `pid` to exit, leaving it to be reaped.
+fn wait_exited(pid: u32) -> std::io::Result<()> {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let ret =
+            unsafe { libc::waitid(libc::P_PID, pid, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        if ret == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_io(pid: u32) -> HashMap<String, u64> {
+    let Ok(io) = std::fs::read_to_string(format!("/proc/{pid}/io")) else {
+        return HashMap::new();
+    };
+    io.lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.to_owned(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_io(_pid: u32) -> HashMap<String, u64> {
+    HashMap::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("4k"), Ok(4 << 10));
+        assert_eq!(parse_size(" 2M "), Ok(2 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("3t"), Ok(3 << 40));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("k").is_err());
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size("1P").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("10us"), Ok(Duration::from_micros(10)));
+        assert_eq!(parse_duration("3m"), Ok(Duration::from_secs(180)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+}
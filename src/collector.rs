@@ -0,0 +1,213 @@
+//! Collectors of custom metrics, measured on each benchmark run alongside the metrics of the
+//! backend, such as the garbage collections or JIT cache hits a runtime reports.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{child::Outcome, metrics::Metrics};
+
+/// Source of additional metrics of benchmark runs, registered with
+/// [`measure_with`](crate::record::measure_with).
+pub trait Collector {
+    /// Names of the metrics it reports, if known in advance, which must not clash with the
+    /// recorded ones. Those only known once read mustn't either, failing the recording.
+    fn metrics(&self) -> Vec<String>;
+    /// Called right before a benchmark process is spawned, with its command, e.g. to point the
+    /// runtime to the file it should report to through its environment.
+    fn start(&mut self, command: &mut Command) -> anyhow::Result<()>;
+    /// Called right after the benchmark process has been reaped, with how it ended.
+    fn stop(&mut self, outcome: &Outcome) -> anyhow::Result<()>;
+    /// The metrics of the last run, among those of [`Collector::metrics`].
+    fn read(&mut self) -> anyhow::Result<Metrics>;
+}
+
+/// Checks that the metrics of `collectors` are distinct from each other and from `recorded`.
+pub fn check_names(collectors: &[Box<dyn Collector>], recorded: &[String]) -> anyhow::Result<()> {
+    let mut names = recorded.to_vec();
+    for collector in collectors {
+        for name in collector.metrics() {
+            if names.contains(&name) {
+                anyhow::bail!("several collectors report the metric {name}");
+            }
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// File of the private directory `dir` the standard output of the benchmark processes is
+/// redirected to, for the collectors reading it.
+pub fn stdout_path(dir: &Path) -> PathBuf {
+    dir.join("stdout")
+}
+
+/// A metric printed by a shell command run after each benchmark run, given as `NAME=COMMAND`.
+#[derive(Debug, Clone)]
+pub struct ExtraMetric {
+    pub name: String,
+    pub command: String,
+}
+
+impl std::str::FromStr for ExtraMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, command) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=COMMAND, got {s:?}"))?;
+        if name.is_empty() || command.is_empty() {
+            return Err(format!("expected NAME=COMMAND, got {s:?}"));
+        }
+        Ok(ExtraMetric {
+            name: name.to_owned(),
+            command: command.to_owned(),
+        })
+    }
+}
+
+/// Runs the commands of extra metrics after each benchmark run, with the PID of the
+/// benchmark process and the path of the file its standard output went to as arguments,
+/// parsing the number each prints.
+pub struct Scripts {
+    metrics: Vec<ExtraMetric>,
+    stdout: PathBuf,
+    values: Metrics,
+}
+
+impl Scripts {
+    /// Runs the commands of `metrics`, the output of the benchmarks going to `stdout`.
+    pub fn new(metrics: Vec<ExtraMetric>, stdout: PathBuf) -> Self {
+        Scripts {
+            metrics,
+            stdout,
+            values: Metrics::new(),
+        }
+    }
+}
+
+impl Collector for Scripts {
+    fn metrics(&self) -> Vec<String> {
+        self.metrics.iter().map(|m| m.name.clone()).collect()
+    }
+
+    fn start(&mut self, command: &mut Command) -> anyhow::Result<()> {
+        command.stdout(std::fs::File::create(&self.stdout)?);
+        Ok(())
+    }
+
+    fn stop(&mut self, outcome: &Outcome) -> anyhow::Result<()> {
+        for metric in &self.metrics {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$@\"", metric.command))
+                .arg("sh")
+                .arg(outcome.pid.to_string())
+                .arg(&self.stdout)
+                .stderr(Stdio::inherit())
+                .output()?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "the command of {} failed ({}): {}",
+                    metric.name,
+                    output.status,
+                    metric.command
+                );
+            }
+            let printed = String::from_utf8_lossy(&output.stdout);
+            let value = printed.trim().parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "the command of {} printed {:?} instead of a number",
+                    metric.name,
+                    printed.trim()
+                )
+            })?;
+            self.values.insert(metric.name.clone(), value);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self) -> anyhow::Result<Metrics> {
+        Ok(std::mem::take(&mut self.values))
+    }
+}
+
+/// Convention of the benchmarked programs to print metrics on their standard output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// NAME=NUMBER words, e.g. compile_ms=12.3
+    KeyValue,
+    /// Lines holding a JSON object, whose numeric fields are metrics
+    Json,
+}
+
+/// Parses the metrics the benchmark processes print on their standard output.
+pub struct OutputParser {
+    format: OutputFormat,
+    stdout: PathBuf,
+}
+
+impl OutputParser {
+    /// Parses the output of the benchmarks, redirected to `stdout`.
+    pub fn new(format: OutputFormat, stdout: PathBuf) -> Self {
+        OutputParser { format, stdout }
+    }
+}
+
+fn is_metric_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl Collector for OutputParser {
+    fn metrics(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn start(&mut self, command: &mut Command) -> anyhow::Result<()> {
+        command.stdout(std::fs::File::create(&self.stdout)?);
+        Ok(())
+    }
+
+    fn stop(&mut self, _outcome: &Outcome) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn read(&mut self) -> anyhow::Result<Metrics> {
+        let output = std::fs::read(&self.stdout)?;
+        let output = String::from_utf8_lossy(&output);
+        let mut metrics = Metrics::new();
+        match self.format {
+            OutputFormat::KeyValue => {
+                for word in output.split_whitespace() {
+                    let Some((name, value)) = word.split_once('=') else {
+                        continue;
+                    };
+                    if let (true, Ok(value)) = (is_metric_name(name), value.parse()) {
+                        metrics.insert(name.to_owned(), value);
+                    }
+                }
+            }
+            OutputFormat::Json => {
+                for line in output
+                    .lines()
+                    .filter(|line| line.trim_start().starts_with('{'))
+                {
+                    let Ok(serde_json::Value::Object(object)) = serde_json::from_str(line) else {
+                        continue;
+                    };
+                    for (name, value) in object {
+                        if let (true, Some(value)) = (is_metric_name(&name), value.as_f64()) {
+                            metrics.insert(name, value);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(metrics)
+    }
+}
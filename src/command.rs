@@ -0,0 +1,230 @@
+//! Turning benchmark command strings into processes.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Splits `command` into words following the POSIX shell quoting rules.
+pub fn split(command: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated single quote in {command:?}"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('$' | '`' | '"' | '\\')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => anyhow::bail!("unterminated double quote in {command:?}"),
+                        },
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated double quote in {command:?}"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => anyhow::bail!("trailing backslash in {command:?}"),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Joins `words` into a command that [`split`] turns back into the same words.
+pub fn join(words: &[String]) -> String {
+    let quote = |word: &String| {
+        let plain = !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+        if plain {
+            word.clone()
+        } else {
+            format!("'{}'", word.replace('\'', r"'\''"))
+        }
+    };
+    words.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+/// The program run by `command`.
+pub fn program(command: &str) -> anyhow::Result<String> {
+    split(command)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty command"))
+}
+
+/// Builds the process running `command` on `input`.
+///
+/// With `shell`, `command` is interpreted by `sh -c`, with `input` as its positional parameter.
+pub fn build(command: &str, input: Option<&Path>, shell: bool) -> anyhow::Result<Command> {
+    let mut process = if shell {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(format!("{command} \"$@\"")).arg("sh");
+        process
+    } else {
+        let words = split(command)?;
+        let Some((program, args)) = words.split_first() else {
+            anyhow::bail!("empty command");
+        };
+        let mut process = Command::new(program);
+        process.args(args);
+        process
+    };
+    process.args(input);
+    Ok(process)
+}
+
+/// Gives `to` the environment changes and working directory of `from`.
+fn copy_environment(from: &Command, to: &mut Command) {
+    for (name, value) in from.get_envs() {
+        match value {
+            Some(value) => to.env(name, value),
+            None => to.env_remove(name),
+        };
+    }
+    if let Some(dir) = from.get_current_dir() {
+        to.current_dir(dir);
+    }
+}
+
+/// Runs `command` as the last arguments of `tool`, after the options `args`.
+pub fn wrap(command: Command, tool: &str, args: &[String]) -> Command {
+    let mut wrapped = Command::new(tool);
+    wrapped
+        .args(args)
+        .arg(command.get_program())
+        .args(command.get_args());
+    copy_environment(&command, &mut wrapped);
+    wrapped
+}
+
+/// Replaces the program of `command` with the path it resolves to, for the tools running it
+/// that don't search the `PATH`.
+pub fn with_resolved_program(command: Command) -> Command {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let Some(resolved) = resolve_program(&program) else {
+        return command;
+    };
+    let mut replaced = Command::new(resolved);
+    replaced.args(command.get_args());
+    copy_environment(&command, &mut replaced);
+    replaced
+}
+
+/// Runs the shell command `build` from `dir`, failing if it does.
+pub fn run_build(build: &str, dir: &Path) -> anyhow::Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(build)
+        .current_dir(dir)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("build command {build:?} failed ({status})");
+    }
+    Ok(())
+}
+
+/// The command line running `command` on `input`, as a shell would accept it.
+pub fn command_line(command: &str, input: Option<&Path>) -> String {
+    match input {
+        Some(input) => format!(
+            "{command} {}",
+            join(&[input.to_string_lossy().into_owned()])
+        ),
+        None => command.to_owned(),
+    }
+}
+
+/// Resolves `program` the way `execvp` would, to find the actual binary being benchmarked.
+pub fn resolve_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn split_quotes_and_backslashes() {
+        assert_eq!(split("a  b\tc").unwrap(), words(&["a", "b", "c"]));
+        assert_eq!(split("'a b' \"c d\"").unwrap(), words(&["a b", "c d"]));
+        assert_eq!(
+            split(r#"'a\b' "a\"b\\c\d""#).unwrap(),
+            words(&[r"a\b", r#"a"b\c\d"#])
+        );
+        assert_eq!(split(r"a\ b \'").unwrap(), words(&["a b", "'"]));
+        assert_eq!(split("a'b'\"c\"d").unwrap(), words(&["abcd"]));
+        assert_eq!(split("a \\\nb").unwrap(), words(&["a", "b"]));
+    }
+
+    #[test]
+    fn split_empty_words() {
+        assert_eq!(split("").unwrap(), words(&[]));
+        assert_eq!(split("  ").unwrap(), words(&[]));
+        assert_eq!(split("'' a \"\"").unwrap(), words(&["", "a", ""]));
+    }
+
+    #[test]
+    fn split_unterminated() {
+        assert!(split("'a").is_err());
+        assert!(split("\"a").is_err());
+        assert!(split("\"a\\").is_err());
+        assert!(split("a\\").is_err());
+    }
+
+    #[test]
+    fn join_round_trips() {
+        for case in [
+            words(&["prog", "--flag=1", "./a.txt"]),
+            words(&[
+                "",
+                "a b",
+                "it's",
+                "\"quoted\"",
+                r"back\slash",
+                "$HOME",
+                "*",
+                "\n",
+            ]),
+            words(&[]),
+        ] {
+            assert_eq!(split(&join(&case)).unwrap(), case);
+        }
+        assert_eq!(join(&words(&["a", "b c"])), "a 'b c'");
+        assert_eq!(join(&words(&["it's"])), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,1168 @@
+//! Relative differences between two results files.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    distribution,
+    group::{self, GroupBy},
+    history,
+    metadata::Metadata,
+    metrics, monitor,
+    results::{self, Measure, Results, Status},
+};
+
+/// Relative difference in percent of `compared` over `base`.
+pub fn rel_diff(base: f64, compared: f64) -> f64 {
+    ((compared - base) * 100.) / base
+}
+
+/// Thresholds of a metric, overriding the general ones.
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MetricThresholds {
+    pub warn_at: Option<f64>,
+    pub fail_at: Option<f64>,
+}
+
+/// Runs of the history whose values the sparklines of comparisons show.
+pub const SPARKLINE_RUNS: usize = 10;
+
+/// Relative differences in percent beyond which changes are highlighted, and beyond which
+/// regressions are failures and improvements are too large to be trusted without checking.
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    pub warn_at: f64,
+    /// Regressions beyond `warn_at` fail if none.
+    pub fail_at: Option<f64>,
+    pub metrics: BTreeMap<String, MetricThresholds>,
+}
+
+/// How a change is highlighted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Band {
+    Unchanged,
+    Improved,
+    /// Improved beyond the fail threshold, more than expected of a change, which deserves
+    /// checking the results are still correct.
+    Suspicious,
+    Warned,
+    Failed,
+}
+
+impl Thresholds {
+    pub fn new(warn_at: f64) -> Self {
+        Self {
+            warn_at,
+            ..Default::default()
+        }
+    }
+
+    /// The thresholds of the metric `name`, recorded in a phase or not.
+    fn of(&self, name: &str) -> (f64, Option<f64>) {
+        let metric = self
+            .metrics
+            .get(name)
+            .or_else(|| self.metrics.get(metrics::split_phase(name).1))
+            .copied()
+            .unwrap_or_default();
+        (
+            metric.warn_at.unwrap_or(self.warn_at),
+            metric.fail_at.or(self.fail_at),
+        )
+    }
+
+    /// Relative difference in percent beyond which the metric `name` regressed.
+    pub fn warn_at(&self, name: &str) -> f64 {
+        self.of(name).0
+    }
+
+    /// The band of a change of `diff` percent of the metric `name`.
+    pub fn band(&self, name: &str, diff: f64) -> Band {
+        let (warn_at, fail_at) = self.of(name);
+        match fail_at {
+            _ if diff.is_nan() => Band::Unchanged,
+            Some(fail_at) if diff > fail_at => Band::Failed,
+            None if diff > warn_at => Band::Failed,
+            _ if diff > warn_at => Band::Warned,
+            Some(fail_at) if diff < -fail_at => Band::Suspicious,
+            _ if diff < -warn_at => Band::Improved,
+            _ => Band::Unchanged,
+        }
+    }
+}
+
+/// How the changes of metrics are shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffFormat {
+    /// Relative differences in percent, e.g. +4.3%
+    #[default]
+    Percent,
+    /// Ratios of the compared values to the base ones, e.g. 1.043
+    Ratio,
+    /// Ratios with a multiplication sign, e.g. 1.043x
+    X,
+}
+
+impl DiffFormat {
+    /// The change from `base` to `compared`.
+    pub fn show(self, base: f64, compared: f64) -> String {
+        match self {
+            DiffFormat::Percent => format!("{:+.1}%", rel_diff(base, compared)),
+            DiffFormat::Ratio => format!("{:.3}", compared / base),
+            DiffFormat::X => format!("{:.3}x", compared / base),
+        }
+    }
+}
+
+/// How the changes of a comparison are shown.
+#[derive(Clone, Copy)]
+pub struct Style<'a> {
+    pub thresholds: &'a Thresholds,
+    pub diff_format: DiffFormat,
+    /// Recent values of the metrics of the benchmarks, shown as sparklines after their changes.
+    pub history: Option<&'a history::Recent>,
+    /// A second recording of the base, whose difference with it is the noise of each metric of
+    /// each benchmark: smaller changes are insignificant.
+    pub noise: Option<&'a Results>,
+}
+
+/// Whether the change of the metric `name` of the benchmark `key` from `base` to `compared`
+/// is within the difference between `base` and the `noise` run.
+fn within_noise(noise: Option<&Results>, key: &str, name: &str, base: f64, compared: f64) -> bool {
+    let Some(&noise) = noise.and_then(|noise| noise.get(key)?.metrics.get(name)) else {
+        return false;
+    };
+    rel_diff(base, compared).abs() <= rel_diff(base, noise).abs()
+}
+
+impl Style<'_> {
+    /// The band of the change of the metric `name` of the benchmark `key`, unchanged if it is
+    /// within the noise.
+    fn band(&self, key: &str, name: &str, base: f64, compared: f64) -> Band {
+        if within_noise(self.noise, key, name, base, compared) {
+            return Band::Unchanged;
+        }
+        self.thresholds.band(name, rel_diff(base, compared))
+    }
+
+    /// Whether the change of the metric `name` of the benchmark `key` is beyond the
+    /// thresholds but within the noise.
+    fn insignificant(&self, key: &str, name: &str, base: f64, compared: f64) -> bool {
+        self.thresholds.band(name, rel_diff(base, compared)) != Band::Unchanged
+            && self.band(key, name, base, compared) == Band::Unchanged
+    }
+
+    /// The change of the metric `name` of the benchmark `key`, followed by the sparkline of its
+    /// recent values and the compared one.
+    fn show(&self, key: &str, name: &str, base: f64, compared: f64) -> String {
+        let diff = self.diff_format.show(base, compared);
+        let Some(values) = self.history.and_then(|history| history.get(key)?.get(name)) else {
+            return diff;
+        };
+        let mut values = values.clone();
+        values.push(compared);
+        format!("{diff} {}", distribution::sparkline(&values))
+    }
+}
+
+pub fn print_comparison(base: &Results, compared: &Results, style: Style, total: bool) {
+    let total = total.then(|| totals(base, compared));
+    print_rows(base, compared, base.keys(), style, total);
+}
+
+/// The largest increase of the metrics of the benchmark `key`, in percent.
+fn worst_change(base: &Results, compared: &Results, key: &str) -> f64 {
+    let (Some(base), Some(compared)) = (base.get(key), compared.get(key)) else {
+        return f64::NEG_INFINITY;
+    };
+    base.metrics
+        .iter()
+        .filter_map(|(name, &value)| Some(rel_diff(value, *compared.metrics.get(name)?)))
+        .filter(|change| !change.is_nan())
+        .fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// The `n` benchmarks whose metrics increased the most and a row summing the others, as the
+/// results of these rows and their keys, worst first.
+fn top_rows(base: &Results, compared: &Results, n: usize) -> (Results, Results, Vec<String>) {
+    let mut keys: Vec<&String> = base.keys().filter(|k| compared.contains_key(*k)).collect();
+    keys.sort_by(|a, b| {
+        worst_change(base, compared, b).total_cmp(&worst_change(base, compared, a))
+    });
+    let rest = keys.split_off(n.min(keys.len()));
+    let mut base_top: Results = keys
+        .iter()
+        .map(|k| ((*k).clone(), base[*k].clone()))
+        .collect();
+    let mut compared_top: Results = keys
+        .iter()
+        .map(|k| ((*k).clone(), compared[*k].clone()))
+        .collect();
+    let mut keys: Vec<String> = keys.into_iter().cloned().collect();
+    if !rest.is_empty() {
+        let others = format!("({} others, summed)", rest.len());
+        base_top.insert(others.clone(), group::sum(base, rest.iter().copied()));
+        compared_top.insert(others.clone(), group::sum(compared, rest.iter().copied()));
+        keys.push(others);
+    }
+    (base_top, compared_top, keys)
+}
+
+/// Like [`print_comparison`], only showing the `n` benchmarks whose metrics increased the
+/// most, worst first, and the others summed into a row.
+pub fn print_top_comparison(
+    base: &Results,
+    compared: &Results,
+    style: Style,
+    n: usize,
+    total: bool,
+) {
+    let total = total.then(|| totals(base, compared));
+    let (base, compared, keys) = top_rows(base, compared, n);
+    print_rows(&base, &compared, &keys, style, total);
+}
+
+/// The benchmarks of `base` also in `compared`, by group.
+fn common_groups<'a>(
+    base: &'a Results,
+    compared: &Results,
+    by: GroupBy,
+) -> BTreeMap<String, Vec<&'a String>> {
+    let mut groups = group::keys(base, by);
+    for keys in groups.values_mut() {
+        keys.retain(|key| compared.contains_key(*key));
+    }
+    groups.retain(|_, keys| !keys.is_empty());
+    groups
+}
+
+/// Like [`print_comparison`], with the benchmarks grouped `by` and the comparison of the
+/// subtotal of each group after its benchmarks.
+pub fn print_grouped_comparison(
+    base: &Results,
+    compared: &Results,
+    style: Style,
+    by: GroupBy,
+    total: bool,
+) {
+    let total = total.then(|| totals(base, compared));
+    let groups = common_groups(base, compared, by);
+    let (base, order) = group::with_subtotals(base, &groups);
+    let (compared, _) = group::with_subtotals(compared, &groups);
+    print_rows(&base, &compared, &order, style, total);
+}
+
+/// The sums of each metric over the benchmarks of both `base` and `compared`.
+fn totals(base: &Results, compared: &Results) -> (Measure, Measure) {
+    let common: Vec<&String> = base.keys().filter(|k| compared.contains_key(*k)).collect();
+    (
+        group::sum(base, common.iter().copied()),
+        group::sum(compared, common.iter().copied()),
+    )
+}
+
+/// Whether some benchmark of `base` or `compared` has a note, shown in a column of its own.
+fn has_notes(base: &Results, compared: &Results) -> bool {
+    base.values()
+        .chain(compared.values())
+        .any(|measure| measure.note.is_some())
+}
+
+/// The note of a benchmark, the compared one's if it has one.
+fn note<'a>(base: &'a Measure, compared: &'a Measure) -> Option<&'a str> {
+    compared.note.as_deref().or(base.note.as_deref())
+}
+
+/// Prints the comparison of the benchmarks `keys` of `base`, in that order, followed by the
+/// `total` of all of them if any.
+fn print_rows<'a>(
+    base: &Results,
+    compared: &Results,
+    keys: impl IntoIterator<Item = &'a String>,
+    style: Style,
+    total: Option<(Measure, Measure)>,
+) {
+    let names = common_metric_names(base, compared);
+    let notes = has_notes(base, compared);
+    let mut table = prettytable::Table::new();
+    let mut header = vec![prettytable::Cell::new("File")];
+    header.extend(names.iter().map(|name| prettytable::Cell::new(name)));
+    if notes {
+        header.push(prettytable::Cell::new("Note"));
+    }
+    table.add_row(prettytable::Row::new(header));
+    let mut suspicious = 0;
+    let mut insignificant = 0;
+    // Improvements of measures whose runs printed a wrong output are not highlighted.
+    let mut diff_cell =
+        |key: &str, name: &str, base: Option<&f64>, compared: Option<&f64>, incorrect: bool| {
+            let (Some(&base), Some(&compared)) = (base, compared) else {
+                return prettytable::Cell::new("-");
+            };
+            if style.insignificant(key, name, base, compared) {
+                insignificant += 1;
+            }
+            let mut cell = prettytable::Cell::new(&style.show(key, name, base, compared));
+            let color = match style.band(key, name, base, compared) {
+                Band::Failed => Some(prettytable::color::RED),
+                Band::Warned => Some(prettytable::color::YELLOW),
+                Band::Improved if !incorrect => Some(prettytable::color::GREEN),
+                Band::Suspicious if !incorrect => {
+                    suspicious += 1;
+                    Some(prettytable::color::MAGENTA)
+                }
+                _ => None,
+            };
+            if let Some(color) = color {
+                cell.style(prettytable::Attr::ForegroundColor(color));
+            }
+            cell
+        };
+    let mut noisy = 0;
+    let mut degraded = 0;
+    let mut incorrect = 0;
+    let total = total
+        .as_ref()
+        .map(|(base, compared)| (group::TOTAL, base, compared));
+    for (key, base_measure, compared_measure) in keys
+        .into_iter()
+        .filter_map(|key| Some((key.as_str(), base.get(key)?, compared.get(key)?)))
+        .chain(total)
+    {
+        let mut file_cell = prettytable::Cell::new(key);
+        if compared_measure.incorrect {
+            incorrect += 1;
+            file_cell = prettytable::Cell::new(&format!("{key} (incorrect)"));
+            file_cell.style(prettytable::Attr::ForegroundColor(prettytable::color::RED));
+        } else if base_measure.is_noisy() || compared_measure.is_noisy() {
+            noisy += 1;
+            file_cell = prettytable::Cell::new(&format!("{key} (busy)"));
+            file_cell.style(prettytable::Attr::ForegroundColor(
+                prettytable::color::YELLOW,
+            ));
+        }
+        if base_measure.is_degraded() || compared_measure.is_degraded() {
+            degraded += 1;
+        }
+        let mut row = vec![file_cell];
+        row.extend(names.iter().map(|name| {
+            diff_cell(
+                key,
+                name,
+                base_measure.metrics.get(name),
+                compared_measure.metrics.get(name),
+                compared_measure.incorrect,
+            )
+        }));
+        if notes {
+            let note = note(base_measure, compared_measure).unwrap_or("");
+            row.push(prettytable::Cell::new(note));
+        }
+        table.add_row(prettytable::Row::new(row));
+    }
+    table.printstd();
+    if suspicious > 0 {
+        tracing::warn!(
+            "{suspicious} improvement(s) in magenta are beyond the fail threshold, more than \
+             expected: check the benchmarks still compute the right results"
+        );
+    }
+    if insignificant > 0 {
+        tracing::info!(
+            "{insignificant} change(s) beyond the threshold are within the difference between \
+             the base and its noise run, and not highlighted"
+        );
+    }
+    if incorrect > 0 {
+        tracing::warn!(
+            "{incorrect} comparison(s) marked (incorrect) are of runs that printed a wrong \
+             output, whose improvements are not credited"
+        );
+    }
+    if noisy > 0 {
+        tracing::warn!(
+            "{noisy} comparison(s) marked (busy) involve a measurement taken while other \
+             processes used more than {:.0}% of the machine",
+            monitor::INTERFERENCE_THRESHOLD * 100.
+        );
+    }
+    if degraded > 0 {
+        tracing::warn!(
+            "{degraded} comparison(s) involve a degraded measurement, missing metrics whose \
+             counters were unavailable"
+        );
+    }
+}
+
+/// Prints, for each benchmark with per-function counts on both sides, the `limit` functions
+/// whose instruction counts changed the most.
+pub fn print_function_deltas(
+    base: &Results,
+    compared: &Results,
+    limit: usize,
+    display: metrics::DisplayOptions,
+) {
+    for (key, base_measure) in base {
+        let Some(compared_measure) = compared.get(key) else {
+            continue;
+        };
+        let (base_fns, compared_fns) = (&base_measure.functions, &compared_measure.functions);
+        if limit == 0 || base_fns.is_empty() || compared_fns.is_empty() {
+            continue;
+        }
+        print_deltas(
+            key,
+            (base_fns, compared_fns),
+            ("cg_instructions", "instructions"),
+            limit,
+            display,
+        );
+    }
+}
+
+/// Prints, for each benchmark of `regressions` with hot functions sampled on both sides, the
+/// `limit` functions whose self cycles, or self CPU clock, changed the most.
+pub fn print_hot_function_deltas(
+    base: &Results,
+    compared: &Results,
+    regressions: &[Regression],
+    limit: usize,
+    display: metrics::DisplayOptions,
+) {
+    let regressed: std::collections::BTreeSet<&String> =
+        regressions.iter().map(|r| &r.key).collect();
+    let mut unsampled = 0;
+    for key in regressed {
+        let (Some(base_measure), Some(compared_measure)) = (base.get(key), compared.get(key))
+        else {
+            continue;
+        };
+        let sampled = |measure: &Measure, metric: &str| -> metrics::Metrics {
+            measure
+                .hot_functions
+                .iter()
+                .filter_map(|(name, metrics)| Some((name.clone(), *metrics.get(metric)?)))
+                .collect()
+        };
+        let sides = ["cycles", "cpu_clock"].into_iter().find_map(|metric| {
+            let (base_fns, compared_fns) = (
+                sampled(base_measure, metric),
+                sampled(compared_measure, metric),
+            );
+            (!base_fns.is_empty() && !compared_fns.is_empty()).then_some((
+                metric,
+                base_fns,
+                compared_fns,
+            ))
+        });
+        let Some((metric, base_fns, compared_fns)) = sides else {
+            unsampled += 1;
+            continue;
+        };
+        if limit > 0 {
+            let label = format!("self {metric}");
+            print_deltas(
+                key,
+                (&base_fns, &compared_fns),
+                (metric, &label),
+                limit,
+                display,
+            );
+        }
+    }
+    if unsampled > 0 {
+        tracing::warn!(
+            "{unsampled} regressed benchmark(s) have no hot functions sampled alike on both \
+             sides to explain them, recorded with --hot-functions"
+        );
+    }
+}
+
+/// Prints the change of the total of the values by function of `base_fns` and
+/// `compared_fns`, shown as `metric` and labeled `label`, and the `limit` functions that
+/// changed the most.
+fn print_deltas(
+    key: &str,
+    (base_fns, compared_fns): (&metrics::Metrics, &metrics::Metrics),
+    (metric, label): (&str, &str),
+    limit: usize,
+    display: metrics::DisplayOptions,
+) {
+    let show = |value: f64| {
+        let shown = metrics::display(metric, value, display);
+        if value > 0. {
+            format!("+{shown}")
+        } else {
+            shown
+        }
+    };
+    let mut deltas: Vec<(&String, f64, f64)> = base_fns
+        .keys()
+        .chain(
+            compared_fns
+                .keys()
+                .filter(|name| !base_fns.contains_key(*name)),
+        )
+        .map(|name| {
+            let base = base_fns.get(name).copied().unwrap_or(0.);
+            let compared = compared_fns.get(name).copied().unwrap_or(0.);
+            (name, base, compared)
+        })
+        .filter(|(_, base, compared)| base != compared)
+        .collect();
+    if deltas.is_empty() {
+        return;
+    }
+    let total: f64 = deltas
+        .iter()
+        .map(|(_, base, compared)| compared - base)
+        .sum();
+    deltas.sort_by(|a, b| (b.2 - b.1).abs().total_cmp(&(a.2 - a.1).abs()));
+    println!("{key}: {} {label}", show(total));
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row![
+        "Function", "Base", "Compared", "Delta", "Share"
+    ]);
+    for (name, base, compared) in deltas.into_iter().take(limit) {
+        let share = if total == 0. {
+            "-".to_owned()
+        } else {
+            format!("{:.1}%", (compared - base) / total * 100.)
+        };
+        table.add_row(prettytable::row![
+            name,
+            metrics::display(metric, base, display),
+            metrics::display(metric, compared, display),
+            show(compared - base),
+            share,
+        ]);
+    }
+    table.printstd();
+}
+
+pub fn warn_metadata_differences(base: &Metadata, compared: &Metadata) {
+    let differences = base.differences(compared);
+    if !differences.is_empty() {
+        tracing::warn!(
+            "the results were recorded in different environments, which may explain some \
+             differences: {}",
+            differences.join(", ")
+        );
+    }
+}
+
+/// A benchmark whose measurement went differently in the base and compared results.
+pub struct StatusChange<'a> {
+    pub key: &'a str,
+    pub base: &'a Status,
+    pub compared: &'a Status,
+}
+
+impl StatusChange<'_> {
+    /// Whether a benchmark that completed no longer does.
+    pub fn is_regression(&self) -> bool {
+        self.compared.is_failure() && !self.base.is_failure()
+    }
+
+    /// What changed, e.g. "now crashes (killed by signal 11)".
+    pub fn describe(&self) -> String {
+        let (base, compared) = (self.base, self.compared);
+        match compared {
+            Status::Ok => format!("fixed, {base} before"),
+            Status::Signal { .. } if !base.is_failure() => format!("now crashes ({compared})"),
+            Status::Timeout if !base.is_failure() => "now times out".to_owned(),
+            Status::NonzeroExit { .. } if !base.is_failure() => {
+                format!("now fails ({compared})")
+            }
+            _ => format!("{compared}, {base} before"),
+        }
+    }
+}
+
+/// The benchmarks of both `base` and `compared` whose status changed, the skipped ones left
+/// out.
+pub fn status_changes<'a>(base: &'a Results, compared: &'a Results) -> Vec<StatusChange<'a>> {
+    base.iter()
+        .filter_map(|(key, base)| {
+            let compared = compared.get(key)?;
+            let skipped = base.status == Status::Skipped || compared.status == Status::Skipped;
+            (base.status != compared.status && !skipped).then_some(StatusChange {
+                key,
+                base: &base.status,
+                compared: &compared.status,
+            })
+        })
+        .collect()
+}
+
+/// Prints the status changes, the new failures in red and the fixes in green.
+fn print_status_changes(changes: &[StatusChange]) {
+    if changes.is_empty() {
+        return;
+    }
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row!["File", "Status change"]);
+    for change in changes {
+        let mut cell = prettytable::Cell::new(&change.describe());
+        let color = if change.is_regression() {
+            prettytable::color::RED
+        } else if change.compared.is_ok() {
+            prettytable::color::GREEN
+        } else {
+            prettytable::color::YELLOW
+        };
+        cell.style(prettytable::Attr::ForegroundColor(color));
+        cell.style(prettytable::Attr::Bold);
+        table.add_row(prettytable::Row::new(vec![
+            prettytable::Cell::new(change.key),
+            cell,
+        ]));
+    }
+    table.printstd();
+}
+
+/// The status changes as a Markdown list, the new failures in bold.
+fn status_changes_markdown(changes: &[StatusChange]) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+    let mut markdown = "Status changes:\n\n".to_owned();
+    for change in changes {
+        let description = change.describe();
+        if change.is_regression() {
+            markdown.push_str(&format!("- `{}`: **{description}**\n", change.key));
+        } else {
+            markdown.push_str(&format!("- `{}`: {description}\n", change.key));
+        }
+    }
+    markdown.push('\n');
+    markdown
+}
+
+/// Lists the benchmarks the `side` results `skipped`, by reason, which are left out of
+/// comparisons.
+pub fn warn_skipped(side: &str, skipped: &Results) {
+    let mut by_reason: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (key, measure) in skipped {
+        let reason = measure.skip_reason.as_deref().unwrap_or("no reason given");
+        by_reason.entry(reason).or_default().push(key);
+    }
+    for (reason, keys) in by_reason {
+        tracing::warn!(
+            "{} benchmark(s) skipped in the {side} results, so not compared ({reason}): {}",
+            keys.len(),
+            keys.join(", ")
+        );
+    }
+}
+
+/// The metrics recorded in both `base` and `compared`, in their usual order.
+fn common_metric_names(base: &Results, compared: &Results) -> Vec<String> {
+    let compared = results::metric_names([compared]);
+    results::metric_names([base])
+        .into_iter()
+        .filter(|name| compared.contains(name))
+        .collect()
+}
+
+/// Warns about the metrics recorded on a single side, which are left out of comparisons.
+pub fn warn_metric_differences(base: &Results, compared: &Results) {
+    let (base, compared) = (
+        results::metric_names([base]),
+        results::metric_names([compared]),
+    );
+    for (names, others, side) in [(&base, &compared, "base"), (&compared, &base, "compared")] {
+        let only: Vec<&str> = names
+            .iter()
+            .filter(|name| !others.contains(name))
+            .map(String::as_str)
+            .collect();
+        if !only.is_empty() {
+            tracing::warn!(
+                "{} only recorded in the {side} results, so not compared",
+                only.join(", ")
+            );
+        }
+    }
+}
+
+/// Warns about the benchmarks of sharded recordings measured on different machines.
+pub fn warn_host_differences(base: &Results, compared: &Results) {
+    let moved: Vec<&str> = base
+        .iter()
+        .filter(|(key, measure)| {
+            compared.get(*key).is_some_and(|other| {
+                measure.host.is_some() && other.host.is_some() && measure.host != other.host
+            })
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+    if !moved.is_empty() {
+        tracing::warn!(
+            "{} benchmark(s) were measured on different hosts, which may explain their \
+             differences: {}",
+            moved.len(),
+            moved.join(", ")
+        );
+    }
+}
+
+/// Warns of the benchmarks whose CPUs ran at materially different mean frequencies, which
+/// changes their times but not their counts of events.
+pub fn warn_frequency_differences(base: &Results, compared: &Results) {
+    let differing: Vec<String> = base
+        .iter()
+        .filter_map(|(key, measure)| {
+            let base = measure.frequency?.mean;
+            let compared = compared.get(key)?.frequency?.mean;
+            (base > 0. && ((compared - base) / base).abs() > monitor::FREQUENCY_TOLERANCE)
+                .then(|| format!("{key} ({base:.0} vs {compared:.0} MHz)"))
+        })
+        .collect();
+    if !differing.is_empty() {
+        tracing::warn!(
+            "{} benchmark(s) ran at materially different CPU frequencies, which may explain \
+             their differences in time: {}",
+            differing.len(),
+            differing.join(", ")
+        );
+    }
+}
+
+/// The relative differences in percent, by key then metric, with the base and compared
+/// `status` of the benchmarks whose status changed.
+pub fn comparison_json(base: &Results, compared: &Results) -> serde_json::Value {
+    let changes: BTreeMap<&str, StatusChange> = status_changes(base, compared)
+        .into_iter()
+        .map(|change| (change.key, change))
+        .collect();
+    let mut diffs = serde_json::Map::new();
+    for (key, base_measure) in base {
+        let Some(compared_measure) = compared.get(key) else {
+            continue;
+        };
+        let mut metrics: serde_json::Map<_, _> = base_measure
+            .metrics
+            .iter()
+            .filter_map(|(name, &base)| {
+                let compared = *compared_measure.metrics.get(name)?;
+                Some((name.clone(), rel_diff(base, compared).into()))
+            })
+            .collect();
+        if let Some(change) = changes.get(key.as_str()) {
+            metrics.insert(
+                "status".to_owned(),
+                serde_json::json!({ "base": change.base, "compared": change.compared }),
+            );
+        }
+        diffs.insert(key.clone(), metrics.into());
+    }
+    diffs.into()
+}
+
+/// Marks the reports posted by the orchestrator, so that later runs update them.
+pub const REPORT_MARKER: &str = "<!-- perf-bench-orchestrator -->";
+
+/// The comparison as a Markdown table, with the changes beyond the `thresholds` in bold.
+pub fn comparison_markdown(
+    base: &Results,
+    compared: &Results,
+    style: Style,
+    total: bool,
+) -> String {
+    let total = total.then(|| totals(base, compared));
+    let mut marked = Marked::default();
+    let mut markdown = markdown_table(base, compared, base.keys(), style, total, &mut marked);
+    markdown.push_str(&marked.notes());
+    markdown
+}
+
+/// Like [`comparison_markdown`], only showing the `n` benchmarks whose metrics increased the
+/// most, worst first, and the others summed into a row.
+pub fn top_comparison_markdown(
+    base: &Results,
+    compared: &Results,
+    style: Style,
+    n: usize,
+    total: bool,
+) -> String {
+    let total = total.then(|| totals(base, compared));
+    let (base, compared, keys) = top_rows(base, compared, n);
+    let mut marked = Marked::default();
+    let mut markdown = markdown_table(&base, &compared, &keys, style, total, &mut marked);
+    markdown.push_str(&marked.notes());
+    markdown
+}
+
+/// Like [`comparison_markdown`], with a table of the subtotal of each group of benchmarks
+/// followed by the comparisons of the benchmarks of each group in a collapsed section.
+pub fn grouped_comparison_markdown(
+    base: &Results,
+    compared: &Results,
+    style: Style,
+    by: GroupBy,
+    total: bool,
+) -> String {
+    let total = total.then(|| totals(base, compared));
+    let groups = common_groups(base, compared, by);
+    let (base, _) = group::with_subtotals(base, &groups);
+    let (compared, _) = group::with_subtotals(compared, &groups);
+    let subtotals: Vec<String> = groups
+        .keys()
+        .map(|group| group::subtotal_key(group))
+        .collect();
+    let mut marked = Marked::default();
+    let mut markdown = markdown_table(&base, &compared, &subtotals, style, total, &mut marked);
+    marked = Marked::default();
+    for (group, keys) in &groups {
+        markdown.push_str(&format!(
+            "\n<details><summary>{group} ({} benchmark(s))</summary>\n\n",
+            keys.len()
+        ));
+        markdown.push_str(&markdown_table(
+            &base,
+            &compared,
+            keys.iter().copied(),
+            style,
+            None,
+            &mut marked,
+        ));
+        markdown.push_str("\n</details>\n");
+    }
+    markdown.push_str(&marked.notes());
+    markdown
+}
+
+/// Numbers of comparisons marked in Markdown tables.
+#[derive(Default)]
+struct Marked {
+    noisy: usize,
+    incorrect: usize,
+    suspicious: usize,
+    insignificant: usize,
+}
+
+impl Marked {
+    /// The notes explaining the marks.
+    fn notes(&self) -> String {
+        let mut notes = String::new();
+        if self.suspicious > 0 {
+            notes.push_str(&format!(
+                "\n{} improvement(s) marked :warning: are beyond the fail threshold, more than \
+                 expected: check the benchmarks still compute the right results.\n",
+                self.suspicious
+            ));
+        }
+        if self.insignificant > 0 {
+            notes.push_str(&format!(
+                "\n{} change(s) beyond the threshold are within the difference between the base \
+                 and its noise run, and not in bold.\n",
+                self.insignificant
+            ));
+        }
+        if self.incorrect > 0 {
+            notes.push_str(&format!(
+                "\n{} comparison(s) marked (incorrect) are of runs that printed a wrong \
+                 output, whose improvements are not credited.\n",
+                self.incorrect
+            ));
+        }
+        if self.noisy > 0 {
+            notes.push_str(&format!(
+                "\n{} comparison(s) marked (busy) involve a measurement taken while other \
+                 processes used more than {:.0}% of the machine.\n",
+                self.noisy,
+                monitor::INTERFERENCE_THRESHOLD * 100.
+            ));
+        }
+        notes
+    }
+}
+
+/// A Markdown table of the comparison of the benchmarks `keys` of `base`, in that order,
+/// followed by the `total` of all of them if any.
+fn markdown_table<'a>(
+    base: &Results,
+    compared: &Results,
+    keys: impl IntoIterator<Item = &'a String>,
+    style: Style,
+    total: Option<(Measure, Measure)>,
+    marked: &mut Marked,
+) -> String {
+    let names = common_metric_names(base, compared);
+    let notes = has_notes(base, compared);
+    let mut markdown = format!("| File | {} |", names.join(" | "));
+    markdown.push_str(if notes { " Note |\n" } else { "\n" });
+    markdown.push_str(&format!("|---|{}", "---:|".repeat(names.len())));
+    markdown.push_str(if notes { "---|\n" } else { "\n" });
+    let total = total
+        .as_ref()
+        .map(|(base, compared)| (group::TOTAL, base, compared));
+    for (key, base_measure, compared_measure) in keys
+        .into_iter()
+        .filter_map(|key| Some((key.as_str(), base.get(key)?, compared.get(key)?)))
+        .chain(total)
+    {
+        let mut row = format!("| `{key}`");
+        if compared_measure.incorrect {
+            marked.incorrect += 1;
+            row.push_str(" (incorrect)");
+        }
+        if base_measure.is_noisy() || compared_measure.is_noisy() {
+            marked.noisy += 1;
+            row.push_str(" (busy)");
+        }
+        for name in &names {
+            let cell = match (
+                base_measure.metrics.get(name),
+                compared_measure.metrics.get(name),
+            ) {
+                (Some(&base), Some(&compared)) => {
+                    let diff = style.show(key, name, base, compared);
+                    if style.insignificant(key, name, base, compared) {
+                        marked.insignificant += 1;
+                    }
+                    match style.band(key, name, base, compared) {
+                        Band::Failed | Band::Warned => format!("**{diff}**"),
+                        _ if compared_measure.incorrect => diff,
+                        Band::Improved => format!("**{diff}**"),
+                        Band::Suspicious => {
+                            marked.suspicious += 1;
+                            format!("**{diff}** :warning:")
+                        }
+                        Band::Unchanged => diff,
+                    }
+                }
+                _ => "-".to_owned(),
+            };
+            row.push_str(&format!(" | {cell}"));
+        }
+        if notes {
+            let note = note(base_measure, compared_measure).unwrap_or("");
+            row.push_str(&format!(" | {}", note.replace('|', "\\|")));
+        }
+        markdown.push_str(&row);
+        markdown.push_str(" |\n");
+    }
+    markdown
+}
+
+/// A metric that increased beyond the threshold.
+pub struct Regression {
+    pub key: String,
+    pub metric: String,
+    /// Relative difference in percent.
+    pub change: f64,
+}
+
+/// The metrics of `compared` that increased by more than their warning threshold over `base`,
+/// and by more than the difference with the `noise` run of `base` if any.
+pub fn regressions(
+    base: &Results,
+    compared: &Results,
+    thresholds: &Thresholds,
+    noise: Option<&Results>,
+) -> Vec<Regression> {
+    let mut regressions = Vec::new();
+    for (key, base_measure) in base {
+        let Some(compared_measure) = compared.get(key) else {
+            continue;
+        };
+        for (metric, &base) in &base_measure.metrics {
+            let Some(&compared) = compared_measure.metrics.get(metric) else {
+                continue;
+            };
+            let change = rel_diff(base, compared);
+            if change > thresholds.warn_at(metric)
+                && !within_noise(noise, key, metric, base, compared)
+            {
+                regressions.push(Regression {
+                    key: key.clone(),
+                    metric: metric.clone(),
+                    change,
+                });
+            }
+        }
+    }
+    regressions
+}
+
+/// Comparison of results with base results, where changes beyond the `thresholds` are
+/// significant.
+pub struct Comparator<'a> {
+    pub base: &'a Results,
+    pub compared: &'a Results,
+    pub thresholds: Thresholds,
+    pub rows: Rows,
+    pub diff_format: DiffFormat,
+    pub history: Option<&'a history::Recent>,
+    pub noise: Option<&'a Results>,
+}
+
+/// Which rows the tables of a comparison show.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rows {
+    /// Only the benchmarks that regressed the most, and the sum of the others.
+    pub top: Option<usize>,
+    /// The benchmarks by group, with their subtotals.
+    pub group_by: Option<GroupBy>,
+    /// A last row with the total of all the benchmarks.
+    pub total: bool,
+}
+
+impl<'a> Comparator<'a> {
+    pub fn new(base: &'a Results, compared: &'a Results, threshold: f64) -> Self {
+        Self {
+            base,
+            compared,
+            thresholds: Thresholds::new(threshold),
+            rows: Rows::default(),
+            diff_format: DiffFormat::default(),
+            history: None,
+            noise: None,
+        }
+    }
+
+    pub fn with_thresholds(self, thresholds: Thresholds) -> Self {
+        Self { thresholds, ..self }
+    }
+
+    pub fn with_rows(self, rows: Rows) -> Self {
+        Self { rows, ..self }
+    }
+
+    pub fn with_diff_format(self, diff_format: DiffFormat) -> Self {
+        Self {
+            diff_format,
+            ..self
+        }
+    }
+
+    pub fn with_style(self, style: Style<'a>) -> Self {
+        Self {
+            thresholds: style.thresholds.clone(),
+            diff_format: style.diff_format,
+            history: style.history,
+            noise: style.noise,
+            ..self
+        }
+    }
+
+    /// Prints the comparison as a table, highlighting the significant changes.
+    pub fn print(&self) {
+        let style = Style {
+            thresholds: &self.thresholds,
+            diff_format: self.diff_format,
+            history: self.history,
+            noise: self.noise,
+        };
+        print_status_changes(&status_changes(self.base, self.compared));
+        match self.rows {
+            Rows {
+                top: Some(n),
+                total,
+                ..
+            } => print_top_comparison(self.base, self.compared, style, n, total),
+            Rows {
+                group_by: Some(by),
+                total,
+                ..
+            } => print_grouped_comparison(self.base, self.compared, style, by, total),
+            Rows { total, .. } => print_comparison(self.base, self.compared, style, total),
+        }
+    }
+
+    /// The relative differences in percent, by key then metric, and the status changes.
+    pub fn json(&self) -> serde_json::Value {
+        comparison_json(self.base, self.compared)
+    }
+
+    /// The comparison as a Markdown table, with the significant changes in bold.
+    pub fn markdown(&self) -> String {
+        let style = Style {
+            thresholds: &self.thresholds,
+            diff_format: self.diff_format,
+            history: self.history,
+            noise: self.noise,
+        };
+        let changes = status_changes_markdown(&status_changes(self.base, self.compared));
+        changes
+            + &match self.rows {
+                Rows {
+                    top: Some(n),
+                    total,
+                    ..
+                } => top_comparison_markdown(self.base, self.compared, style, n, total),
+                Rows {
+                    group_by: Some(by),
+                    total,
+                    ..
+                } => grouped_comparison_markdown(self.base, self.compared, style, by, total),
+                Rows { total, .. } => comparison_markdown(self.base, self.compared, style, total),
+            }
+    }
+
+    /// The metrics that increased significantly.
+    pub fn regressions(&self) -> Vec<Regression> {
+        regressions(self.base, self.compared, &self.thresholds, self.noise)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands_without_fail_threshold() {
+        let thresholds = Thresholds::new(5.);
+        assert_eq!(thresholds.band("cycles", 0.), Band::Unchanged);
+        assert_eq!(thresholds.band("cycles", 5.), Band::Unchanged);
+        assert_eq!(thresholds.band("cycles", 5.1), Band::Failed);
+        assert_eq!(thresholds.band("cycles", -5.), Band::Unchanged);
+        assert_eq!(thresholds.band("cycles", -50.), Band::Improved);
+        assert_eq!(thresholds.band("cycles", f64::NAN), Band::Unchanged);
+    }
+
+    #[test]
+    fn bands_with_fail_threshold() {
+        let thresholds = Thresholds {
+            fail_at: Some(10.),
+            ..Thresholds::new(5.)
+        };
+        assert_eq!(thresholds.band("cycles", 3.), Band::Unchanged);
+        assert_eq!(thresholds.band("cycles", 7.), Band::Warned);
+        assert_eq!(thresholds.band("cycles", 10.), Band::Warned);
+        assert_eq!(thresholds.band("cycles", 12.), Band::Failed);
+        assert_eq!(thresholds.band("cycles", -7.), Band::Improved);
+        assert_eq!(thresholds.band("cycles", -12.), Band::Suspicious);
+    }
+
+    #[test]
+    fn bands_of_metric_thresholds() {
+        let thresholds = Thresholds {
+            fail_at: Some(10.),
+            metrics: BTreeMap::from([(
+                "cycles".to_owned(),
+                MetricThresholds {
+                    warn_at: Some(1.),
+                    fail_at: None,
+                },
+            )]),
+            ..Thresholds::new(5.)
+        };
+        assert_eq!(thresholds.band("cycles", 2.), Band::Warned);
+        assert_eq!(thresholds.band("cycles", 11.), Band::Failed);
+        assert_eq!(thresholds.band("run.cycles", 2.), Band::Warned);
+        assert_eq!(thresholds.band("instructions", 2.), Band::Unchanged);
+        assert_eq!(thresholds.warn_at("cycles"), 1.);
+        assert_eq!(thresholds.warn_at("instructions"), 5.);
+    }
+
+    #[test]
+    fn relative_differences() {
+        assert_eq!(rel_diff(100., 110.), 10.);
+        assert_eq!(rel_diff(200., 100.), -50.);
+        assert!(rel_diff(0., 0.).is_nan());
+    }
+}
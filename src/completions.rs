@@ -0,0 +1,31 @@
+//! Shell completion scripts, generated from the clap definition of the command line.
+
+use clap::{builder::PossibleValuesParser, CommandFactory};
+use clap_complete::Shell;
+use perf_bench_orchestrator::metrics;
+
+/// `command` with the names of the metrics as the values to complete of its `--metrics`
+/// options, and of those of its subcommands.
+fn with_metric_names(mut command: clap::Command) -> clap::Command {
+    if command.get_arguments().any(|arg| arg.get_id() == "metrics") {
+        command = command.mut_arg("metrics", |arg| {
+            arg.value_parser(PossibleValuesParser::new(
+                metrics::METRICS.iter().map(|metric| metric.name),
+            ))
+        });
+    }
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_owned())
+        .collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, with_metric_names);
+    }
+    command
+}
+
+pub fn print(shell: Shell) {
+    let mut command = with_metric_names(crate::CliOptions::command());
+    let bin = command.get_name().to_owned();
+    clap_complete::generate(shell, &mut command, bin, &mut std::io::stdout());
+}
@@ -0,0 +1,237 @@
+//! Layered configuration: a user configuration file, overridden by a project `perfbench.toml`,
+//! itself overridden by command-line flags.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend, child, collector,
+    compare::{DiffFormat, MetricThresholds, Thresholds},
+    cores::CoreType,
+    group::GroupBy,
+    metrics::{DisplayOptions, TimeUnit},
+    Format,
+};
+
+pub const PROJECT_FILE: &str = "perfbench.toml";
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Command run on the input files when the command line doesn't give one.
+    pub command: Option<String>,
+    pub metrics: Option<Vec<String>>,
+    pub backend: Option<backend::Kind>,
+    pub qemu: Option<String>,
+    pub qemu_plugin: Option<PathBuf>,
+    pub iterations: Option<u32>,
+    pub warmup: Option<u32>,
+    pub pin: Option<Vec<usize>>,
+    pub core_type: Option<CoreType>,
+    pub numa_node: Option<usize>,
+    pub exclusive_core: Option<bool>,
+    #[serde(alias = "warn-at")]
+    pub threshold: Option<f64>,
+    pub fail_at: Option<f64>,
+    /// Thresholds of some metrics, overriding the general ones.
+    #[serde(default)]
+    pub thresholds: BTreeMap<String, MetricThresholds>,
+    pub diff_format: Option<DiffFormat>,
+    pub sparklines: Option<bool>,
+    /// Frequency in MHz the reference cycles of compared results are normalized to.
+    pub reference_frequency: Option<f64>,
+    pub format: Option<Format>,
+    pub group_by: Option<GroupBy>,
+    pub total: Option<bool>,
+    pub raw_numbers: Option<bool>,
+    pub time_unit: Option<TimeUnit>,
+    pub cold: Option<bool>,
+    pub compile_cmd: Option<String>,
+    pub precompile: Option<bool>,
+    pub shell: Option<bool>,
+    pub cache: Option<bool>,
+    pub cache_dir: Option<PathBuf>,
+    pub dedup: Option<bool>,
+    pub max_total_time: Option<String>,
+    pub skip_file: Option<PathBuf>,
+    pub quarantine: Option<bool>,
+    /// Directory of the history of runs.
+    pub history_dir: Option<PathBuf>,
+    pub key_template: Option<String>,
+    pub strip_prefix: Option<PathBuf>,
+    pub memory_limit: Option<Size>,
+    pub rlimit: Option<Vec<String>>,
+    pub timeout: Option<String>,
+    pub extra_metric: Option<Vec<String>>,
+    pub breakpoint: Option<Vec<String>>,
+    pub count_calls: Option<Vec<String>>,
+    pub parse_output: Option<collector::OutputFormat>,
+    pub per_thread: Option<bool>,
+    pub per_process: Option<bool>,
+    pub hot_functions: Option<bool>,
+    pub roi: Option<bool>,
+    pub skip_start: Option<String>,
+    pub container: Option<String>,
+    pub container_runtime: Option<String>,
+    /// Profile used when the command line doesn't select one.
+    pub profile: Option<String>,
+    /// Profiles defined by the user, replacing the built-in ones of the same name.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Named preset of run options, overridden by the flags given on the command line.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub iterations: Option<u32>,
+    pub warmup: Option<u32>,
+    pub pin: Option<Vec<usize>>,
+    pub metrics: Option<Vec<String>>,
+}
+
+fn builtin_profile(name: &str) -> Option<Profile> {
+    let metrics = |names: &[&str]| Some(names.iter().map(|name| name.to_string()).collect());
+    match name {
+        "quick" => Some(Profile {
+            iterations: Some(1),
+            warmup: Some(0),
+            pin: None,
+            metrics: metrics(&["instructions", "cpu_time"]),
+        }),
+        "thorough" => Some(Profile {
+            iterations: Some(10),
+            warmup: Some(2),
+            pin: None,
+            metrics: None,
+        }),
+        // Shared CI runners are noisy, so favour the metrics that are least sensitive to it.
+        "ci" => Some(Profile {
+            iterations: Some(5),
+            warmup: Some(1),
+            pin: None,
+            metrics: metrics(&["instructions"]),
+        }),
+        _ => None,
+    }
+}
+
+impl Config {
+    pub fn display_options(&self) -> DisplayOptions {
+        DisplayOptions {
+            raw: self.raw_numbers.unwrap_or(false),
+            time_unit: self.time_unit,
+            ratios: false,
+        }
+    }
+
+    /// The thresholds of comparisons, `threshold` and `fail_at` overriding the configured
+    /// ones.
+    pub fn thresholds(&self, threshold: Option<f64>, fail_at: Option<f64>) -> Thresholds {
+        Thresholds {
+            warn_at: threshold.or(self.threshold).unwrap_or(0.1),
+            fail_at: fail_at.or(self.fail_at),
+            metrics: self.thresholds.clone(),
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> anyhow::Result<Profile> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .or_else(|| builtin_profile(name))
+            .ok_or_else(|| {
+                let mut known: Vec<&str> = vec!["quick", "thorough", "ci"];
+                known.extend(self.profiles.keys().map(String::as_str));
+                known.sort();
+                known.dedup();
+                anyhow::anyhow!(
+                    "unknown profile {name:?}, expected one of {}",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// A byte count, either as an integer or a string with a K, M, G or T suffix.
+#[derive(Debug, Clone, Copy)]
+pub struct Size(pub u64);
+
+impl<'de> serde::Deserialize<'de> for Size {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bytes(u64),
+            Suffixed(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Bytes(n) => Ok(Size(n)),
+            Repr::Suffixed(s) => child::parse_size(&s)
+                .map(Size)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+    Some(base.join("perf-bench-orchestrator").join("config.toml"))
+}
+
+/// Finds the project configuration in the current directory or its closest ancestor.
+fn project_config_path() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+fn read(path: &Path) -> anyhow::Result<serde_json::Value> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+    toml::from_str(&contents).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+}
+
+/// Merges `overlay` into `base`, recursively for tables.
+fn merge(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Loads the user configuration overridden by the project one, which is `project` if given or
+/// else looked up from the current directory.
+pub fn load(project: Option<&Path>) -> anyhow::Result<Config> {
+    let mut merged = serde_json::Value::Object(Default::default());
+    let mut sources = Vec::new();
+    if let Some(user) = user_config_path().filter(|path| path.is_file()) {
+        sources.push(user);
+    }
+    match project {
+        Some(project) => sources.push(project.to_owned()),
+        None => sources.extend(project_config_path()),
+    }
+    for source in &sources {
+        tracing::debug!("loading configuration from {}", source.display());
+        merge(&mut merged, read(source)?);
+    }
+    serde_json::from_value(merged).map_err(|e| {
+        let sources: Vec<_> = sources.iter().map(|s| s.display().to_string()).collect();
+        anyhow::anyhow!("invalid configuration ({}): {e}", sources.join(", "))
+    })
+}
@@ -0,0 +1,298 @@
+//! Running benchmarks in containers, counting the events of their cgroups rather than of the
+//! container runtime's client that is spawned for them.
+
+use std::{
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    backend::Backend,
+    child::Outcome,
+    command,
+    metrics::{Counters, Metric, Reading},
+    temp::TempDir,
+};
+
+/// Where the directory of the gate is mounted in the containers.
+const GATE_MOUNT: &str = "/perf-bench-gate";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+pub struct Container {
+    runtime: String,
+    image: String,
+    /// Metrics counted on the cgroup of the container.
+    counted: Vec<String>,
+    wall_time: bool,
+    pin: Vec<usize>,
+    numa_node: Option<usize>,
+    /// CPUs the counters are opened on.
+    cpus: Vec<usize>,
+    /// Directory of the file the runtime writes the container ID to, and of the FIFO the
+    /// container reads a line from before running the benchmark, once its counters are open.
+    dir: TempDir,
+    run: Option<Run>,
+    unavailable: Vec<&'static str>,
+}
+
+/// Opening of the counters of a container being started.
+struct Run {
+    exited: Arc<AtomicBool>,
+    thread: JoinHandle<anyhow::Result<(Counters, Instant)>>,
+}
+
+impl Container {
+    pub fn new(
+        image: &str,
+        runtime: Option<&str>,
+        metrics: Vec<&'static Metric>,
+        (pin, numa_node): (&[usize], Option<usize>),
+    ) -> anyhow::Result<Self> {
+        if let Some(metric) = metrics.iter().find(|m| !m.in_container()) {
+            anyhow::bail!("{} cannot be measured with --container", metric.name);
+        }
+        let runtime = match runtime {
+            Some(runtime) => runtime.to_owned(),
+            None if command::resolve_program("podman").is_some() => "podman".into(),
+            None => "docker".into(),
+        };
+        let cpus = if pin.is_empty() {
+            let online = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+            (0..online.max(1) as usize).collect()
+        } else {
+            pin.to_vec()
+        };
+        let dir = TempDir::new("perf-bench-container")?;
+        let gate = dir.path().join("gate");
+        let gate = std::ffi::CString::new(gate.into_os_string().into_encoded_bytes())?;
+        if unsafe { libc::mkfifo(gate.as_ptr(), 0o666) } != 0 {
+            anyhow::bail!(
+                "cannot create a FIFO in {}: {}",
+                dir.path().display(),
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(Self {
+            runtime,
+            image: image.to_owned(),
+            counted: metrics
+                .iter()
+                .filter(|m| !m.is_outcome())
+                .map(|m| m.name.to_owned())
+                .collect(),
+            wall_time: metrics.iter().any(|m| m.is_outcome()),
+            pin: pin.to_vec(),
+            numa_node,
+            cpus,
+            dir,
+            run: None,
+            unavailable: Vec::new(),
+        })
+    }
+
+    fn cidfile(&self) -> PathBuf {
+        self.dir.path().join("cid")
+    }
+}
+
+/// The directory of the cgroup of the process `pid` that perf events can be counted on.
+fn cgroup_dir(pid: u32) -> anyhow::Result<PathBuf> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup"))?;
+    let mut unified = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let path = path.trim_start_matches('/');
+        if controllers.split(',').any(|c| c == "perf_event") {
+            return Ok(Path::new("/sys/fs/cgroup/perf_event").join(path));
+        }
+        if id == "0" {
+            unified = Some(path.to_owned());
+        }
+    }
+    let path = unified.ok_or_else(|| anyhow::anyhow!("cannot find the cgroup of process {pid}"))?;
+    // Hybrid hierarchies mount the unified one apart from the controllers.
+    let root = Path::new("/sys/fs/cgroup/unified");
+    let root = if root.is_dir() {
+        root
+    } else {
+        Path::new("/sys/fs/cgroup")
+    };
+    Ok(root.join(path))
+}
+
+/// Waits for the container recorded in `cidfile` to start, opens the counters of its cgroup,
+/// and lets it run the benchmark through `gate`.
+fn open_counters(
+    runtime: &str,
+    cidfile: &Path,
+    gate: &Path,
+    names: &[String],
+    cpus: &[usize],
+    exited: &AtomicBool,
+) -> anyhow::Result<(Counters, Instant)> {
+    let check_running = || {
+        if exited.load(Ordering::Relaxed) {
+            anyhow::bail!("the container exited before running the benchmark");
+        }
+        std::thread::sleep(POLL_INTERVAL);
+        Ok(())
+    };
+    let id = loop {
+        match std::fs::read_to_string(cidfile) {
+            Ok(id) if !id.trim().is_empty() => break id.trim().to_owned(),
+            _ => check_running()?,
+        }
+    };
+    let pid = loop {
+        let output = Command::new(runtime)
+            .args(["inspect", "--format", "{{.State.Pid}}", &id])
+            .output()?;
+        match String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u32>()
+        {
+            Ok(pid) if output.status.success() && pid != 0 => break pid,
+            _ => check_running()?,
+        }
+    };
+    let cgroup = cgroup_dir(pid)?;
+    let cgroup = std::fs::File::open(&cgroup)
+        .map_err(|e| anyhow::anyhow!("cannot open {}: {e}", cgroup.display()))?;
+    let mut counters = Counters::open_cgroup(names, &cgroup, cpus)?;
+    // Opening the FIFO for writing fails until the container has opened it for reading.
+    loop {
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(gate)
+        {
+            Ok(mut gate) => {
+                counters.enable()?;
+                let started = Instant::now();
+                gate.write_all(b"\n")?;
+                return Ok((counters, started));
+            }
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => check_running()?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+impl Backend for Container {
+    fn wrap(&mut self, command: Command) -> Command {
+        let mut wrapped = Command::new(&self.runtime);
+        wrapped
+            .args(["run", "--rm", "--network=none"])
+            .arg(format!("--cidfile={}", self.cidfile().display()))
+            .arg(format!(
+                "--volume={}:{GATE_MOUNT}",
+                self.dir.path().display()
+            ));
+        if !self.pin.is_empty() {
+            let cpus: Vec<String> = self.pin.iter().map(usize::to_string).collect();
+            wrapped.arg(format!("--cpuset-cpus={}", cpus.join(",")));
+        }
+        if let Some(node) = self.numa_node {
+            wrapped.arg(format!("--cpuset-mems={node}"));
+        }
+        let cwd = command
+            .get_current_dir()
+            .map(Path::to_owned)
+            .or_else(|| std::env::current_dir().ok());
+        let mut mounted: Vec<PathBuf> = Vec::new();
+        if let Some(cwd) = cwd {
+            wrapped.arg(format!("--workdir={}", cwd.display()));
+            mounted.push(cwd);
+        }
+        for arg in command.get_args() {
+            let arg = Path::new(arg);
+            if let Some(parent) = arg.parent().filter(|_| arg.is_absolute()) {
+                if parent != Path::new("/")
+                    && parent.is_dir()
+                    && !mounted.iter().any(|m| m == parent)
+                {
+                    mounted.push(parent.to_owned());
+                }
+            }
+        }
+        for dir in &mounted {
+            wrapped.arg(format!("--volume={0}:{0}", dir.display()));
+        }
+        for (name, value) in command.get_envs() {
+            if let Some(value) = value {
+                let mut env = std::ffi::OsString::from("--env=");
+                env.push(name);
+                env.push("=");
+                env.push(value);
+                wrapped.arg(env);
+            }
+        }
+        wrapped
+            .arg(&self.image)
+            .args(["sh", "-c"])
+            .arg(format!("read _ < {GATE_MOUNT}/gate && exec \"$@\""))
+            .arg("sh")
+            .arg(command.get_program())
+            .args(command.get_args());
+        wrapped
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        // The runtimes refuse to overwrite the container ID file.
+        match std::fs::remove_file(self.cidfile()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let exited = Arc::new(AtomicBool::new(false));
+        let (runtime, cidfile, gate) = (
+            self.runtime.clone(),
+            self.cidfile(),
+            self.dir.path().join("gate"),
+        );
+        let (names, cpus) = (self.counted.clone(), self.cpus.clone());
+        let thread = std::thread::spawn({
+            let exited = exited.clone();
+            move || open_counters(&runtime, &cidfile, &gate, &names, &cpus, &exited)
+        });
+        self.run = Some(Run { exited, thread });
+        Ok(())
+    }
+
+    fn finish(&mut self, _outcome: &Outcome) -> std::io::Result<Reading> {
+        let run = self.run.take().expect("started before finishing");
+        run.exited.store(true, Ordering::Relaxed);
+        let (mut counters, started) = run
+            .thread
+            .join()
+            .expect("the thread opening the counters panicked")
+            .map_err(|e| std::io::Error::other(format!("{e:#}")))?;
+        let wall_time = started.elapsed();
+        counters.disable()?;
+        let mut reading = counters.read()?;
+        if self.wall_time {
+            reading
+                .metrics
+                .insert("wall_time".into(), wall_time.as_nanos() as f64);
+        }
+        self.unavailable = counters.unavailable;
+        Ok(reading)
+    }
+
+    fn unavailable(&self) -> &[&'static str] {
+        &self.unavailable
+    }
+}
@@ -0,0 +1,104 @@
+//! Core types of hybrid CPUs, whose performance and efficiency cores run the same code at
+//! different speeds, so that benchmarks the scheduler moves between them measure bimodally.
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoreType {
+    /// Performance cores
+    P,
+    /// Efficiency cores
+    E,
+}
+
+impl CoreType {
+    pub fn name(self) -> &'static str {
+        match self {
+            CoreType::P => "p",
+            CoreType::E => "e",
+        }
+    }
+}
+
+/// Parses a list of CPUs of sysfs, such as `0-3,8`.
+pub fn parse_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Formats `cpus` as a list of sysfs, such as `0-3,8`.
+pub fn format_list(cpus: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    let ranges: Vec<String> = ranges
+        .into_iter()
+        .map(|(first, last)| match first == last {
+            true => first.to_string(),
+            false => format!("{first}-{last}"),
+        })
+        .collect();
+    ranges.join(",")
+}
+
+fn read_list(path: &str) -> Option<Vec<usize>> {
+    parse_list(&std::fs::read_to_string(path).ok()?)
+}
+
+/// The performance and efficiency CPUs, none unless the CPU is hybrid.
+pub fn detect() -> Option<(Vec<usize>, Vec<usize>)> {
+    // Hybrid Intel CPUs have a PMU per core type.
+    if let (Some(p), Some(e)) = (
+        read_list("/sys/devices/cpu_core/cpus"),
+        read_list("/sys/devices/cpu_atom/cpus"),
+    ) {
+        return Some((p, e));
+    }
+    // Otherwise, as on ARM big.LITTLE CPUs, the performance cores have the highest capacity.
+    let mut capacities = Vec::new();
+    for entry in std::fs::read_dir("/sys/devices/system/cpu").ok()?.flatten() {
+        let name = entry.file_name();
+        let Some(cpu) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpu")?.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        let capacity = std::fs::read_to_string(Path::new(&entry.path()).join("cpu_capacity"));
+        if let Some(capacity) = capacity.ok().and_then(|c| c.trim().parse::<u64>().ok()) {
+            capacities.push((cpu, capacity));
+        }
+    }
+    capacities.sort();
+    let highest = capacities.iter().map(|&(_, capacity)| capacity).max()?;
+    let (p, e): (Vec<_>, Vec<_>) = capacities
+        .iter()
+        .partition(|&&(_, capacity)| capacity == highest);
+    if e.is_empty() {
+        return None;
+    }
+    let cpus = |cores: Vec<&(usize, u64)>| cores.into_iter().map(|&(cpu, _)| cpu).collect();
+    Some((cpus(p), cpus(e)))
+}
+
+/// The CPUs of type `core_type`.
+pub fn cpus(core_type: CoreType) -> anyhow::Result<Vec<usize>> {
+    let Some((p, e)) = detect() else {
+        anyhow::bail!("the CPU isn't hybrid, it has a single type of cores");
+    };
+    Ok(match core_type {
+        CoreType::P => p,
+        CoreType::E => e,
+    })
+}
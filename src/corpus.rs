@@ -0,0 +1,90 @@
+//! Corpus manifests listing benchmark inputs to download rather than to vendor.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::{hash, http};
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Corpus {
+    inputs: Vec<Input>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Input {
+    url: String,
+    sha256: String,
+    /// Path of the file in the corpus directory, the last segment of the URL by default.
+    path: Option<PathBuf>,
+}
+
+impl Input {
+    fn path(&self) -> anyhow::Result<PathBuf> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => PathBuf::from(
+                self.url
+                    .split(['?', '#'])
+                    .next()
+                    .and_then(|url| url.rsplit('/').next())
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| anyhow::anyhow!("{} has no file name, set a path", self.url))?,
+            ),
+        };
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            anyhow::bail!(
+                "corpus path {} must be relative and stay inside the corpus directory",
+                path.display()
+            );
+        }
+        Ok(path)
+    }
+}
+
+impl Corpus {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::read_to_string(path)?;
+        serde_json::from_str(&file)
+            .map_err(|e| anyhow::anyhow!("invalid corpus manifest {}: {e}", path.display()))
+    }
+
+    /// Downloads the inputs missing from `dir` or not matching their checksum, returning the
+    /// paths of all of them.
+    pub fn fetch(&self, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let mut downloaded = 0;
+        for input in &self.inputs {
+            let path = dir.join(input.path()?);
+            let expected = input.sha256.to_ascii_lowercase();
+            if path.exists() && hash::sha256_file(&path)? == expected {
+                paths.push(path);
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            tracing::info!("downloading {}", input.url);
+            // Appended to the whole name, so that inputs only differing by extension don't
+            // share it.
+            let mut partial = path.clone().into_os_string();
+            partial.push(".part");
+            let partial = PathBuf::from(partial);
+            http::download(&input.url, &partial)?;
+            let actual = hash::sha256_file(&partial)?;
+            if actual != expected {
+                std::fs::remove_file(&partial)?;
+                anyhow::bail!("{} has SHA-256 {actual} instead of {expected}", input.url);
+            }
+            std::fs::rename(&partial, &path)?;
+            downloaded += 1;
+            paths.push(path);
+        }
+        tracing::info!(
+            "downloaded {downloaded} of {} corpus input(s) into {}",
+            self.inputs.len(),
+            dir.display()
+        );
+        Ok(paths)
+    }
+}
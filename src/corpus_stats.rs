@@ -0,0 +1,301 @@
+//! Statistics of the inputs of a corpus, to curate the files of a suite: their sizes, the
+//! sections of the WebAssembly modules, and how long recording them took before.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    metrics::{self, DisplayOptions},
+    record::KeyFormat,
+    results::Results,
+};
+
+/// An input file of the corpus.
+pub struct Input {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Number of each kind of module field, for WebAssembly text and binary modules.
+    pub sections: Option<BTreeMap<String, usize>>,
+}
+
+/// Metrics the runtime is estimated from, the first one recorded.
+const TIME_METRICS: &[&str] = &["wall_time", "cpu_time"];
+
+/// The files of `paths`, those of directories recursively, sorted.
+fn files(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let entries = std::fs::read_dir(path)
+                .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+            let mut children = Vec::new();
+            for entry in entries {
+                children.push(entry?.path());
+            }
+            files.extend(self::files(&children)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads an unsigned LEB128 integer at the start of `bytes`, advancing past it.
+fn leb128(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The number of entries of each section of a binary module, none if it is malformed.
+fn binary_sections(module: &[u8]) -> Option<BTreeMap<String, usize>> {
+    let mut sections = BTreeMap::new();
+    let mut rest = module.get(8..)?;
+    while let Some((&id, after)) = rest.split_first() {
+        rest = after;
+        let size = leb128(&mut rest)? as usize;
+        let mut payload = rest.get(..size)?;
+        rest = &rest[size..];
+        let name = match id {
+            1 => "type",
+            2 => "import",
+            3 => "func",
+            4 => "table",
+            5 => "memory",
+            6 => "global",
+            7 => "export",
+            8 => "start",
+            9 => "elem",
+            11 => "data",
+            13 => "tag",
+            _ => continue,
+        };
+        let count = if id == 8 { 1 } else { leb128(&mut payload)? };
+        *sections.entry(name.to_owned()).or_default() += count as usize;
+    }
+    Some(sections)
+}
+
+/// The number of each kind of field of the modules of a text file, such as `func` or
+/// `import`, skipping comments and strings.
+fn text_sections(text: &str) -> BTreeMap<String, usize> {
+    let mut sections = BTreeMap::new();
+    let mut chars = text.chars().peekable();
+    let mut depth = 0;
+    // Depth of the fields: 2 inside a `(module ...)`, 1 for modules written as bare fields.
+    let mut field_depth = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            ';' if chars.peek() == Some(&';') => {
+                chars.find(|&c| c == '\n');
+            }
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '(' if chars.peek() == Some(&';') => {
+                let mut nesting = 1;
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    match (previous, c) {
+                        ('(', ';') => nesting += 1,
+                        (';', ')') => nesting -= 1,
+                        _ => {}
+                    }
+                    if nesting == 0 {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '(' => {
+                depth += 1;
+                let mut keyword = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '.' || c == '_') {
+                        break;
+                    }
+                    keyword.push(c);
+                    chars.next();
+                }
+                if depth == 1 && keyword == "module" {
+                    field_depth = 2;
+                } else if depth == field_depth && !keyword.is_empty() {
+                    *sections.entry(keyword).or_default() += 1;
+                }
+            }
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    sections
+}
+
+/// The sections of the module `path`, if it is one.
+fn sections(path: &Path, content: &[u8]) -> Option<BTreeMap<String, usize>> {
+    if content.starts_with(b"\0asm") {
+        return binary_sections(content);
+    }
+    match path.extension()?.to_str()? {
+        "wat" | "wast" => Some(text_sections(&String::from_utf8_lossy(content))),
+        _ => None,
+    }
+}
+
+/// The inputs in `paths`, files or directories.
+pub fn collect(paths: &[PathBuf]) -> anyhow::Result<Vec<Input>> {
+    files(paths)?
+        .into_iter()
+        .map(|path| {
+            let content = std::fs::read(&path)
+                .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+            Ok(Input {
+                sections: sections(&path, &content),
+                size: content.len() as u64,
+                path,
+            })
+        })
+        .collect()
+}
+
+/// Prints the number of inputs of each power-of-two size range.
+fn print_sizes(inputs: &[Input], display: DisplayOptions) {
+    let bytes = |value: u64| metrics::display("input_size", value as f64, display);
+    let mut buckets: BTreeMap<u32, usize> = BTreeMap::new();
+    for input in inputs {
+        *buckets
+            .entry(u64::BITS - input.size.leading_zeros())
+            .or_default() += 1;
+    }
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row!["Size", "Inputs", "Share"]);
+    for (bits, count) in buckets {
+        let range = match bits {
+            0 => bytes(0),
+            _ => format!("{} - {}", bytes(1 << (bits - 1)), bytes((1 << bits) - 1)),
+        };
+        table.add_row(prettytable::row![
+            range,
+            count,
+            format!("{:.1}%", count as f64 * 100. / inputs.len() as f64)
+        ]);
+    }
+    table.printstd();
+}
+
+/// Prints the totals of the sections of the modules among `inputs`.
+fn print_sections(inputs: &[Input]) {
+    let modules: Vec<&BTreeMap<String, usize>> =
+        inputs.iter().filter_map(|i| i.sections.as_ref()).collect();
+    if modules.is_empty() {
+        return;
+    }
+    println!("Sections of the {} WebAssembly module(s):", modules.len());
+    let mut names: Vec<&String> = modules.iter().flat_map(|m| m.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row![
+        "Section", "Modules", "Total", "Mean", "Max"
+    ]);
+    for name in names {
+        let counts: Vec<usize> = modules
+            .iter()
+            .filter_map(|m| m.get(name).copied())
+            .collect();
+        let total: usize = counts.iter().sum();
+        table.add_row(prettytable::row![
+            name,
+            counts.len(),
+            total,
+            format!("{:.1}", total as f64 / modules.len() as f64),
+            counts.iter().max().copied().unwrap_or(0),
+        ]);
+    }
+    table.printstd();
+}
+
+/// Prints the time recording `inputs` took in `prior` results, estimating that of the inputs
+/// they lack as their mean.
+fn print_runtime(
+    inputs: &[Input],
+    prior: &Results,
+    key_format: &KeyFormat,
+    display: DisplayOptions,
+) -> anyhow::Result<()> {
+    let Some(metric) = TIME_METRICS
+        .iter()
+        .find(|name| prior.values().any(|m| m.metrics.contains_key(**name)))
+    else {
+        tracing::warn!("the prior results have no time metric to estimate the runtime with");
+        return Ok(());
+    };
+    let mut times = Vec::new();
+    for input in inputs {
+        let key = key_format.render(&input.path)?;
+        if let Some(measure) = prior.get(&key) {
+            if let Some(value) = measure.metrics.get(*metric) {
+                times.push(value * measure.samples.len().max(1) as f64);
+            }
+        }
+    }
+    let known: f64 = times.iter().sum();
+    let unknown = inputs.len() - times.len();
+    let estimate = match times.len() {
+        0 => 0.,
+        n => known + known / n as f64 * unknown as f64,
+    };
+    println!(
+        "Estimated runtime: {} ({metric} of {} input(s) in the prior results over their \
+         iterations, {unknown} estimated as their mean)",
+        metrics::display(metric, estimate, display),
+        times.len(),
+    );
+    Ok(())
+}
+
+/// Prints the statistics of `inputs`, with their estimated runtime if `prior` results are
+/// given, whose keys are rendered with `key_format`.
+pub fn print(
+    inputs: &[Input],
+    prior: Option<&Results>,
+    key_format: &KeyFormat,
+    display: DisplayOptions,
+) -> anyhow::Result<()> {
+    if inputs.is_empty() {
+        anyhow::bail!("no inputs");
+    }
+    let bytes = |value: f64| metrics::display("input_size", value, display);
+    let sizes: Vec<f64> = inputs.iter().map(|i| i.size as f64).collect();
+    println!(
+        "Inputs: {}, total size {} (min {}, median {}, max {})",
+        inputs.len(),
+        bytes(sizes.iter().sum()),
+        bytes(sizes.iter().copied().fold(f64::INFINITY, f64::min)),
+        bytes(metrics::median_of(sizes.clone())),
+        bytes(sizes.iter().copied().fold(0., f64::max)),
+    );
+    print_sizes(inputs, display);
+    print_sections(inputs);
+    if let Some(prior) = prior {
+        print_runtime(inputs, prior, key_format, display)?;
+    }
+    Ok(())
+}
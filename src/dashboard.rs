@@ -0,0 +1,259 @@
+//! Local web dashboard of the history: the latest comparison of every benchmark, and a page
+//! per benchmark charting its metrics over the runs.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    compare,
+    history::{History, Run},
+    metrics::DisplayOptions,
+    results, trend,
+};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; } \
+    table { border-collapse: collapse; } \
+    td, th { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: right; } \
+    td:first-child, th:first-child { text-align: left; } \
+    .worse { color: #c00; } .better { color: #080; } .busy { color: #b80; } \
+    .note { color: #666; font-style: italic; }";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Encodes `text` as a URL path segment.
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\
+         <style>{STYLE}</style></head>\n<body>{body}</body></html>\n",
+        escape(title)
+    )
+}
+
+fn change_cell(base: Option<f64>, compared: Option<f64>, threshold: f64) -> String {
+    let (Some(base), Some(compared)) = (base, compared) else {
+        return "<td>-</td>".into();
+    };
+    let diff = compare::rel_diff(base, compared);
+    let class = if diff > threshold {
+        " class=\"worse\""
+    } else if diff < -threshold {
+        " class=\"better\""
+    } else {
+        ""
+    };
+    format!("<td{class}>{diff:+.1}%</td>")
+}
+
+/// The benchmarks of the latest run, with their changes over the previous one.
+fn index(runs: &[Run], threshold: f64) -> String {
+    let Some(latest) = runs.last() else {
+        return page(
+            "Benchmarks",
+            "<h1>Benchmarks</h1><p>The history is empty.</p>",
+        );
+    };
+    let previous = runs.len().checked_sub(2).map(|i| &runs[i].file.results);
+    let names = results::metric_names([&latest.file.results]);
+    let mut body = format!(
+        "<h1>Benchmarks</h1>\n<p>{} run(s). Latest: {}",
+        runs.len(),
+        escape(&latest.label())
+    );
+    if let Some(metadata) = &latest.file.metadata {
+        body.push_str(&format!(", {}", escape(&metadata.summary())));
+    }
+    body.push_str(match previous {
+        Some(_) => ", compared with the previous run.</p>\n",
+        None => ".</p>\n",
+    });
+    body.push_str("<table><tr><th>Benchmark</th>");
+    for name in &names {
+        body.push_str(&format!("<th>{}</th>", escape(name)));
+    }
+    body.push_str("</tr>\n");
+    for (key, measure) in &latest.file.results {
+        let mut link = format!("<a href=\"/benchmark/{}\">{}</a>", encode(key), escape(key));
+        if let Some(note) = &measure.note {
+            link.push_str(&format!(" <span class=\"note\">{}</span>", escape(note)));
+        }
+        if measure.is_noisy() {
+            body.push_str(&format!(
+                "<tr><td>{link} <span class=\"busy\">(busy)</span></td>"
+            ));
+        } else {
+            body.push_str(&format!("<tr><td>{link}</td>"));
+        }
+        let base = previous.and_then(|previous| previous.get(key));
+        for name in &names {
+            body.push_str(&change_cell(
+                base.and_then(|m| m.metrics.get(name).copied()),
+                measure.metrics.get(name).copied(),
+                threshold,
+            ));
+        }
+        body.push_str("</tr>\n");
+    }
+    body.push_str("</table>\n");
+    page("Benchmarks", &body)
+}
+
+/// A chart per metric of `key`, and its runs taken on a busy machine.
+fn benchmark(runs: &[Run], key: &str, display: DisplayOptions) -> Option<String> {
+    let measured: Vec<&Run> = runs
+        .iter()
+        .filter(|run| run.file.results.contains_key(key))
+        .collect();
+    let latest = measured.last()?;
+    let mut body = format!(
+        "<p><a href=\"/\">All benchmarks</a></p>\n<h1>{}</h1>\n",
+        escape(key)
+    );
+    if let Some(note) = &latest.file.results[key].note {
+        body.push_str(&format!("<p class=\"note\">{}</p>\n", escape(note)));
+    }
+    let busy: Vec<String> = measured
+        .iter()
+        .filter(|run| run.file.results[key].is_noisy())
+        .map(|run| escape(&run.label()))
+        .collect();
+    if !busy.is_empty() {
+        body.push_str(&format!(
+            "<p class=\"busy\">Measured on a busy machine in {}</p>\n",
+            busy.join(", ")
+        ));
+    }
+    let mut names: Vec<String> = latest.file.results[key].metrics.keys().cloned().collect();
+    results::sort_metric_names(&mut names);
+    for name in names {
+        let points = trend::points(runs, key, &name);
+        if !points.is_empty() {
+            body.push_str(&trend::svg(&points, key, &name, display));
+        }
+    }
+    Some(page(key, &body))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn handle(
+    stream: &mut TcpStream,
+    history: &History,
+    threshold: f64,
+    display: DisplayOptions,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let mut words = request.split_whitespace();
+    let (Some("GET"), Some(path)) = (words.next(), words.next()) else {
+        return Ok(respond(
+            stream,
+            "405 Method Not Allowed",
+            &page("Error", "Only GET is supported"),
+        )?);
+    };
+    let path = path.split('?').next().unwrap_or_default();
+    let runs = history.runs()?;
+    let page = match path {
+        "/" => Some(index(&runs, threshold)),
+        _ => path
+            .strip_prefix("/benchmark/")
+            .and_then(|key| benchmark(&runs, &decode(key), display)),
+    };
+    match page {
+        Some(page) => respond(stream, "200 OK", &page)?,
+        None => respond(
+            stream,
+            "404 Not Found",
+            &self::page("Not found", "Not found"),
+        )?,
+    }
+    Ok(())
+}
+
+/// Serves the dashboard of `history` on `address` until interrupted, reading the history
+/// again for every page.
+pub fn serve(
+    history: &History,
+    address: &str,
+    threshold: f64,
+    display: DisplayOptions,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address)
+        .map_err(|e| anyhow::anyhow!("cannot listen on {address}: {e}"))?;
+    tracing::info!(
+        "serving the dashboard of {} on http://{}",
+        history.dir().display(),
+        listener.local_addr()?
+    );
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("cannot accept a connection: {e}");
+                continue;
+            }
+        };
+        if let Err(e) = handle(&mut stream, history, threshold, display) {
+            tracing::warn!("cannot serve a request: {e}");
+            let _ = respond(
+                &mut stream,
+                "500 Internal Server Error",
+                &page("Error", &escape(&e.to_string())),
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Terminal plots of the metrics of every iteration, showing at a glance distributions that a
+//! median hides, such as bimodal ones.
+
+use crate::{
+    metrics::{self, DisplayOptions},
+    results::{self, Results},
+};
+
+/// Heights of the bars, from the lowest to the highest.
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Number of bins of the histograms.
+const BINS: usize = 16;
+
+/// The block of `value` on a scale from 0 to `max`, a space for 0.
+fn block(value: f64, max: f64) -> char {
+    if value <= 0. || max <= 0. {
+        return ' ';
+    }
+    let level = (value / max * (BLOCKS.len() - 1) as f64).round() as usize;
+    BLOCKS[level.min(BLOCKS.len() - 1)]
+}
+
+fn bounds(values: &[f64]) -> (f64, f64) {
+    values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        })
+}
+
+/// The number of `values` in each of [`BINS`] equal ranges from the lowest to the highest.
+fn histogram(values: &[f64]) -> String {
+    let (min, max) = bounds(values);
+    let mut counts = [0usize; BINS];
+    for &value in values {
+        let bin = if max > min {
+            ((value - min) / (max - min) * BINS as f64) as usize
+        } else {
+            BINS / 2
+        };
+        counts[bin.min(BINS - 1)] += 1;
+    }
+    let highest = *counts.iter().max().unwrap_or(&0) as f64;
+    counts
+        .iter()
+        .map(|&count| block(count as f64, highest))
+        .collect()
+}
+
+/// The `values` in the order of the iterations, scaled from the lowest to the highest.
+pub fn sparkline(values: &[f64]) -> String {
+    let (min, max) = bounds(values);
+    values
+        .iter()
+        .map(|&value| {
+            let level = if max > min {
+                ((value - min) / (max - min) * (BLOCKS.len() - 1) as f64).round() as usize
+            } else {
+                BLOCKS.len() / 2
+            };
+            BLOCKS[level]
+        })
+        .collect()
+}
+
+/// Prints the histogram of the iterations of each metric of each benchmark, and their values
+/// in the order they ran in.
+pub fn print(res: &Results, display: DisplayOptions) {
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row![
+        "File",
+        "Metric",
+        "Min",
+        "Histogram",
+        "Max",
+        "Iterations"
+    ]);
+    for (key, measure) in res.iter().filter(|(_, m)| m.samples.len() > 1) {
+        let mut names: Vec<String> = measure.metrics.keys().cloned().collect();
+        results::sort_metric_names(&mut names);
+        for name in &names {
+            let values: Vec<f64> = measure
+                .samples
+                .iter()
+                .filter_map(|sample| sample.metrics.get(name).copied())
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+            let (min, max) = bounds(&values);
+            table.add_row(prettytable::row![
+                key,
+                metrics::header(name, display),
+                r->metrics::display(name, min, display),
+                histogram(&values),
+                metrics::display(name, max, display),
+                sparkline(&values),
+            ]);
+        }
+    }
+    if table.len() == 1 {
+        tracing::warn!("no benchmark has several iterations to show the distribution of");
+        return;
+    }
+    table.printstd();
+}
@@ -0,0 +1,121 @@
+//! Tracing of the kernel activity of the benchmark process tree with eBPF, through `bpftrace`.
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, ChildStdout, Command, Stdio},
+};
+
+use crate::{
+    command,
+    metrics::{Metric, Metrics},
+};
+
+/// Follows the processes forked from this one, i.e. the benchmarks and their descendants, and
+/// accounts their system calls, block I/O and the time they spend switched out.
+const SCRIPT: &str = r#"
+tracepoint:sched:sched_process_fork
+/args->parent_pid == $1 || @tree[args->parent_pid]/
+{ @tree[args->child_pid] = 1; }
+
+tracepoint:raw_syscalls:sys_enter /@tree[tid]/ { @syscalls = count(); }
+
+tracepoint:block:block_rq_issue /@tree[tid]/ { @block_io_bytes = sum(args->bytes); }
+
+tracepoint:sched:sched_switch /@tree[args->prev_pid]/
+{ @switched_out[args->prev_pid] = nsecs; }
+
+tracepoint:sched:sched_switch /@switched_out[args->next_pid]/
+{
+    @off_cpu_time = sum(nsecs - @switched_out[args->next_pid]);
+    delete(@switched_out[args->next_pid]);
+}
+
+END { clear(@tree); clear(@switched_out); }
+"#;
+
+pub struct Tracer {
+    metrics: Vec<&'static Metric>,
+}
+
+/// A `bpftrace` process tracing one run.
+pub struct Trace {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Tracer {
+    /// Traces the eBPF metrics among `metrics`, if any.
+    pub fn new(metrics: &[String]) -> anyhow::Result<Option<Self>> {
+        let mut traced = Vec::new();
+        for name in metrics {
+            let metric = crate::metrics::lookup(name)?;
+            if metric.is_ebpf() {
+                traced.push(metric);
+            }
+        }
+        if traced.is_empty() {
+            return Ok(None);
+        }
+        if command::resolve_program("bpftrace").is_none() {
+            anyhow::bail!(
+                "cannot find bpftrace, which traces {}",
+                traced.iter().map(|m| m.name).collect::<Vec<_>>().join(", ")
+            );
+        }
+        Ok(Some(Self { metrics: traced }))
+    }
+
+    /// Starts tracing, returning once the probes are attached.
+    pub fn start(&self) -> anyhow::Result<Trace> {
+        let mut child = Command::new("bpftrace")
+            .args(["-f", "json", "-e", SCRIPT])
+            .arg(std::process::id().to_string())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if stdout.read_line(&mut line)? == 0 {
+                anyhow::bail!(
+                    "bpftrace exited before attaching its probes ({})",
+                    child.wait()?
+                );
+            }
+            if line.contains("\"attached_probes\"") {
+                return Ok(Trace { child, stdout });
+            }
+        }
+    }
+
+    /// Stops `trace` and collects its metrics, which are zero if nothing happened.
+    pub fn stop(&self, mut trace: Trace) -> anyhow::Result<Metrics> {
+        if unsafe { libc::kill(trace.child.id() as libc::pid_t, libc::SIGINT) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut metrics: Metrics = self
+            .metrics
+            .iter()
+            .map(|metric| (metric.name.to_owned(), 0.))
+            .collect();
+        for line in trace.stdout.lines() {
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(&line?) else {
+                continue;
+            };
+            if event["type"] != "map" {
+                continue;
+            }
+            let Some(data) = event["data"].as_object() else {
+                continue;
+            };
+            for (map, value) in data {
+                let name = map.trim_start_matches('@');
+                if let (Some(metric), Some(value)) = (metrics.get_mut(name), value.as_f64()) {
+                    *metric = value;
+                }
+            }
+        }
+        trace.child.wait()?;
+        Ok(metrics)
+    }
+}
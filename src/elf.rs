@@ -0,0 +1,144 @@
+//! The function symbols of 64-bit little-endian ELF binaries, and where their code is in the
+//! file, read from their symbol tables without loading them.
+
+use std::path::Path;
+
+/// A function defined in a binary.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    /// Virtual address of its code when the binary is loaded at its preferred address.
+    pub address: u64,
+    pub size: u64,
+}
+
+/// A loaded segment of a binary.
+struct Segment {
+    address: u64,
+    offset: u64,
+    size: u64,
+}
+
+pub struct Elf {
+    segments: Vec<Segment>,
+    /// Functions of the symbol table and the dynamic one, sorted by address.
+    pub functions: Vec<Symbol>,
+}
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const STT_FUNC: u8 = 2;
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// The nul-terminated string at `at` of a string table.
+fn str_at(table: &[u8], at: usize) -> Option<String> {
+    let bytes = table.get(at..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+fn parse(data: &[u8]) -> Option<Elf> {
+    let phoff = u64_at(data, 0x20)? as usize;
+    let shoff = u64_at(data, 0x28)? as usize;
+    let (phentsize, phnum) = (u16_at(data, 0x36)? as usize, u16_at(data, 0x38)? as usize);
+    let (shentsize, shnum) = (u16_at(data, 0x3a)? as usize, u16_at(data, 0x3c)? as usize);
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        if u32_at(data, header)? == PT_LOAD {
+            segments.push(Segment {
+                offset: u64_at(data, header + 8)?,
+                address: u64_at(data, header + 16)?,
+                size: u64_at(data, header + 32)?,
+            });
+        }
+    }
+    // Offset and size of each section.
+    let section = |i: usize| -> Option<&[u8]> {
+        let header = shoff + i * shentsize;
+        let offset = u64_at(data, header + 24)? as usize;
+        data.get(offset..offset + u64_at(data, header + 32)? as usize)
+    };
+    let mut functions: Vec<Symbol> = Vec::new();
+    for i in 0..shnum {
+        let header = shoff + i * shentsize;
+        if !matches!(u32_at(data, header + 4)?, SHT_SYMTAB | SHT_DYNSYM) {
+            continue;
+        }
+        let symbols = section(i)?;
+        let names = section(u32_at(data, header + 40)? as usize)?;
+        for symbol in symbols.chunks_exact(24) {
+            let defined = u16_at(symbol, 6)? != 0;
+            if symbol[4] & 0xf != STT_FUNC || !defined {
+                continue;
+            }
+            functions.push(Symbol {
+                name: str_at(names, u32_at(symbol, 0)? as usize)?,
+                address: u64_at(symbol, 8)?,
+                size: u64_at(symbol, 16)?,
+            });
+        }
+    }
+    functions.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+    functions.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+    Some(Elf {
+        segments,
+        functions,
+    })
+}
+
+impl Elf {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        if !data.starts_with(b"\x7fELF\x02\x01") {
+            anyhow::bail!(
+                "{} is not a 64-bit little-endian ELF binary",
+                path.display()
+            );
+        }
+        parse(&data).ok_or_else(|| anyhow::anyhow!("{} is a malformed ELF binary", path.display()))
+    }
+
+    /// The function named `name`.
+    pub fn function(&self, name: &str) -> Option<&Symbol> {
+        self.functions.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// The offset in the file of the code at `address`.
+    pub fn file_offset(&self, address: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|s| (s.address..s.address + s.size).contains(&address))
+            .map(|s| address - s.address + s.offset)
+    }
+
+    /// The address the code at `offset` of the file is loaded at.
+    pub fn address(&self, offset: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|s| (s.offset..s.offset + s.size).contains(&offset))
+            .map(|s| offset - s.offset + s.address)
+    }
+
+    /// The function whose code contains `address`.
+    pub fn function_at(&self, address: u64) -> Option<&Symbol> {
+        let after = self.functions.partition_point(|f| f.address <= address);
+        self.functions[..after]
+            .iter()
+            .rev()
+            .find(|f| address < f.address + f.size)
+    }
+}
@@ -0,0 +1,75 @@
+//! Posting comparisons as comments of GitHub pull requests.
+
+use crate::{
+    compare::REPORT_MARKER,
+    http::{self, required_env as env},
+};
+
+/// Number of the pull request a GitHub Actions workflow runs for.
+fn current_pr() -> anyhow::Result<u64> {
+    if let Some(number) = std::env::var("GITHUB_REF").ok().and_then(|r| {
+        r.strip_prefix("refs/pull/")?
+            .split('/')
+            .next()?
+            .parse()
+            .ok()
+    }) {
+        return Ok(number);
+    }
+    let event: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(env("GITHUB_EVENT_PATH")?)?)?;
+    event["pull_request"]["number"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("not running for a pull request, pass --pr"))
+}
+
+/// Creates the comment of the orchestrator with `body` on a pull request, or updates it.
+///
+/// The repository and token are read from `GITHUB_REPOSITORY` and `GITHUB_TOKEN`, and the pull
+/// request is `pr` or the one of the current GitHub Actions workflow.
+pub fn post_comment(body: &str, pr: Option<u64>) -> anyhow::Result<()> {
+    let token = env("GITHUB_TOKEN")?;
+    let repo = env("GITHUB_REPOSITORY")?;
+    let api = std::env::var("GITHUB_API_URL").unwrap_or_else(|_| "https://api.github.com".into());
+    let pr = match pr {
+        Some(pr) => pr,
+        None => current_pr()?,
+    };
+    let authorization = format!("Bearer {token}");
+    let headers = [
+        ("Authorization", authorization.as_str()),
+        ("Accept", "application/vnd.github+json"),
+        ("User-Agent", "perf-bench-orchestrator"),
+    ];
+    let mut existing = None;
+    for page in 1.. {
+        let url = format!("{api}/repos/{repo}/issues/{pr}/comments?per_page=100&page={page}");
+        let comments = http::json_request("GET", &url, &headers, None)?;
+        let comments = comments.as_array().cloned().unwrap_or_default();
+        existing = comments
+            .iter()
+            .find(|c| {
+                c["body"]
+                    .as_str()
+                    .is_some_and(|b| b.contains(REPORT_MARKER))
+            })
+            .and_then(|c| c["id"].as_u64());
+        if existing.is_some() || comments.len() < 100 {
+            break;
+        }
+    }
+    let body = serde_json::json!({ "body": format!("{REPORT_MARKER}\n{body}") });
+    match existing {
+        Some(id) => {
+            let url = format!("{api}/repos/{repo}/issues/comments/{id}");
+            http::json_request("PATCH", &url, &headers, Some(&body))?;
+            tracing::info!("updated the comparison comment of {repo}#{pr}");
+        }
+        None => {
+            let url = format!("{api}/repos/{repo}/issues/{pr}/comments");
+            http::json_request("POST", &url, &headers, Some(&body))?;
+            tracing::info!("commented the comparison on {repo}#{pr}");
+        }
+    }
+    Ok(())
+}
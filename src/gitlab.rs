@@ -0,0 +1,54 @@
+//! Posting comparisons as notes of GitLab merge requests.
+
+use crate::{
+    compare::REPORT_MARKER,
+    http::{self, required_env as env},
+};
+
+/// Creates the note of the orchestrator with `body` on a merge request, or updates it.
+///
+/// The API and project are read from the `CI_API_V4_URL` and `CI_PROJECT_ID` variables of
+/// GitLab CI and the token from `GITLAB_TOKEN`, and the merge request is `mr` or the one of
+/// the current pipeline.
+pub fn post_note(body: &str, mr: Option<u64>) -> anyhow::Result<()> {
+    let token = env("GITLAB_TOKEN")?;
+    let api = env("CI_API_V4_URL")?;
+    let project = env("CI_PROJECT_ID")?;
+    let mr = match mr {
+        Some(mr) => mr,
+        None => env("CI_MERGE_REQUEST_IID")
+            .map_err(|_| anyhow::anyhow!("not running for a merge request, pass --mr"))?
+            .parse()?,
+    };
+    let headers = [("PRIVATE-TOKEN", token.as_str())];
+    let notes_url = format!("{api}/projects/{project}/merge_requests/{mr}/notes");
+    let mut existing = None;
+    for page in 1.. {
+        let url = format!("{notes_url}?per_page=100&page={page}");
+        let notes = http::json_request("GET", &url, &headers, None)?;
+        let notes = notes.as_array().cloned().unwrap_or_default();
+        existing = notes
+            .iter()
+            .find(|n| {
+                n["body"]
+                    .as_str()
+                    .is_some_and(|b| b.contains(REPORT_MARKER))
+            })
+            .and_then(|n| n["id"].as_u64());
+        if existing.is_some() || notes.len() < 100 {
+            break;
+        }
+    }
+    let body = serde_json::json!({ "body": format!("{REPORT_MARKER}\n{body}") });
+    match existing {
+        Some(id) => {
+            http::json_request("PUT", &format!("{notes_url}/{id}"), &headers, Some(&body))?;
+            tracing::info!("updated the comparison note of !{mr}");
+        }
+        None => {
+            http::json_request("POST", &notes_url, &headers, Some(&body))?;
+            tracing::info!("posted the comparison as a note of !{mr}");
+        }
+    }
+    Ok(())
+}
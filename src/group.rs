@@ -0,0 +1,77 @@
+//! Groups of benchmarks, such as the categories of a suite organized in directories, shown with
+//! a subtotal of each.
+
+use std::collections::BTreeMap;
+
+use crate::results::{Measure, Results};
+
+/// What benchmarks are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// Directory of their keys
+    Dir,
+}
+
+/// The group of the benchmark `key`.
+pub fn of(key: &str, by: GroupBy) -> String {
+    match by {
+        GroupBy::Dir => match key.rsplit_once('/') {
+            Some((dir, _)) => format!("{dir}/"),
+            None => "./".to_owned(),
+        },
+    }
+}
+
+/// The keys of `res` by group.
+pub fn keys(res: &Results, by: GroupBy) -> BTreeMap<String, Vec<&String>> {
+    let mut groups: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+    for key in res.keys() {
+        groups.entry(of(key, by)).or_default().push(key);
+    }
+    groups
+}
+
+/// The sum of each metric of the benchmarks `keys` of `res`, with their mean machine load.
+pub fn sum<'a>(res: &Results, keys: impl IntoIterator<Item = &'a String>) -> Measure {
+    let mut sum = Measure::default();
+    let mut n = 0;
+    for measure in keys.into_iter().filter_map(|key| res.get(key)) {
+        for (name, value) in &measure.metrics {
+            *sum.metrics.entry(name.clone()).or_default() += value;
+        }
+        sum.load_avg += measure.load_avg;
+        sum.interference += measure.interference;
+        n += 1;
+    }
+    if n > 0 {
+        sum.load_avg /= n as f64;
+        sum.interference /= n as f64;
+    }
+    sum
+}
+
+/// Key of the row of the total of all the benchmarks.
+pub const TOTAL: &str = "TOTAL";
+
+/// Key of the subtotal row of `group`.
+pub fn subtotal_key(group: &str) -> String {
+    format!("{group} (subtotal)")
+}
+
+/// The entries of `res` and a subtotal entry per group of `groups`, with their keys in the
+/// order they are shown in: the benchmarks of each group followed by its subtotal.
+pub fn with_subtotals(
+    res: &Results,
+    groups: &BTreeMap<String, Vec<&String>>,
+) -> (Results, Vec<String>) {
+    let mut rows = res.clone();
+    let mut order = Vec::new();
+    for (group, keys) in groups {
+        order.extend(keys.iter().map(|key| (*key).clone()));
+        let subtotal = subtotal_key(group);
+        rows.insert(subtotal.clone(), sum(res, keys.iter().copied()));
+        order.push(subtotal);
+    }
+    (rows, order)
+}
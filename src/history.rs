@@ -0,0 +1,218 @@
+//! History of runs: a directory of results files, ordered by the date they were recorded.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache, config, metadata, metrics,
+    results::{self, Results, ResultsFile},
+};
+
+pub struct History {
+    dir: PathBuf,
+}
+
+/// Values of each metric of each benchmark over runs, oldest first, by key then metric.
+pub type Recent = BTreeMap<String, BTreeMap<String, Vec<f64>>>;
+
+/// A results file of the history.
+pub struct Run {
+    pub path: PathBuf,
+    pub file: ResultsFile,
+}
+
+impl Run {
+    /// When the run was recorded, in seconds since the epoch.
+    pub fn time(&self) -> Option<i64> {
+        metadata::parse_utc(&self.file.metadata.as_ref()?.date)
+    }
+
+    /// The git revision of the run, or else its date.
+    pub fn label(&self) -> String {
+        match &self.file.metadata {
+            Some(metadata) => match &metadata.git {
+                Some(git) => git.short(),
+                None => metadata.date.clone(),
+            },
+            None => "unknown".into(),
+        }
+    }
+}
+
+impl History {
+    /// The history in `dir`, or else in the configured directory.
+    pub fn open(dir: Option<PathBuf>, config: &config::Config) -> anyhow::Result<Self> {
+        let dir = match dir.or(config.history_dir.clone()) {
+            Some(dir) => dir,
+            None => config
+                .cache_dir
+                .clone()
+                .or_else(cache::default_dir)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("cannot determine the history directory, pass --history-dir")
+                })?
+                .join("history"),
+        };
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| anyhow::anyhow!("cannot create {}: {e}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// Stores a copy of `file`, named after its date and git revision.
+    pub fn add(&self, file: &ResultsFile) -> anyhow::Result<PathBuf> {
+        let path = self.free_path(file)?;
+        results::write_new(&path, file)?;
+        Ok(path)
+    }
+
+    /// A path not taken yet for `file`, named after its date and git revision.
+    fn free_path(&self, file: &ResultsFile) -> anyhow::Result<PathBuf> {
+        let Some(metadata) = &file.metadata else {
+            anyhow::bail!("the results have no metadata to date them with");
+        };
+        let mut name = metadata.date.replace(':', "");
+        if let Some(git) = &metadata.git {
+            name = format!("{name}-{}", git.short());
+        }
+        let mut path = self.dir.join(format!("{name}.json"));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = self.dir.join(format!("{name}-{n}.json"));
+        }
+        Ok(path)
+    }
+
+    /// The runs of the history, oldest first.
+    pub fn runs(&self) -> anyhow::Result<Vec<Run>> {
+        let mut runs = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let file = results::read(&path)?;
+                runs.push(Run { path, file });
+            }
+        }
+        runs.sort_by(|a, b| (a.time(), &a.path).cmp(&(b.time(), &b.path)));
+        Ok(runs)
+    }
+
+    /// The values of each metric of each benchmark in the last `n` runs that measured it.
+    pub fn recent(&self, n: usize) -> anyhow::Result<Recent> {
+        let mut recent = Recent::new();
+        for run in self.runs()?.iter().rev() {
+            for (key, measure) in &run.file.results {
+                let metrics = recent.entry(key.clone()).or_default();
+                for (name, &value) in &measure.metrics {
+                    let values = metrics.entry(name.clone()).or_default();
+                    if values.len() < n {
+                        values.push(value);
+                    }
+                }
+            }
+        }
+        for values in recent.values_mut().flat_map(BTreeMap::values_mut) {
+            values.reverse();
+        }
+        Ok(recent)
+    }
+
+    /// Compacts the runs older than the `keep_last` most recent ones into an aggregate per day,
+    /// leaving the tagged ones alone if `keep_tagged`. Returns the number of runs removed and
+    /// of aggregates written.
+    pub fn prune(&self, keep_last: usize, keep_tagged: bool) -> anyhow::Result<(usize, usize)> {
+        let mut runs = self.runs()?;
+        runs.truncate(runs.len().saturating_sub(keep_last));
+        let mut days: BTreeMap<String, Vec<Run>> = BTreeMap::new();
+        for run in runs {
+            let Some(metadata) = &run.file.metadata else {
+                continue;
+            };
+            if keep_tagged && !metadata.tags.is_empty() {
+                continue;
+            }
+            let day = metadata
+                .date
+                .split('T')
+                .next()
+                .unwrap_or_default()
+                .to_owned();
+            days.entry(day).or_default().push(run);
+        }
+        let (mut removed, mut aggregates) = (0, 0);
+        for (day, runs) in days {
+            if runs.len() < 2 {
+                continue;
+            }
+            // Written aside first, for a failure to leave the runs as they were, and named once
+            // they are removed, after the latest of them.
+            let aggregate = aggregate(&runs);
+            let tmp = self.dir.join(format!("aggregate-{day}.json.tmp"));
+            serde_json::to_writer_pretty(std::fs::File::create(&tmp)?, &aggregate)?;
+            for run in &runs {
+                std::fs::remove_file(&run.path).map_err(|e| {
+                    anyhow::anyhow!(
+                        "cannot remove {} ({e}), the aggregate of {day} is left in {}",
+                        run.path.display(),
+                        tmp.display()
+                    )
+                })?;
+            }
+            let path = self.free_path(&aggregate)?;
+            std::fs::rename(&tmp, &path)?;
+            tracing::info!(
+                "compacted {} run(s) of {day} into {}",
+                runs.len(),
+                path.display()
+            );
+            removed += runs.len();
+            aggregates += 1;
+        }
+        Ok((removed, aggregates))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// A run with the medians of the metrics of `runs`, each weighted by the number of runs it
+/// aggregates, and the metadata of the latest one.
+fn aggregate(runs: &[Run]) -> ResultsFile {
+    let latest = &runs[runs.len() - 1].file;
+    let weight = |run: &Run| {
+        run.file
+            .metadata
+            .as_ref()
+            .and_then(|m| m.aggregate_of)
+            .unwrap_or(1)
+    };
+    let mut metadata = latest.metadata.clone();
+    if let Some(metadata) = &mut metadata {
+        metadata.aggregate_of = Some(runs.iter().map(weight).sum());
+    }
+    let mut results = Results::new();
+    for run in runs.iter().rev() {
+        for (key, measure) in &run.file.results {
+            if results.contains_key(key) {
+                continue;
+            }
+            let mut aggregated = measure.clone();
+            for (metric, value) in &mut aggregated.metrics {
+                *value = metrics::weighted_median(
+                    runs.iter()
+                        .filter_map(|run| {
+                            let value = run.file.results.get(key)?.metrics.get(metric)?;
+                            Some((*value, weight(run)))
+                        })
+                        .collect(),
+                );
+            }
+            aggregated.samples.clear();
+            results.insert(key.clone(), aggregated);
+        }
+    }
+    ResultsFile { metadata, results }
+}
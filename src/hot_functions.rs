@@ -0,0 +1,214 @@
+//! Self cycles of the functions of the benchmarks, from samples of their instruction pointer
+//! attributed with the symbol tables of the binaries mapped there. Samples are symbolized as
+//! they are recorded, the binaries of the base of a comparison being often rebuilt by the
+//! time it is made.
+
+#[cfg(target_os = "linux")]
+use std::{
+    collections::{BTreeMap, HashMap},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+#[cfg(target_os = "linux")]
+use perf_event as prf;
+#[cfg(target_os = "linux")]
+use prf::{
+    data::Record,
+    events::{Hardware, Software},
+    SampleFlag,
+};
+
+#[cfg(target_os = "linux")]
+use crate::{elf::Elf, metrics::Metrics};
+#[cfg(not(target_os = "linux"))]
+use {crate::metrics::Metrics, std::collections::BTreeMap};
+
+/// Size of the buffer of the samples of a run on each CPU, enough for seconds of samples.
+#[cfg(target_os = "linux")]
+const BUFFER_SIZE: usize = 1 << 21;
+
+/// Cycles between samples, not round so that loops don't alias with it.
+#[cfg(target_os = "linux")]
+const CYCLES_PERIOD: u64 = 250_003;
+
+/// Nanoseconds of CPU time between samples, without hardware counters.
+#[cfg(target_os = "linux")]
+const CLOCK_PERIOD: u64 = 100_000;
+
+#[cfg(target_os = "linux")]
+pub struct Profiler {
+    /// A sampler per CPU, the kernel refusing to map the buffer of inherited counters that
+    /// follow a task on any CPU.
+    samplers: Vec<prf::Sampler>,
+    /// Metric sampled: cycles, or the CPU clock without hardware counters.
+    metric: &'static str,
+    period: u64,
+}
+
+/// A file mapped executable in a process.
+#[cfg(target_os = "linux")]
+struct Mapping {
+    start: u64,
+    end: u64,
+    /// Offset in the file of the start of the mapping.
+    offset: u64,
+    path: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+fn open(
+    event: impl Fn() -> prf::Builder<'static>,
+    period: u64,
+) -> std::io::Result<Vec<prf::Sampler>> {
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as usize;
+    (0..cpus)
+        .map(|cpu| {
+            event()
+                .one_cpu(cpu)
+                .inherit(true)
+                .enable_on_exec(true)
+                .exclude_kernel(true)
+                .exclude_hv(true)
+                .mmap(true)
+                .task(true)
+                .sample(SampleFlag::IP | SampleFlag::TID)
+                .sample_period(period)
+                .build()?
+                .sampled(BUFFER_SIZE)
+        })
+        .collect()
+}
+
+/// The name of the function of `path` whose code is at `offset`, `elves` caching the symbols
+/// of the binaries. Functions are not qualified with their binary, for the same functions of
+/// binaries built under other names to match; code outside the known functions is named
+/// after the binary, in brackets.
+#[cfg(target_os = "linux")]
+fn symbolize(elves: &mut HashMap<PathBuf, Option<Elf>>, path: &Path, offset: u64) -> String {
+    let elf = elves
+        .entry(path.to_owned())
+        .or_insert_with(|| Elf::read(path).ok());
+    let function = elf
+        .as_ref()
+        .and_then(|elf| elf.function_at(elf.address(offset)?));
+    match function {
+        Some(function) => function.name.clone(),
+        None => {
+            let binary = path.file_name().unwrap_or(path.as_os_str());
+            format!("[{}]", binary.to_string_lossy())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Profiler {
+    pub fn open() -> anyhow::Result<Self> {
+        match open(|| prf::Builder::new(Hardware::CPU_CYCLES), CYCLES_PERIOD) {
+            Ok(samplers) => Ok(Self {
+                samplers,
+                metric: "cycles",
+                period: CYCLES_PERIOD,
+            }),
+            Err(e) => {
+                tracing::warn!("cannot sample cycles ({e}), sampling cpu_clock instead");
+                let samplers = open(|| prf::Builder::new(Software::CPU_CLOCK), CLOCK_PERIOD)
+                    .map_err(|e| anyhow::anyhow!("cannot sample the hot functions: {e}"))?;
+                Ok(Self {
+                    samplers,
+                    metric: "cpu_clock",
+                    period: CLOCK_PERIOD,
+                })
+            }
+        }
+    }
+
+    /// Discards the samples of the processes spawned since the last run.
+    pub fn start(&mut self) {
+        for sampler in &mut self.samplers {
+            while sampler.next_record().is_some() {}
+        }
+    }
+
+    /// The samples since [`Profiler::start`] by function, as their estimated self metric.
+    pub fn read(&mut self) -> BTreeMap<String, Metrics> {
+        let mut mappings: HashMap<u32, Vec<Mapping>> = HashMap::new();
+        let mut parents: HashMap<u32, u32> = HashMap::new();
+        let mut hits: HashMap<(u32, u64), u64> = HashMap::new();
+        let mut lost = false;
+        // The buffers of the CPUs are not ordered with each other, so the samples are only
+        // attributed once all the mappings are known.
+        for sampler in &mut self.samplers {
+            while let Some(record) = sampler.next_record() {
+                match record.parse_record() {
+                    Ok(Record::Mmap(mmap)) => {
+                        mappings.entry(mmap.pid).or_default().push(Mapping {
+                            start: mmap.addr,
+                            end: mmap.addr + mmap.len,
+                            offset: mmap.pgoff,
+                            path: PathBuf::from(std::ffi::OsStr::from_bytes(&mmap.filename)),
+                        });
+                    }
+                    Ok(Record::Fork(fork)) if fork.pid != fork.ppid => {
+                        parents.insert(fork.pid, fork.ppid);
+                    }
+                    Ok(Record::Sample(sample)) => {
+                        if let (Some(pid), Some(ip)) = (sample.pid(), sample.ip()) {
+                            *hits.entry((pid, ip)).or_default() += 1;
+                        }
+                    }
+                    Ok(Record::Lost(_)) => lost = true,
+                    _ => {}
+                }
+            }
+        }
+        if lost {
+            tracing::warn!("some samples of the hot functions were lost, the buffer being full");
+        }
+        let mut elves = HashMap::new();
+        let mut functions: BTreeMap<String, Metrics> = BTreeMap::new();
+        for ((mut pid, ip), count) in hits {
+            // Forked processes share the mappings of their parent until they exec. Later
+            // mappings are those of the program a process executed last.
+            let mapping = loop {
+                let found = mappings
+                    .get(&pid)
+                    .and_then(|m| m.iter().rev().find(|m| (m.start..m.end).contains(&ip)));
+                match (found, parents.get(&pid)) {
+                    (Some(mapping), _) => break Some(mapping),
+                    (None, Some(&parent)) => pid = parent,
+                    (None, None) => break None,
+                }
+            };
+            let name = match mapping {
+                Some(m) => symbolize(&mut elves, &m.path, ip - m.start + m.offset),
+                None => "[unknown]".to_owned(),
+            };
+            *functions
+                .entry(name)
+                .or_default()
+                .entry(self.metric.to_owned())
+                .or_default() += (count * self.period) as f64;
+        }
+        functions
+    }
+}
+
+/// Sampling relies on perf events, only available on Linux.
+#[cfg(not(target_os = "linux"))]
+pub enum Profiler {}
+
+#[cfg(not(target_os = "linux"))]
+impl Profiler {
+    pub fn open() -> anyhow::Result<Self> {
+        anyhow::bail!("--hot-functions samples with perf events, only available on Linux")
+    }
+
+    pub fn start(&mut self) {
+        match *self {}
+    }
+
+    pub fn read(&mut self) -> BTreeMap<String, Metrics> {
+        match *self {}
+    }
+}
@@ -0,0 +1,117 @@
+//! HTTP requests to the services results are published to, through `curl`.
+
+use std::{
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{command, temp::TempDir};
+
+pub fn required_env(name: &str) -> anyhow::Result<String> {
+    std::env::var(name).map_err(|_| anyhow::anyhow!("{name} is not set"))
+}
+
+/// Quotes `value` for a curl configuration file.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Sends a request with `headers` and `body` to `url`, returning the response body.
+///
+/// The headers are passed to curl on its standard input rather than on its command line, where
+/// other users could read tokens, and the body in a file only the user can read.
+pub fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+) -> anyhow::Result<String> {
+    if command::resolve_program("curl").is_none() {
+        anyhow::bail!("cannot find curl, which sends the requests to {url}");
+    }
+    let mut config = format!(
+        "silent\nshow-error\nfail-with-body\nrequest = {}\nurl = {}\n",
+        quote(method),
+        quote(url)
+    );
+    for (name, value) in headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            quote(&format!("{name}: {value}"))
+        ));
+    }
+    // Removed on return, once curl read it.
+    let mut body_dir = None;
+    if let Some(body) = body {
+        let dir = body_dir.insert(TempDir::new("perf-bench-http")?);
+        let body_file = dir.path().join("body");
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&body_file)?
+            .write_all(body)?;
+        config.push_str(&format!(
+            "data-binary = {}\n",
+            quote(&format!("@{}", body_file.display()))
+        ));
+    }
+    let mut curl = Command::new("curl")
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    curl.stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(config.as_bytes())?;
+    let output = curl.wait_with_output()?;
+    let response = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        anyhow::bail!("{method} {url} failed ({}): {response}", output.status);
+    }
+    Ok(response)
+}
+
+/// Downloads `url` into the file `dest`.
+pub fn download(url: &str, dest: &Path) -> anyhow::Result<()> {
+    if command::resolve_program("curl").is_none() {
+        anyhow::bail!("cannot find curl, which downloads {url}");
+    }
+    let status = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--output",
+        ])
+        .arg(dest)
+        // Not positional, for a URL starting with a dash not to be taken for an option.
+        .arg("--url")
+        .arg(url)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("cannot download {url} ({status})");
+    }
+    Ok(())
+}
+
+/// Sends `body` as JSON, parsing the response as JSON.
+pub fn json_request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&serde_json::Value>,
+) -> anyhow::Result<serde_json::Value> {
+    let mut headers = headers.to_vec();
+    headers.push(("Content-Type", "application/json"));
+    let body = body.map(serde_json::to_vec).transpose()?;
+    let response = request(method, url, &headers, body.as_deref())?;
+    if response.trim().is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+    Ok(serde_json::from_str(&response)?)
+}
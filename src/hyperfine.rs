@@ -0,0 +1,114 @@
+//! Conversion from and to the JSON files of hyperfine's `--export-json`.
+
+use crate::{
+    metrics::{self, Metrics},
+    results::{Measure, Results, ResultsFile, Sample},
+};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Export {
+    results: Vec<Benchmark>,
+}
+
+/// A benchmarked command, with its times in seconds.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Benchmark {
+    command: String,
+    mean: f64,
+    stddev: Option<f64>,
+    /// Absent from the exports of old versions.
+    median: Option<f64>,
+    user: f64,
+    system: f64,
+    min: f64,
+    max: f64,
+    #[serde(default)]
+    times: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exit_codes: Vec<Option<i32>>,
+}
+
+const NANOS: f64 = 1e9;
+
+/// The results of a hyperfine export, keyed by command, with the wall time and the CPU time
+/// of the user and system.
+pub fn import(export: Export) -> ResultsFile {
+    let mut results = Results::new();
+    for benchmark in export.results {
+        let median = benchmark
+            .median
+            .unwrap_or_else(|| metrics::median_of(benchmark.times.clone()));
+        let metrics = Metrics::from([
+            ("wall_time".to_owned(), median * NANOS),
+            (
+                "cpu_time".to_owned(),
+                (benchmark.user + benchmark.system) * NANOS,
+            ),
+        ]);
+        let samples = if benchmark.times.len() > 1 {
+            benchmark
+                .times
+                .iter()
+                .map(|time| Sample {
+                    metrics: Metrics::from([("wall_time".to_owned(), time * NANOS)]),
+                    cpus: Vec::new(),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        results.insert(
+            benchmark.command.clone(),
+            Measure {
+                metrics,
+                command: Some(benchmark.command),
+                samples,
+                ..Measure::default()
+            },
+        );
+    }
+    ResultsFile {
+        metadata: None,
+        results,
+    }
+}
+
+/// The results in hyperfine's format, which requires their wall time.
+///
+/// The CPU time, if recorded, is reported as user time, since it isn't split.
+pub fn export(file: &ResultsFile) -> anyhow::Result<Export> {
+    let mut results = Vec::new();
+    for (key, measure) in &file.results {
+        let Some(&median) = measure.metrics.get("wall_time") else {
+            anyhow::bail!("{key} has no wall_time, which hyperfine results require");
+        };
+        let mut times: Vec<f64> = measure
+            .samples
+            .iter()
+            .filter_map(|sample| sample.metrics.get("wall_time"))
+            .map(|time| time / NANOS)
+            .collect();
+        if times.is_empty() {
+            times.push(median / NANOS);
+        }
+        let mean = times.iter().sum::<f64>() / times.len() as f64;
+        let stddev = (times.len() > 1).then(|| {
+            let variance =
+                times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (times.len() - 1) as f64;
+            variance.sqrt()
+        });
+        results.push(Benchmark {
+            command: measure.command.clone().unwrap_or_else(|| key.clone()),
+            mean,
+            stddev,
+            median: Some(median / NANOS),
+            user: measure.metrics.get("cpu_time").map_or(0., |t| t / NANOS),
+            system: 0.,
+            min: times.iter().copied().fold(f64::INFINITY, f64::min),
+            max: times.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            exit_codes: vec![Some(0); times.len()],
+            times,
+        });
+    }
+    Ok(Export { results })
+}
@@ -0,0 +1,113 @@
+//! InfluxDB line protocol output of results.
+
+use crate::{
+    compare, metadata,
+    results::{Results, ResultsFile},
+};
+
+/// Measurement the results are written to.
+const MEASUREMENT: &str = "perf_bench";
+
+/// Escapes a tag key, tag value or field key.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(match c {
+            '\n' => ' ',
+            c => c,
+        });
+    }
+    escaped
+}
+
+/// One line per benchmark of `file`, tagged with the benchmark, its command and the host,
+/// git revision and tags of the recording, with the metrics as fields and the recording date
+/// as timestamp.
+pub fn results(file: &ResultsFile) -> String {
+    let mut common = Vec::new();
+    let mut timestamp = None;
+    if let Some(metadata) = &file.metadata {
+        common.extend(metadata.tags.iter().map(|(k, v)| (k.clone(), v.clone())));
+        common.extend(
+            metadata
+                .hostname
+                .clone()
+                .map(|host| ("host".to_owned(), host)),
+        );
+        if let Some(git) = &metadata.git {
+            common.push(("commit".to_owned(), git.commit.clone()));
+            common.extend(
+                git.branch
+                    .clone()
+                    .map(|branch| ("branch".to_owned(), branch)),
+            );
+        }
+        timestamp = metadata::parse_utc(&metadata.date).map(|secs| secs * 1_000_000_000);
+    }
+    let mut text = String::new();
+    for (key, measure) in &file.results {
+        if measure.metrics.is_empty() {
+            continue;
+        }
+        let mut tags = common.clone();
+        tags.push(("benchmark".to_owned(), key.clone()));
+        tags.extend(
+            measure
+                .command
+                .clone()
+                .map(|command| ("command".to_owned(), command)),
+        );
+        // Tags are sorted by key, as recommended for the performance of InfluxDB.
+        tags.sort();
+        let mut line = MEASUREMENT.to_owned();
+        for (name, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+            line.push_str(&format!(",{}={}", escape(name), escape(value)));
+        }
+        let fields: Vec<String> = measure
+            .metrics
+            .iter()
+            .map(|(name, value)| format!("{}={value}", escape(name)))
+            .collect();
+        line.push(' ');
+        line.push_str(&fields.join(","));
+        if let Some(timestamp) = timestamp {
+            line.push_str(&format!(" {timestamp}"));
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+/// The relative differences in percent of the comparison, with a line per benchmark.
+pub fn comparison(base: &Results, compared: &Results) -> String {
+    let mut text = String::new();
+    for (key, base_measure) in base {
+        let Some(compared_measure) = compared.get(key) else {
+            continue;
+        };
+        let fields: Vec<String> = base_measure
+            .metrics
+            .iter()
+            .filter_map(|(name, &base)| {
+                let compared = *compared_measure.metrics.get(name)?;
+                Some(format!(
+                    "{}={}",
+                    escape(name),
+                    compare::rel_diff(base, compared)
+                ))
+            })
+            .collect();
+        if !fields.is_empty() {
+            text.push_str(&format!(
+                "{MEASUREMENT}_change,benchmark={} {}\n",
+                escape(key),
+                fields.join(",")
+            ));
+        }
+    }
+    text
+}
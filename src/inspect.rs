@@ -0,0 +1,232 @@
+//! Human-readable summaries of a results file.
+
+use std::path::Path;
+
+use crate::{
+    cores,
+    metrics::{self, DisplayOptions},
+    results::{self, Measure, Results, ResultsFile},
+};
+
+/// Prints the metadata of `file`, its metrics and their distribution over the benchmarks.
+pub fn print_summary(path: &Path, file: &ResultsFile, display: DisplayOptions) {
+    println!("File: {}", path.display());
+    match &file.metadata {
+        Some(metadata) => {
+            println!("Recorded: {}", metadata.summary());
+            let fields = [
+                ("CPU model", metadata.cpu_model.clone()),
+                ("Cores", metadata.cores.map(|n| n.to_string())),
+                ("Kernel", metadata.kernel.clone()),
+                ("Governor", metadata.governor.clone()),
+                ("ASLR", metadata.aslr.clone()),
+                ("NUMA nodes", metadata.numa.clone()),
+                ("NUMA node", metadata.numa_node.map(|n| n.to_string())),
+                (
+                    "Nominal frequency",
+                    metadata.nominal_mhz.map(|mhz| format!("{mhz:.0} MHz")),
+                ),
+                ("Efficiency CPUs", metadata.efficiency_cpus.clone()),
+                ("Core type", metadata.core_type.clone()),
+                (
+                    "Git revision",
+                    metadata.git.as_ref().map(|git| git.to_string()),
+                ),
+            ];
+            for (name, value) in fields {
+                println!("{name}: {}", value.as_deref().unwrap_or("unknown"));
+            }
+            println!("Orchestrator version: {}", metadata.version);
+        }
+        None => println!("Recorded: no metadata"),
+    }
+    let names = results::metric_names([&file.results]);
+    println!("Benchmarks: {}", file.results.len());
+    println!("Metrics: {}", names.join(", "));
+    let noisy = file.results.values().filter(|m| m.is_noisy()).count();
+    if noisy > 0 {
+        println!("Busy-machine measurements: {noisy}");
+    }
+    let degraded = file.results.values().filter(|m| m.is_degraded()).count();
+    if degraded > 0 {
+        println!("Degraded measurements, missing unavailable counters: {degraded}");
+    }
+    let failed = file
+        .results
+        .values()
+        .filter(|m| m.limit_hit.is_some())
+        .count();
+    if failed > 0 {
+        println!("Runs that hit a resource limit: {failed}");
+    }
+    if names.is_empty() {
+        return;
+    }
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row!["Metric", "Min", "Median", "Mean", "Max"]);
+    for name in &names {
+        let values: Vec<f64> = file
+            .results
+            .values()
+            .filter_map(|m| m.metrics.get(name).copied())
+            .collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let show = |value: f64| metrics::display(name, value, display);
+        table.add_row(prettytable::row![
+            metrics::header(name, display),
+            show(min),
+            show(metrics::median_of(values)),
+            show(mean),
+            show(max),
+        ]);
+    }
+    table.printstd();
+}
+
+/// `cpus` as a list, with the share of efficiency ones among them if any.
+fn show_cpus(cpus: &[usize], efficiency: &[usize]) -> String {
+    let list = cores::format_list(cpus);
+    match cpus.iter().filter(|cpu| efficiency.contains(cpu)).count() {
+        0 => list,
+        n if n == cpus.len() => format!("{list} (E-cores)"),
+        n => format!("{list} ({n} E-core(s))"),
+    }
+}
+
+/// Prints everything recorded about the benchmark `key`, whose CPUs are marked with the
+/// `efficiency` ones of the machine it ran on.
+pub fn print_measure(key: &str, measure: &Measure, efficiency: &[usize], display: DisplayOptions) {
+    println!("Key: {key}");
+    if let Some(note) = &measure.note {
+        println!("Note: {note}");
+    }
+    if !measure.status.is_ok() {
+        println!("Status: {}", measure.status);
+    }
+    if measure.skipped {
+        let reason = measure.skip_reason.as_deref().unwrap_or("no reason given");
+        println!("Skipped: {reason}");
+    }
+    if let Some(original) = &measure.duplicate_of {
+        println!("Duplicate of: {original}");
+    }
+    if let Some(command) = &measure.command {
+        println!("Command: {command}");
+    }
+    if let Some(binary) = &measure.binary {
+        println!("Binary: {}", binary.display());
+    }
+    if let Some(hash) = &measure.binary_sha256 {
+        println!("Binary SHA-256: {hash}");
+    }
+    println!("Load avg: {:.2}", measure.load_avg);
+    println!("Interference: {:.1}%", measure.interference * 100.);
+    if let Some(frequency) = &measure.frequency {
+        println!(
+            "CPU frequency: {:.0} MHz (min {:.0}, max {:.0})",
+            frequency.mean, frequency.min, frequency.max
+        );
+    }
+    if !measure.cpus.is_empty() {
+        println!("CPUs: {}", show_cpus(&measure.cpus, efficiency));
+    }
+    if let Some(limit) = &measure.limit_hit {
+        println!("Limit hit: {limit}");
+    }
+    if measure.is_degraded() {
+        println!("Unavailable metrics: {}", measure.unavailable.join(", "));
+    }
+    let mut table = prettytable::Table::new();
+    let mut header = vec![
+        prettytable::Cell::new("Metric"),
+        prettytable::Cell::new("Median"),
+    ];
+    header.extend((1..=measure.samples.len()).map(|i| prettytable::Cell::new(&format!("Run {i}"))));
+    table.add_row(prettytable::Row::new(header));
+    let mut names: Vec<String> = measure.metrics.keys().cloned().collect();
+    results::sort_metric_names(&mut names);
+    for name in &names {
+        let value = measure.metrics[name];
+        let mut row = vec![
+            prettytable::Cell::new(&metrics::header(name, display)),
+            prettytable::Cell::new(&metrics::display(name, value, display)),
+        ];
+        row.extend(
+            measure
+                .samples
+                .iter()
+                .map(|sample| match sample.metrics.get(name) {
+                    Some(&value) => prettytable::Cell::new(&metrics::display(name, value, display)),
+                    None => prettytable::Cell::new("-"),
+                }),
+        );
+        table.add_row(prettytable::Row::new(row));
+    }
+    if measure.samples.iter().any(|sample| !sample.cpus.is_empty()) {
+        let mut row = vec![prettytable::Cell::new("CPUs"), prettytable::Cell::new("")];
+        row.extend(
+            measure
+                .samples
+                .iter()
+                .map(|sample| prettytable::Cell::new(&show_cpus(&sample.cpus, efficiency))),
+        );
+        table.add_row(prettytable::Row::new(row));
+    }
+    table.printstd();
+}
+
+/// Prints the `n` benchmarks with the highest `metric`, with their share of its total over all
+/// the benchmarks, and the others summed into a row.
+pub fn print_top(res: &Results, metric: &str, n: usize, display: DisplayOptions) {
+    let mut values: Vec<(&String, f64)> = res
+        .iter()
+        .filter_map(|(key, measure)| Some((key, *measure.metrics.get(metric)?)))
+        .collect();
+    if values.is_empty() {
+        tracing::warn!("no benchmark has {metric}");
+        return;
+    }
+    values.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    let total: f64 = values.iter().map(|(_, value)| value).sum();
+    let share = |value: f64| {
+        if total == 0. {
+            "-".to_owned()
+        } else {
+            format!("{:.1}%", value / total * 100.)
+        }
+    };
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row![
+        "Rank",
+        "File",
+        metrics::header(metric, display),
+        "Share"
+    ]);
+    let rest = values.split_off(n.min(values.len()));
+    for (rank, (key, value)) in values.into_iter().enumerate() {
+        table.add_row(prettytable::row![
+            rank + 1,
+            key,
+            metrics::display(metric, value, display),
+            share(value),
+        ]);
+    }
+    if !rest.is_empty() {
+        let others: f64 = rest.iter().map(|(_, value)| value).sum();
+        table.add_row(prettytable::row![
+            "",
+            format!("({} others)", rest.len()),
+            metrics::display(metric, others, display),
+            share(others),
+        ]);
+    }
+    table.add_row(prettytable::row![
+        "",
+        "Total",
+        metrics::display(metric, total, display),
+        share(total),
+    ]);
+    table.printstd();
+}
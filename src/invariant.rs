@@ -0,0 +1,67 @@
+//! Frequency-invariant cycles, to compare results of machines of different frequencies: the
+//! reference cycles count at the nominal frequency of each machine, so scaling them to a common
+//! reference frequency makes them count the same unhalted time on either.
+
+use crate::{metadata::Metadata, metrics::Metrics, results::ResultsFile};
+
+/// Metric of the reference cycles scaled to the reference frequency.
+pub const METRIC: &str = "normalized_cycles";
+
+/// The nominal frequency of the CPU in MHz, from cpufreq or else from the model name, as in
+/// `Intel(R) Core(TM) i7-8650U CPU @ 1.90GHz`.
+pub fn nominal_mhz() -> Option<f64> {
+    if let Ok(khz) = std::fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/base_frequency")
+    {
+        if let Ok(khz) = khz.trim().parse::<f64>() {
+            return Some(khz / 1000.);
+        }
+    }
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    let model = cpuinfo.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == "model name").then_some(value)
+    })?;
+    let (_, frequency) = model.rsplit_once('@')?;
+    let ghz: f64 = frequency.trim().strip_suffix("GHz")?.parse().ok()?;
+    Some(ghz * 1000.)
+}
+
+/// Adds to the measures of `file` the [`METRIC`] of their `ref_cycles` counted at the nominal
+/// frequency of the machine they ran on, scaled to `reference_mhz`.
+pub fn add_normalized_cycles(
+    file: &mut ResultsFile,
+    name: &str,
+    reference_mhz: f64,
+) -> anyhow::Result<()> {
+    let metadata = file.metadata.as_ref();
+    let nominal = |metadata: Option<&Metadata>| metadata.and_then(|m| m.nominal_mhz);
+    let mut missing = 0;
+    for measure in file.results.values_mut() {
+        let host = measure
+            .host
+            .as_ref()
+            .and_then(|host| metadata?.hosts.get(host));
+        let Some(nominal) = nominal(host).or(nominal(metadata)) else {
+            anyhow::bail!("{name} has no nominal frequency recorded to normalize the cycles with");
+        };
+        let scale = |metrics: &mut Metrics| {
+            if let Some(&ref_cycles) = metrics.get("ref_cycles") {
+                metrics.insert(METRIC.to_owned(), ref_cycles * reference_mhz / nominal);
+            }
+        };
+        if !measure.skipped && !measure.metrics.contains_key("ref_cycles") {
+            missing += 1;
+        }
+        scale(&mut measure.metrics);
+        for sample in &mut measure.samples {
+            scale(&mut sample.metrics);
+        }
+    }
+    if missing > 0 {
+        tracing::warn!(
+            "{missing} benchmark(s) of {name} have no ref_cycles to normalize to {reference_mhz} \
+             MHz"
+        );
+    }
+    Ok(())
+}
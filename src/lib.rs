@@ -0,0 +1,443 @@
+//! Orchestration of performance benchmarks: recording hardware counters and other metrics
+//! of commands run on input files, and comparing the results.
+//!
+//! [`record::measure`] runs benchmarks into [`Measure`]s, gathered with the metadata of the
+//! machine in a [`RunSet`], and a [`Comparator`] reports the changes between two of them.
+
+use std::path::PathBuf;
+
+pub mod backend;
+pub mod bisect;
+mod breakpoint;
+pub mod cache;
+pub mod changepoint;
+pub mod child;
+pub mod collector;
+pub mod command;
+pub mod compare;
+pub mod config;
+#[cfg(target_os = "linux")]
+mod container;
+pub mod cores;
+pub mod corpus;
+pub mod corpus_stats;
+pub mod dashboard;
+pub mod distribution;
+mod ebpf;
+#[cfg(target_os = "linux")]
+mod elf;
+pub mod github;
+pub mod gitlab;
+pub mod group;
+mod hash;
+pub mod history;
+mod hot_functions;
+mod http;
+pub mod hyperfine;
+pub mod influx;
+pub mod inspect;
+pub mod invariant;
+pub mod manifest;
+pub mod metadata;
+pub mod metrics;
+mod monitor;
+pub mod notify;
+mod numa;
+pub mod openmetrics;
+pub mod parquet;
+mod perf_stat;
+pub mod pivot;
+mod qemu;
+pub mod query;
+pub mod record;
+pub mod remote;
+pub mod results;
+pub mod revs;
+mod roi;
+pub mod samples;
+pub mod sampling;
+pub mod schema;
+pub mod selftest;
+pub mod skip;
+mod smt;
+mod tasks;
+mod temp;
+pub mod trend;
+pub mod upload;
+mod uprobe;
+mod valgrind;
+pub mod validate;
+mod wasm;
+pub mod watch;
+
+pub use compare::Comparator;
+pub use metadata::{Build, Git, Metadata};
+/// The results of a recording: the measures of each benchmark, with the machine they were
+/// measured on.
+pub use results::ResultsFile as RunSet;
+pub use results::{Measure, Results, Sample, SCHEMA_VERSION};
+
+use record::KeyFormat;
+
+/// Format of the results and comparisons printed on the standard output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Table,
+    Json,
+    /// OpenMetrics text exposition, with a gauge per metric labeled by benchmark
+    Openmetrics,
+    /// InfluxDB line protocol, with a line per benchmark
+    Influx,
+    /// Parquet table with a row per benchmark, iteration and metric, of results only
+    Parquet,
+}
+
+/// How benchmarks are measured.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct RunOptions {
+    /// Comma-separated metrics to record [default: ref_cycles,instructions,cpu_time with the
+    /// perf backends, wall_time,cpu_time,max_rss with the portable one, the main counts of the
+    /// others]
+    #[arg(long, value_delimiter = ',')]
+    pub metrics: Vec<String>,
+    /// How to measure the benchmarks [default: perf on Linux, portable elsewhere]
+    #[arg(long)]
+    pub backend: Option<backend::Kind>,
+    /// QEMU user-mode emulator running the benchmarks with the qemu backend, e.g.
+    /// qemu-aarch64
+    #[arg(long)]
+    pub qemu: Option<String>,
+    /// Path of the QEMU instruction-counting plugin (libinsn.so) for the qemu backend
+    #[arg(long)]
+    pub qemu_plugin: Option<PathBuf>,
+    /// Number of runs of each benchmark, whose median is reported [default: 1]
+    #[arg(long)]
+    pub iterations: Option<u32>,
+    /// Number of unmeasured runs of each benchmark before the measured ones [default: 0]
+    #[arg(long)]
+    pub warmup: Option<u32>,
+    /// Comma-separated CPUs to pin the benchmarked process to
+    #[arg(long, value_delimiter = ',')]
+    pub pin: Vec<usize>,
+    /// Pin the benchmarked process to the performance or the efficiency cores of a hybrid CPU
+    #[arg(long, conflicts_with = "pin")]
+    pub core_type: Option<cores::CoreType>,
+    /// Bind the benchmarked process to the CPUs and the memory of this NUMA node, or only its
+    /// memory with --pin or --core-type
+    #[arg(long, value_name = "NODE")]
+    pub numa_node: Option<usize>,
+    /// Take the SMT siblings of the CPUs the benchmarks are pinned to offline while they run,
+    /// which needs root privileges, or else mark the measures as possibly perturbed by them
+    #[arg(long, overrides_with = "no_exclusive_core")]
+    pub exclusive_core: bool,
+    /// Undo --exclusive-core, e.g. set in the configuration
+    #[arg(long, overrides_with = "exclusive_core")]
+    pub no_exclusive_core: bool,
+    /// Preset of iterations, warmup, pinning and metrics: quick, thorough, ci or one defined
+    /// in the configuration
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Drop the page cache (or, without the privileges to do so, copy each input to a fresh
+    /// uncached path) before every measured run
+    #[arg(long, overrides_with = "no_cold")]
+    pub cold: bool,
+    /// Undo --cold, e.g. set in the configuration
+    #[arg(long, overrides_with = "cold")]
+    pub no_cold: bool,
+    /// Command compiling each input before the benchmarked command runs, measured as a
+    /// separate phase: the metrics of the phases are recorded as compile.METRIC and
+    /// run.METRIC, and {output} in the command is replaced with the path of a file the
+    /// benchmarked command then runs on instead of the input
+    #[arg(long)]
+    pub compile_cmd: Option<String>,
+    /// Compile the .wat inputs to binary .wasm modules before measuring, and pass the modules
+    /// to the command instead
+    #[arg(long, overrides_with = "no_precompile")]
+    pub precompile: bool,
+    /// Undo --precompile, e.g. set in the configuration
+    #[arg(long, overrides_with = "precompile")]
+    pub no_precompile: bool,
+    /// Run the command through `sh -c`, instead of splitting it into words with shell quoting
+    /// rules and running it directly
+    #[arg(long, overrides_with = "no_shell")]
+    pub shell: bool,
+    /// Undo --shell, e.g. set in the configuration
+    #[arg(long, overrides_with = "shell")]
+    pub no_shell: bool,
+    /// Cap the virtual address space of the benchmarked process (RLIMIT_AS), in bytes with an
+    /// optional K, M or G suffix. It counts all the mappings, not only the resident memory,
+    /// and reaching it makes allocations fail rather than kill the process
+    #[arg(long, value_parser = child::parse_size)]
+    pub memory_limit: Option<u64>,
+    /// Apply a resource limit RESOURCE=VALUE to the benchmarked process, where RESOURCE is one
+    /// of as, core, cpu, data, fsize, memlock, nofile, nproc or stack
+    #[arg(long, value_name = "RESOURCE=VALUE")]
+    pub rlimit: Vec<child::Rlimit>,
+    /// Kill the benchmarked process once it has run for this long in wall-clock time, e.g.
+    /// 30s, recording it as timed out
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration)]
+    pub timeout: Option<std::time::Duration>,
+    /// Record the number a shell command prints after each run as the metric NAME; the
+    /// command gets the PID of the benchmark process and the path of the file its standard
+    /// output was redirected to as arguments
+    #[arg(long, value_name = "NAME=COMMAND")]
+    pub extra_metric: Vec<collector::ExtraMetric>,
+    /// Record the hits of a hardware breakpoint as the metric NAME: KIND is exec for the code
+    /// at ADDRESS, or read, write or rw for the LENGTH bytes there (1, 2, 4 or 8, by default
+    /// 8); addresses are stable across runs when the program is not position-independent or
+    /// ASLR is disabled
+    #[arg(long, value_name = "NAME=KIND:ADDRESS[/LENGTH]")]
+    pub breakpoint: Vec<breakpoint::Breakpoint>,
+    /// Record the calls of the function SYMBOL of BINARY as the metric SYMBOL_calls, counted
+    /// by a uprobe on its entry in the main thread of the benchmark process, not its other
+    /// threads or children; BINARY is looked up in the PATH unless it has a `/`, and must have
+    /// its symbol table
+    #[arg(long, value_name = "BINARY:SYMBOL")]
+    pub count_calls: Vec<uprobe::CallCount>,
+    /// Record the metrics the benchmarked program prints on its standard output, which is
+    /// then captured rather than shown, failing if it prints one of the recorded metrics
+    #[arg(long, value_name = "FORMAT")]
+    pub parse_output: Option<collector::OutputFormat>,
+    /// Also break the cycles of each benchmark down by thread, or its CPU clock without
+    /// hardware counters
+    #[arg(long, overrides_with = "no_per_thread")]
+    pub per_thread: bool,
+    /// Undo --per-thread, e.g. set in the configuration
+    #[arg(long, overrides_with = "per_thread")]
+    pub no_per_thread: bool,
+    /// Also break the cycles of each benchmark down by process of its process tree, or its
+    /// CPU clock without hardware counters
+    #[arg(long, overrides_with = "no_per_process")]
+    pub per_process: bool,
+    /// Undo --per-process, e.g. set in the configuration
+    #[arg(long, overrides_with = "per_process")]
+    pub no_per_process: bool,
+    /// Also sample where each benchmark spends its cycles, or its CPU clock without hardware
+    /// counters, and record the self share of each function, named after the symbol tables
+    /// of the binaries, for `compare --explain`
+    #[arg(long, overrides_with = "no_hot_functions")]
+    pub hot_functions: bool,
+    /// Undo --hot-functions, e.g. set in the configuration
+    #[arg(long, overrides_with = "hot_functions")]
+    pub no_hot_functions: bool,
+    /// Count only the regions the benchmarks enable and disable by writing `enable` and
+    /// `disable` lines to the FIFO at the path of their PERF_BENCH_CONTROL environment variable,
+    /// as with `perf stat --control`, leaving their setup out; max_rss and the I/O metrics
+    /// still cover the whole run
+    #[arg(long, overrides_with = "no_roi")]
+    pub roi: bool,
+    /// Undo --roi, e.g. set in the configuration
+    #[arg(long, overrides_with = "roi")]
+    pub no_roi: bool,
+    /// Count only from when the benchmarks have run for this long, e.g. 500ms or 2s, leaving
+    /// out the warm-up of JIT-compiled runtimes; wall_time then covers the rest of the run
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration, conflicts_with = "roi")]
+    pub skip_start: Option<std::time::Duration>,
+    /// Run each benchmark in a container of this image with podman or docker, restricted to
+    /// the CPUs of --pin and measured by counting the events of its cgroup; the working
+    /// directory and the directories of the arguments are mounted at the same paths
+    #[arg(long, value_name = "IMAGE")]
+    pub container: Option<String>,
+    /// Container runtime running --container [default: podman if installed, else docker]
+    #[arg(long)]
+    pub container_runtime: Option<String>,
+    /// Reuse cached measurements of identical (command, binary, input, options) runs
+    #[arg(long, overrides_with = "no_cache")]
+    pub cache: bool,
+    /// Undo --cache, e.g. set in the configuration
+    #[arg(long, overrides_with = "cache")]
+    pub no_cache: bool,
+    /// Directory of the measurement cache [default: $XDG_CACHE_HOME/perf-bench-orchestrator]
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+    /// Measure the benchmarks whose command, input content and repetitions are identical to
+    /// those of an earlier one only once, giving them its results
+    #[arg(long, overrides_with = "no_dedup")]
+    pub dedup: bool,
+    /// Undo --dedup, e.g. set in the configuration
+    #[arg(long, overrides_with = "dedup")]
+    pub no_dedup: bool,
+    /// Start no further benchmark once the recording has run for this long, e.g. 30m,
+    /// marking the rest as skipped in the results, which `record --update` then measures
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration)]
+    pub max_total_time: Option<std::time::Duration>,
+    /// Template of the result keys, with placeholders {file}, {file.name}, {file.stem},
+    /// {file.ext} and {file.dir} [default: {file}]
+    #[arg(long)]
+    pub key_template: Option<String>,
+    /// Directory to make the paths in result keys relative to, so that results recorded from
+    /// different working directories share their keys
+    #[arg(long)]
+    pub strip_prefix: Option<PathBuf>,
+}
+
+/// A flag given as `--x` or `--no-x` on the command line, else as set in the configuration.
+fn flag(set: bool, unset: bool, config: Option<bool>) -> bool {
+    match (set, unset) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => config.unwrap_or(false),
+    }
+}
+
+impl RunOptions {
+    /// Fills the options not given on the command line from `config`.
+    pub fn apply_config(&mut self, config: &config::Config) -> anyhow::Result<()> {
+        let profile = match self.profile.as_ref().or(config.profile.as_ref()) {
+            Some(name) => config.profile(name)?,
+            None => config::Profile::default(),
+        };
+        if self.metrics.is_empty() {
+            self.metrics = profile
+                .metrics
+                .or(config.metrics.clone())
+                .unwrap_or_default();
+        }
+        if self.pin.is_empty() && self.core_type.is_none() {
+            self.pin = profile.pin.or(config.pin.clone()).unwrap_or_default();
+        }
+        if self.pin.is_empty() {
+            self.core_type = self.core_type.or(config.core_type);
+        }
+        self.numa_node = self.numa_node.or(config.numa_node);
+        self.exclusive_core = flag(
+            self.exclusive_core,
+            self.no_exclusive_core,
+            config.exclusive_core,
+        );
+        self.iterations = self.iterations.or(profile.iterations).or(config.iterations);
+        self.warmup = self.warmup.or(profile.warmup).or(config.warmup);
+        self.backend = self.backend.or(config.backend);
+        self.qemu = self.qemu.take().or(config.qemu.clone());
+        self.qemu_plugin = self.qemu_plugin.take().or(config.qemu_plugin.clone());
+        self.cold = flag(self.cold, self.no_cold, config.cold);
+        self.compile_cmd = self.compile_cmd.take().or(config.compile_cmd.clone());
+        self.precompile = flag(self.precompile, self.no_precompile, config.precompile);
+        self.shell = flag(self.shell, self.no_shell, config.shell);
+        self.memory_limit = self.memory_limit.or(config.memory_limit.map(|size| size.0));
+        if self.rlimit.is_empty() {
+            for rlimit in config.rlimit.iter().flatten() {
+                self.rlimit
+                    .push(rlimit.parse().map_err(|e| anyhow::anyhow!("{e}"))?);
+            }
+        }
+        if let (None, Some(timeout)) = (self.timeout, &config.timeout) {
+            self.timeout =
+                Some(child::parse_duration(timeout).map_err(|e| anyhow::anyhow!("{e}"))?);
+        }
+        if self.extra_metric.is_empty() {
+            for metric in config.extra_metric.iter().flatten() {
+                self.extra_metric
+                    .push(metric.parse().map_err(|e| anyhow::anyhow!("{e}"))?);
+            }
+        }
+        if self.breakpoint.is_empty() {
+            for breakpoint in config.breakpoint.iter().flatten() {
+                self.breakpoint
+                    .push(breakpoint.parse().map_err(|e| anyhow::anyhow!("{e}"))?);
+            }
+        }
+        if self.count_calls.is_empty() {
+            for call in config.count_calls.iter().flatten() {
+                self.count_calls
+                    .push(call.parse().map_err(|e| anyhow::anyhow!("{e}"))?);
+            }
+        }
+        self.parse_output = self.parse_output.or(config.parse_output);
+        self.per_thread = flag(self.per_thread, self.no_per_thread, config.per_thread);
+        self.per_process = flag(self.per_process, self.no_per_process, config.per_process);
+        self.hot_functions = flag(
+            self.hot_functions,
+            self.no_hot_functions,
+            config.hot_functions,
+        );
+        self.roi = flag(self.roi, self.no_roi, config.roi);
+        if let (None, Some(skip_start)) = (self.skip_start, &config.skip_start) {
+            self.skip_start =
+                Some(child::parse_duration(skip_start).map_err(|e| anyhow::anyhow!("{e}"))?);
+        }
+        self.container = self.container.take().or(config.container.clone());
+        self.container_runtime = self
+            .container_runtime
+            .take()
+            .or(config.container_runtime.clone());
+        self.cache = flag(self.cache, self.no_cache, config.cache);
+        self.cache_dir = self.cache_dir.take().or(config.cache_dir.clone());
+        self.dedup = flag(self.dedup, self.no_dedup, config.dedup);
+        if let (None, Some(max_total_time)) = (self.max_total_time, &config.max_total_time) {
+            self.max_total_time =
+                Some(child::parse_duration(max_total_time).map_err(|e| anyhow::anyhow!("{e}"))?);
+        }
+        self.key_template = self.key_template.take().or(config.key_template.clone());
+        self.strip_prefix = self.strip_prefix.take().or(config.strip_prefix.clone());
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> Vec<String> {
+        if self.metrics.is_empty() {
+            self.backend()
+                .default_metrics()
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        } else {
+            self.metrics.clone()
+        }
+    }
+
+    pub fn backend(&self) -> backend::Kind {
+        self.backend.unwrap_or_else(backend::Kind::platform_default)
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations.unwrap_or(1).max(1)
+    }
+
+    pub fn key_format(&self) -> KeyFormat<'_> {
+        KeyFormat {
+            template: self.key_template.as_deref().unwrap_or("{file}"),
+            strip_prefix: self.strip_prefix.as_deref(),
+        }
+    }
+
+    pub fn rlimits(&self) -> Vec<child::Rlimit> {
+        let mut rlimits = self.rlimit.clone();
+        rlimits.extend(self.memory_limit.map(|value| child::Rlimit {
+            resource: child::Resource::As,
+            value,
+        }));
+        rlimits
+    }
+
+    /// Everything in the options that may influence a measurement, for cache keys.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "{:?}",
+            RunOptions {
+                metrics: self.metrics(),
+                backend: Some(self.backend()),
+                iterations: Some(self.iterations()),
+                warmup: None,
+                profile: None,
+                cache: false,
+                cache_dir: None,
+                dedup: false,
+                max_total_time: None,
+                key_template: None,
+                strip_prefix: None,
+                no_exclusive_core: false,
+                no_cold: false,
+                no_precompile: false,
+                no_shell: false,
+                no_per_thread: false,
+                no_per_process: false,
+                no_hot_functions: false,
+                no_roi: false,
+                no_cache: false,
+                no_dedup: false,
+                ..self.clone()
+            }
+        )
+    }
+}
@@ -1,155 +1,1741 @@
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-};
+use std::{io::IsTerminal, path::PathBuf};
 
 use clap::Parser;
-use perf_event as prf;
-use prf::{events::Hardware, CountAndTime};
+
+mod completions;
+
+use perf_bench_orchestrator::{
+    bisect, cache, changepoint, command, compare,
+    compare::DiffFormat,
+    config, cores, corpus, corpus_stats, dashboard, distribution, github, gitlab,
+    group::GroupBy,
+    history, hyperfine, influx, inspect, invariant, manifest, metadata, metrics, notify,
+    openmetrics, parquet, pivot, query, record,
+    record::{check_unique_keys, file_benchmarks, Benchmark},
+    remote, results, revs, samples,
+    sampling::{self, StratifyBy},
+    schema, selftest, skip, trend, upload, validate, watch, Comparator, Format, RunOptions,
+};
 
 #[derive(clap::Subcommand, Debug)]
 enum Command {
-    Record(RecordCliOptions),
+    Record(Box<RecordCliOptions>),
     Compare(CompareCliOptions),
+    /// Re-record whenever the benchmarked binary or inputs change, comparing against the
+    /// previous results
+    Watch(WatchCliOptions),
+    /// Print a completion script for a shell
+    Completions(CompletionsCliOptions),
+    /// Summarize a results file, or show one of its entries in detail
+    Inspect(InspectCliOptions),
+    /// Print the benchmarks with the highest value of a metric, with their share of its total
+    Top(TopCliOptions),
+    /// Check that a results file is well-formed and safe to use as a baseline
+    Validate(ValidateCliOptions),
+    /// Check two git revisions out, build and benchmark each of them, and compare the results
+    CompareRevs(CompareRevsCliOptions),
+    /// Find the commit that introduced a performance regression with `git bisect`, building
+    /// and benchmarking each candidate
+    Bisect(BisectCliOptions),
+    /// Upload a results file to a continuous-benchmarking service
+    Upload(UploadCliOptions),
+    /// Download the inputs of a corpus manifest and verify their checksums, printing their
+    /// paths
+    Fetch(FetchCliOptions),
+    /// Summarize the inputs of a corpus: their sizes, the sections of the WebAssembly
+    /// modules, and how long recording them took in prior results
+    CorpusStats(CorpusStatsCliOptions),
+    /// Convert the results file of another tool into a results file
+    Import(ImportCliOptions),
+    /// Convert a results file into the format of another tool
+    Export(ExportCliOptions),
+    /// Write the metrics of every iteration of a results file as a CSV table with a row per
+    /// file, iteration and metric
+    ExportSamples(ExportSamplesCliOptions),
+    /// Store results files in the history of runs, or list it
+    History(HistoryCliOptions),
+    /// Plot a metric of a benchmark over the runs of the history
+    Trend(TrendCliOptions),
+    /// Report the runs of the history at which a metric stepped to a new level
+    Changepoints(ChangepointsCliOptions),
+    /// Print the measurements of the history, or of results files, that pass a filter
+    Query(QueryCliOptions),
+    /// Pivot results files recording different commands over the same corpus into a table
+    /// per metric of the ratio of each command to a reference one
+    Pivot(PivotCliOptions),
+    /// Print the JSON Schema of results files
+    Schema,
+    /// Serve a dashboard of the history on a local web server
+    Serve(ServeCliOptions),
+    /// Measure a process doing nothing and a fixed workload, showing the floor of the counts
+    /// and the variation the measurement itself adds
+    Selftest(SelftestCliOptions),
+}
+
+/// Formats of the results of other benchmarking tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ForeignFormat {
+    /// JSON written by hyperfine --export-json
+    Hyperfine,
 }
 
 #[derive(Debug, clap::Args)]
 struct RecordCliOptions {
+    /// COMMAND OUTPUT_FILE WAT_FILES..., where COMMAND is left out when using --manifest,
+    /// passing it after -- or setting it in the configuration, and OUTPUT_FILE when using
+    /// --output
+    #[arg(value_name = "ARGS")]
+    args: Vec<String>,
+    /// Command to run, as separate words rather than a single string
+    #[arg(last = true, value_name = "COMMAND")]
+    trailing_command: Vec<String>,
+    /// File to write the results to
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// JSON manifest of benchmarks to run, which may be commands without input file
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// JSON object of free-text notes by benchmark key, e.g. {"dir/f.wat": "known flaky"},
+    /// shown with their results
+    #[arg(long)]
+    notes: Option<PathBuf>,
+    /// Only record this share of the benchmarks, e.g. 10%, selected by a hash of their keys
+    /// and --seed for quick runs
+    #[arg(long, value_name = "SHARE", value_parser = sampling::parse_fraction)]
+    sample: Option<f64>,
+    /// Sample this share of each directory or input size rather than of the whole corpus
+    #[arg(long, requires = "sample")]
+    stratify_by: Option<StratifyBy>,
+    /// Seed of the sample, recorded in the metadata to select the same benchmarks again
+    #[arg(long, default_value_t = 0, requires = "sample")]
+    seed: u64,
+    /// JSON object of reasons not to record the benchmarks whose keys match its patterns,
+    /// where * matches any text, e.g. {"big/*": "too slow"}; they are marked as skipped
+    #[arg(long)]
+    skip_file: Option<PathBuf>,
+    /// Skip the benchmarks whose iterations spread by more than 5% in each of their last 3
+    /// runs of the history
+    #[arg(long)]
+    quarantine: bool,
+    /// Treat the output file as existing results: only benchmark the files missing from it or
+    /// modified since it was written, and merge the new measurements into it
+    #[arg(long)]
+    update: bool,
+    /// Record the benchmarks twice, storing the metrics of the second recording with the
+    /// first to estimate the noise of each, which comparisons against these results use
+    #[arg(long, conflicts_with_all = ["update", "rerun_failed", "rerun_noisy"])]
+    with_noise_estimate: bool,
+    /// Only record the benchmarks that failed or printed a wrong output in these results,
+    /// writing them with the fresh measurements merged in
+    #[arg(long, value_name = "RESULTS_FILE", conflicts_with_all = ["update", "remote"])]
+    rerun_failed: Option<PathBuf>,
+    /// Only record the benchmarks whose iterations spread by more than 5% or that were
+    /// measured on a busy machine in these results, writing them with the fresh measurements
+    /// merged in
+    #[arg(long, value_name = "RESULTS_FILE", conflicts_with_all = ["update", "remote"])]
+    rerun_noisy: Option<PathBuf>,
+    /// Format of the results printed on the standard output [default: table]
+    #[arg(long)]
+    format: Option<Format>,
+    /// Label the results with KEY=VALUE in their metadata
+    #[arg(long, value_name = "KEY=VALUE", value_parser = metadata::parse_tag)]
+    tag: Vec<(String, String)>,
+    /// Free-form note stored in the metadata of the results
+    #[arg(long)]
+    note: Option<String>,
+    /// Command of the run phase with --compile-cmd, in place of COMMAND
+    #[arg(long)]
+    run_cmd: Option<String>,
+    /// Shell command building the benchmarked programs before recording, e.g. "cargo build
+    /// --release" [default: the build of the manifest]
+    #[arg(long)]
+    build: Option<String>,
+    /// Also store the results in the history of runs
+    #[arg(long)]
+    history: bool,
+    /// Record on this machine over SSH instead, with the orchestrator installed there: the
+    /// inputs are copied to it, the commands run from a temporary directory of it, and the
+    /// results are copied back. Repeat to share the benchmarks out between several machines
+    /// recording in parallel, each benchmark always going to the same one
+    #[arg(long, value_name = "USER@HOST", conflicts_with = "update")]
+    remote: Vec<String>,
+    /// Directory of the remote machines holding the inputs at the same relative paths, which
+    /// are then not copied
+    #[arg(long, value_name = "DIR", requires = "remote")]
+    remote_corpus: Option<PathBuf>,
+    /// Orchestrator program on the remote machine
+    #[arg(long, default_value = "perf-bench-orchestrator")]
+    remote_program: String,
+    /// Print the metrics of each benchmark as ratios to those of the benchmark KEY, e.g. a
+    /// reference command measured alongside, leaving the results file as measured
+    #[arg(long, value_name = "KEY")]
+    normalize_to: Option<String>,
+    /// Group the benchmarks of the table, with a subtotal row after each group
+    #[arg(long, conflicts_with = "normalize_to")]
+    group_by: Option<GroupBy>,
+    /// Add a last row with the total of each metric over all the benchmarks
+    #[arg(long, conflicts_with = "normalize_to")]
+    total: bool,
+    /// Print the histogram of the iterations of each metric of each benchmark, and their
+    /// values in the order they ran in
+    #[arg(long, conflicts_with = "normalize_to")]
+    distribution: bool,
+    #[command(flatten)]
+    run: RunOptions,
+}
+
+#[derive(Debug, clap::Args)]
+struct WatchCliOptions {
     command: String,
-    output_file: PathBuf,
     wat_files: Vec<PathBuf>,
+    #[command(flatten)]
+    run: RunOptions,
+    /// Additional files to watch besides the command binary and the inputs
+    #[arg(long)]
+    watch: Vec<PathBuf>,
 }
 
 #[derive(Debug, clap::Args)]
 struct CompareCliOptions {
     base_file: PathBuf,
     compared_file: PathBuf,
+    /// A second recording of the base: changes smaller than its difference with the base are
+    /// reported as insignificant, whatever the thresholds [default: the one recorded with the
+    /// base by `record --with-noise-estimate`]
+    #[arg(long, value_name = "NOISE_FILE")]
+    noise: Option<PathBuf>,
+    /// Relative difference in percent beyond which changes are highlighted, also given as
+    /// --warn-at [default: 0.1]
+    #[arg(long, visible_alias = "warn-at")]
+    threshold: Option<f64>,
+    /// Relative difference in percent beyond which regressions are shown in red rather than
+    /// yellow, and improvements in magenta as suspicious; it only changes the colours, not the
+    /// exit status [default: the threshold, without suspicious improvements]
+    #[arg(long)]
+    fail_at: Option<f64>,
+    /// Format of the comparison [default: table]
+    #[arg(long)]
+    format: Option<Format>,
+    /// How the changes of metrics are shown in tables and posted reports [default: percent]
+    #[arg(long)]
+    diff_format: Option<DiffFormat>,
+    /// Number of functions whose instruction counts changed the most to show for each
+    /// benchmark recorded with the callgrind backend, and whose self cycles changed the most
+    /// for each regressed benchmark with --explain
+    #[arg(long, default_value_t = 5)]
+    functions: usize,
+    /// Show the functions whose self cycles changed the most in each regressed benchmark,
+    /// from the hot functions recorded with --hot-functions
+    #[arg(long)]
+    explain: bool,
+    /// Post the comparison as a comment of a GitHub pull request, or update the one posted
+    /// before, with the token in GITHUB_TOKEN
+    #[arg(long)]
+    post_github_comment: bool,
+    /// Pull request to comment on [default: the one of the GitHub Actions workflow]
+    #[arg(long, requires = "post_github_comment")]
+    pr: Option<u64>,
+    /// Post the comparison as a note of a GitLab merge request, or update the one posted
+    /// before, with the token in GITLAB_TOKEN
+    #[arg(long)]
+    post_gitlab_note: bool,
+    /// Merge request to post the note on [default: the one of the GitLab CI pipeline]
+    #[arg(long, requires = "post_gitlab_note")]
+    mr: Option<u64>,
+    /// Post a summary of the regressions beyond the threshold to this webhook, if there are
+    /// any
+    #[arg(long, value_name = "URL")]
+    notify_webhook: Option<String>,
+    /// Compare the metrics of each benchmark as ratios to those of the benchmark KEY of the
+    /// same results file, e.g. a reference command, to compare results of different machines
+    #[arg(long, value_name = "KEY")]
+    normalize_to: Option<String>,
+    /// Add the normalized_cycles metric, the ref_cycles of each side scaled from the nominal
+    /// frequency of its machine to MHZ, to compare machines of different frequencies
+    #[arg(long, value_name = "MHZ")]
+    reference_frequency: Option<f64>,
+    /// Only show the N benchmarks whose metrics increased the most, worst first, and the others
+    /// summed into a row, in the table and the posted comments
+    #[arg(long, value_name = "N")]
+    top: Option<usize>,
+    /// Group the benchmarks of the table, with the comparison of the subtotal of each group
+    /// after its benchmarks, and in collapsed sections in the posted reports
+    #[arg(long, conflicts_with = "top")]
+    group_by: Option<GroupBy>,
+    /// Add a last row with the comparison of the total of each metric over all the benchmarks
+    #[arg(long)]
+    total: bool,
+    /// Follow each change with a sparkline of the values of the metric in the last runs of the
+    /// history and the compared one
+    #[arg(long)]
+    sparklines: bool,
+    /// Directory of the history [default: the history directory in the cache directory]
+    #[arg(long, requires = "sparklines")]
+    history_dir: Option<PathBuf>,
+    /// Payload posted to the webhook
+    #[arg(long, default_value = "json", requires = "notify_webhook")]
+    webhook_payload: notify::Payload,
+}
+
+#[derive(Debug, clap::Args)]
+struct RevsOptions {
+    /// Input files, relative to the current directory rather than to the checkouts
+    wat_files: Vec<PathBuf>,
+    /// Shell command building each revision in its checkout, e.g. "cargo build --release"
+    #[arg(long)]
+    build: Option<String>,
+    /// Command to benchmark, run from each checkout [default: the command of the
+    /// configuration]
+    #[arg(long)]
+    command: Option<String>,
+    #[command(flatten)]
+    run: RunOptions,
+}
+
+impl RevsOptions {
+    /// The benchmarks to run in each checkout.
+    fn benchmarks(&self, config: &config::Config) -> anyhow::Result<Vec<Benchmark>> {
+        let command = self
+            .command
+            .clone()
+            .or(config.command.clone())
+            .ok_or_else(|| anyhow::anyhow!("missing --command"))?;
+        let mut benchmarks = file_benchmarks(&command, &self.wat_files, &self.run.key_format())?;
+        check_unique_keys(&benchmarks)?;
+        for benchmark in &mut benchmarks {
+            benchmark.input = benchmark
+                .input
+                .take()
+                .map(std::path::absolute)
+                .transpose()?;
+        }
+        Ok(benchmarks)
+    }
+
+    /// Builds the revision checked out in `worktree` and records `benchmarks` from it.
+    fn record(
+        &self,
+        worktree: &revs::Worktree,
+        benchmarks: &[Benchmark],
+    ) -> anyhow::Result<results::ResultsFile> {
+        if let Some(build) = &self.build {
+            worktree.build(build)?;
+        }
+        worktree.run_in(|| {
+            Ok(results::ResultsFile::new(record::measure(
+                benchmarks, &self.run,
+            )?))
+        })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct CompareRevsCliOptions {
+    base_rev: String,
+    compared_rev: String,
+    #[command(flatten)]
+    revs: RevsOptions,
+    /// Directory to write the results of both revisions to, as base.json and compared.json
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+    /// Relative difference in percent beyond which changes are highlighted, also given as
+    /// --warn-at [default: 0.1]
+    #[arg(long, visible_alias = "warn-at")]
+    threshold: Option<f64>,
+    /// Relative difference in percent beyond which regressions are shown in red rather than
+    /// yellow, and improvements in magenta as suspicious; it only changes the colours, not the
+    /// exit status [default: the threshold, without suspicious improvements]
+    #[arg(long)]
+    fail_at: Option<f64>,
+    /// Format of the comparison [default: table]
+    #[arg(long)]
+    format: Option<Format>,
+    /// How the changes of metrics are shown in tables and posted reports [default: percent]
+    #[arg(long)]
+    diff_format: Option<DiffFormat>,
+    /// Number of functions whose instruction counts changed the most to show for each
+    /// benchmark recorded with the callgrind backend, and whose self cycles changed the most
+    /// for each regressed benchmark with --explain
+    #[arg(long, default_value_t = 5)]
+    functions: usize,
+    /// Show the functions whose self cycles changed the most in each regressed benchmark,
+    /// from the hot functions recorded with --hot-functions
+    #[arg(long)]
+    explain: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct BisectCliOptions {
+    /// Revision without the regression
+    #[arg(long)]
+    good: String,
+    /// Revision with the regression
+    #[arg(long)]
+    bad: String,
+    /// Metric the regression shows in [default: the first recorded one]
+    #[arg(long)]
+    metric: Option<String>,
+    /// Increase in percent of the metric over the good revision beyond which a commit is bad
+    /// [default: half the increase of the bad revision]
+    #[arg(long)]
+    threshold: Option<f64>,
+    #[command(flatten)]
+    revs: RevsOptions,
+}
+
+#[derive(Debug, clap::Args)]
+struct UploadCliOptions {
+    results_file: PathBuf,
+    /// Service to upload to
+    #[arg(long)]
+    to: upload::Service,
+    /// Endpoint to post the results to, address of the Pushgateway, or API of a self-hosted
+    /// Bencher [default: $BENCHER_HOST or https://api.bencher.dev for Bencher]
+    #[arg(long)]
+    url: Option<String>,
+    /// Bencher project to report to [default: $BENCHER_PROJECT]
+    #[arg(long)]
+    project: Option<String>,
+    /// Bencher branch of the report [default: the git branch in the metadata]
+    #[arg(long)]
+    branch: Option<String>,
+    /// Bencher testbed of the report [default: the hostname in the metadata]
+    #[arg(long)]
+    testbed: Option<String>,
+    /// Header "Name: value" to send to the HTTP endpoint, e.g. for authentication
+    #[arg(long)]
+    header: Vec<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct FetchCliOptions {
+    /// JSON manifest of the corpus, with the url, sha256 and optional path of each input
+    corpus: PathBuf,
+    /// Directory to download the inputs into [default: the corpus directory in the cache
+    /// directory]
+    #[arg(long)]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct CorpusStatsCliOptions {
+    /// Input files, or directories of them
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Results file recording the inputs, to estimate the runtime of the corpus from
+    #[arg(long)]
+    results: Option<PathBuf>,
+    /// Template of the keys of the inputs in the results, as in `record` [default: {file}]
+    #[arg(long, requires = "results")]
+    key_template: Option<String>,
+    /// Directory the paths in the keys of the results are relative to, as in `record`
+    #[arg(long, requires = "results")]
+    strip_prefix: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct ImportCliOptions {
+    /// Format of the file to import
+    #[arg(long)]
+    from: ForeignFormat,
+    input_file: PathBuf,
+    /// Results file to create
+    output_file: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+struct ExportCliOptions {
+    /// Format to export to
+    #[arg(long)]
+    to: ForeignFormat,
+    results_file: PathBuf,
+    /// File to write [default: the standard output]
+    output_file: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct ExportSamplesCliOptions {
+    results_file: PathBuf,
+    /// File to write [default: the standard output]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct HistoryCliOptions {
+    #[command(subcommand)]
+    action: HistoryAction,
+    /// Directory of the history [default: the history directory in the cache directory]
+    #[arg(long)]
+    history_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum HistoryAction {
+    /// Store copies of results files
+    Add { results_files: Vec<PathBuf> },
+    /// List the runs, oldest first
+    List,
+    /// Compact the older runs into an aggregate per day, with the medians of their metrics
+    Prune {
+        /// Number of most recent runs to leave alone
+        #[arg(long)]
+        keep_last: usize,
+        /// Leave alone the runs with tags, such as release baselines
+        #[arg(long)]
+        keep_tagged: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct TrendCliOptions {
+    /// Key of the benchmark to plot
+    #[arg(long)]
+    benchmark: String,
+    /// Metric to plot
+    #[arg(long)]
+    metric: String,
+    /// Write the plot as SVG to this file instead of printing it
+    #[arg(long)]
+    svg: Option<PathBuf>,
+    /// Directory of the history [default: the history directory in the cache directory]
+    #[arg(long)]
+    history_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct PivotCliOptions {
+    /// Results files, one per command
+    #[arg(num_args = 2.., required = true)]
+    results_files: Vec<PathBuf>,
+    /// Comma-separated labels of the results files in the headers, in order [default: the
+    /// file stems]
+    #[arg(long, value_delimiter = ',')]
+    label: Vec<String>,
+    /// Label of the results file the others are relative to [default: the first one]
+    #[arg(long)]
+    reference: Option<String>,
+    /// Comma-separated metrics to pivot [default: all those of the reference]
+    #[arg(long, value_delimiter = ',')]
+    metrics: Vec<String>,
+    /// Format of the pivot, table or json [default: table]
+    #[arg(long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, clap::Args)]
+struct ChangepointsCliOptions {
+    /// Key of a benchmark to analyze [default: all of them]
+    #[arg(long)]
+    benchmark: Vec<String>,
+    /// Comma-separated metrics to analyze [default: all of them]
+    #[arg(long, value_delimiter = ',')]
+    metrics: Vec<String>,
+    /// Smallest relative difference in percent between two levels to report
+    #[arg(long, default_value_t = 1.)]
+    min_change: f64,
+    /// Directory of the history [default: the history directory in the cache directory]
+    #[arg(long)]
+    history_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct QueryCliOptions {
+    /// Results files to query instead of the history
+    results_files: Vec<PathBuf>,
+    /// Conditions joined with "and", each FIELD OP VALUE with the operators =, !=, ~
+    /// (contains), !~, <, <=, > and >=, on the fields file, metric, value, date, revision,
+    /// host and run.TAG, e.g. "file ~ simd/ and metric = instructions and run.tag = nightly"
+    #[arg(long = "where", value_name = "CONDITIONS")]
+    filter: Option<query::Filter>,
+    /// Format of the rows
+    #[arg(long, default_value = "csv")]
+    format: query::Format,
+    /// Directory of the history [default: the history directory in the cache directory]
+    #[arg(long)]
+    history_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct ServeCliOptions {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    address: String,
+    /// Relative difference in percent beyond which changes are highlighted [default: 0.1]
+    #[arg(long)]
+    threshold: Option<f64>,
+    /// Directory of the history [default: the history directory in the cache directory]
+    #[arg(long)]
+    history_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct SelftestCliOptions {
+    #[command(flatten)]
+    run: RunOptions,
+}
+
+#[derive(Debug, clap::Args)]
+struct InspectCliOptions {
+    results_file: PathBuf,
+    /// Key of an entry to print in detail, with each of its iterations
+    #[arg(long, value_name = "KEY")]
+    show: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct TopCliOptions {
+    results_file: PathBuf,
+    /// Metric to rank the benchmarks by [default: the first one of the results]
+    #[arg(long)]
+    metric: Option<String>,
+    /// Number of benchmarks to show
+    #[arg(short, default_value_t = 20)]
+    n: usize,
+}
+
+#[derive(Debug, clap::Args)]
+struct ValidateCliOptions {
+    results_file: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+struct CompletionsCliOptions {
+    shell: clap_complete::Shell,
 }
 
 #[derive(clap::Parser)]
 struct CliOptions {
+    /// Project configuration file to use instead of the closest perfbench.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Print the measured values as is, instead of scaled to readable units
+    #[arg(long, global = true)]
+    raw_numbers: bool,
+    /// Unit to display all times in [default: the most readable one for each]
+    #[arg(long, global = true)]
+    time_unit: Option<metrics::TimeUnit>,
     #[command(subcommand)]
     command: Command,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct Measure {
-    ref_cycles: u64,
-    instructions: u64,
-    cpu_time: u64,
+impl RecordCliOptions {
+    /// Sorts the positional arguments out, returning the output file and benchmarks, then runs
+    /// the build step if any.
+    fn plan(
+        &self,
+        config: &config::Config,
+    ) -> anyhow::Result<(PathBuf, Vec<Benchmark>, Option<metadata::Build>)> {
+        let manifest = self
+            .manifest
+            .as_deref()
+            .map(manifest::Manifest::load)
+            .transpose()?;
+        let mut args = self.args.iter();
+        let command = match &manifest {
+            _ if !self.trailing_command.is_empty() => Some(command::join(&self.trailing_command)),
+            _ if self.run_cmd.is_some() => self.run_cmd.clone(),
+            Some(manifest) => manifest.command.clone().or(config.command.clone()),
+            None if config.command.is_some() => config.command.clone(),
+            None => Some(
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("missing COMMAND"))?
+                    .clone(),
+            ),
+        };
+        let output_file = match &self.output {
+            Some(output) => output.clone(),
+            None => PathBuf::from(
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("missing OUTPUT_FILE"))?,
+            ),
+        };
+        let wat_files: Vec<PathBuf> = args.map(PathBuf::from).collect();
+        let mut benchmarks = match &manifest {
+            Some(manifest) => manifest.benchmarks(&self.run.key_format())?,
+            None => Vec::new(),
+        };
+        if !wat_files.is_empty() {
+            let command = command.ok_or_else(|| {
+                anyhow::anyhow!("the manifest has no default command to run the input files with")
+            })?;
+            benchmarks.extend(file_benchmarks(
+                &command,
+                &wat_files,
+                &self.run.key_format(),
+            )?);
+        }
+        check_unique_keys(&benchmarks)?;
+        if let Some(notes) = &self.notes {
+            manifest::apply_notes(notes, &mut benchmarks)?;
+        }
+        if let Some(sample) = self.sample() {
+            benchmarks = sampling::select(benchmarks, &sample);
+        }
+        self.mark_skipped(&mut benchmarks, config)?;
+        let build = match self
+            .build
+            .as_ref()
+            .or(manifest.as_ref().and_then(|m| m.build.as_ref()))
+        {
+            Some(build) => Some(record::build(build, &benchmarks)?),
+            None => None,
+        };
+        Ok((output_file, benchmarks, build))
+    }
+}
+
+impl RecordCliOptions {
+    /// Marks the benchmarks of the skip file, and those quarantined, as skipped.
+    fn mark_skipped(
+        &self,
+        benchmarks: &mut [Benchmark],
+        config: &config::Config,
+    ) -> anyhow::Result<()> {
+        let skip_list = self
+            .skip_file
+            .as_ref()
+            .or(config.skip_file.as_ref())
+            .map(|path| skip::SkipList::load(path))
+            .transpose()?;
+        let quarantined = if self.quarantine || config.quarantine.unwrap_or(false) {
+            skip::quarantined(&history::History::open(None, config)?.runs()?)
+        } else {
+            Default::default()
+        };
+        let mut skipped = 0;
+        for benchmark in benchmarks.iter_mut() {
+            let reason = match skip_list.as_ref().and_then(|s| s.reason(&benchmark.key)) {
+                Some(reason) => Some(reason.to_owned()),
+                None if quarantined.contains(&benchmark.key) => Some(format!(
+                    "quarantined, noisy in its last {} runs",
+                    skip::QUARANTINE_RUNS
+                )),
+                None => None,
+            };
+            if reason.is_some() {
+                benchmark.skip = reason;
+            }
+            skipped += usize::from(benchmark.skip.is_some());
+        }
+        if skipped > 0 {
+            tracing::info!("skipping {skipped} benchmark(s)");
+        }
+        Ok(())
+    }
+
+    /// The subset of the benchmarks to record, if not all.
+    fn sample(&self) -> Option<sampling::Sample> {
+        Some(sampling::Sample {
+            fraction: self.sample?,
+            stratify_by: self.stratify_by,
+            seed: self.seed,
+        })
+    }
+
+    /// Adds the tags and note of the command line, and the build step, to the metadata of
+    /// `res`.
+    fn annotate(&self, res: &mut results::ResultsFile, build: Option<metadata::Build>) {
+        if let Some(metadata) = &mut res.metadata {
+            metadata.build = build;
+            metadata.core_type = self
+                .run
+                .core_type
+                .map(|core_type| core_type.name().to_owned());
+            metadata.numa_node = self.run.numa_node;
+            metadata.tags.extend(self.tag.iter().cloned());
+            if self.note.is_some() {
+                metadata.note = self.note.clone();
+            }
+            metadata.sample = self.sample();
+        }
+    }
+
+    /// Measures `benchmarks` again into `res`, carrying its tags and note over to the metadata
+    /// of this recording.
+    fn remeasure(
+        &self,
+        res: &mut results::ResultsFile,
+        benchmarks: &[Benchmark],
+        build: Option<metadata::Build>,
+    ) -> anyhow::Result<()> {
+        let mut current = metadata::Metadata::collect();
+        if let Some(previous) = res.metadata.take() {
+            compare::warn_metadata_differences(&previous, &current);
+            current.tags = previous.tags;
+            current.note = previous.note;
+        }
+        res.metadata = Some(current);
+        self.annotate(res, build);
+        res.results.extend(record::measure(benchmarks, &self.run)?);
+        res.detect_git();
+        Ok(())
+    }
+
+    /// Prints the recorded `res` in `format`.
+    fn print(
+        &self,
+        res: &results::ResultsFile,
+        format: Format,
+        config: &config::Config,
+    ) -> anyhow::Result<()> {
+        print_results(
+            res,
+            format,
+            self.normalize_to.as_deref(),
+            self.group_by.or(config.group_by),
+            self.distribution,
+            self.total || config.total.unwrap_or(false),
+            config,
+        )
+    }
+}
+
+/// The standard output, unless it is a terminal that binary output would garble.
+fn binary_stdout() -> anyhow::Result<std::io::StdoutLock<'static>> {
+    let stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        anyhow::bail!("not writing binary output to a terminal, redirect it to a file");
+    }
+    Ok(stdout.lock())
+}
+
+/// Prints `res` in `format`, as ratios to the benchmark `normalize_to` if given or grouped
+/// `group_by` with a `total` row, and with the `distribution` of the iterations, which only
+/// tables support.
+fn print_results(
+    res: &results::ResultsFile,
+    format: Format,
+    normalize_to: Option<&str>,
+    group_by: Option<GroupBy>,
+    distribution: bool,
+    total: bool,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    match format {
+        Format::Table => match normalize_to {
+            Some(reference) => record::print_measures(
+                &results::normalize(&res.results, reference)?,
+                metrics::DisplayOptions {
+                    ratios: true,
+                    ..config.display_options()
+                },
+            ),
+            None => {
+                record::print_summed_measures(
+                    &res.results,
+                    group_by,
+                    total,
+                    config.display_options(),
+                );
+                record::print_breakdowns(&res.results, config.display_options());
+                if distribution {
+                    distribution::print(&res.results, config.display_options());
+                }
+            }
+        },
+        Format::Json => println!("{}", serde_json::to_string_pretty(res)?),
+        Format::Openmetrics => print!("{}", openmetrics::results(res)),
+        Format::Influx => print!("{}", influx::results(res)),
+        Format::Parquet => parquet::samples(&res.results, &mut binary_stdout()?)?,
+    }
+    Ok(())
+}
+
+fn record(mut cli_options: RecordCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    cli_options.run.apply_config(config)?;
+    let format = cli_options
+        .format
+        .or(config.format)
+        .unwrap_or(Format::Table);
+    if cli_options.normalize_to.is_some() && format != Format::Table {
+        anyhow::bail!("--normalize-to only applies to tables");
+    }
+    if cli_options.distribution && format != Format::Table {
+        anyhow::bail!("--distribution only applies to tables");
+    }
+    if cli_options.update {
+        return record_update(cli_options, config, format);
+    }
+    if cli_options.rerun_failed.is_some() || cli_options.rerun_noisy.is_some() {
+        return record_rerun(cli_options, config, format);
+    }
+    let (output_file, benchmarks, build) = cli_options.plan(config)?;
+    let record_all = |opts: &RunOptions| {
+        if cli_options.remote.is_empty() {
+            return Ok(results::ResultsFile::new(record::measure(
+                &benchmarks,
+                opts,
+            )?));
+        }
+        let remotes: Vec<remote::Remote> = cli_options
+            .remote
+            .iter()
+            .map(|host| remote::Remote {
+                host: host.clone(),
+                program: cli_options.remote_program.clone(),
+                corpus: cli_options.remote_corpus.clone(),
+            })
+            .collect();
+        remote::record_sharded(&remotes, &benchmarks, opts)
+    };
+    let mut res = record_all(&cli_options.run)?;
+    if cli_options.with_noise_estimate {
+        tracing::info!(
+            "recording the {} benchmark(s) again to estimate their noise",
+            benchmarks.len()
+        );
+        // The second recording must measure, not reuse the cached measurements of the first.
+        let again = record_all(&RunOptions {
+            cache: false,
+            ..cli_options.run.clone()
+        })?;
+        results::add_noise_run(&mut res.results, &again.results);
+    }
+    cli_options.annotate(&mut res, build);
+    results::write_new(&output_file, &res)?;
+    if cli_options.history {
+        let path = history::History::open(None, config)?.add(&res)?;
+        tracing::info!("stored the results in the history as {}", path.display());
+    }
+    cli_options.print(&res, format, config)
+}
+
+fn record_update(
+    cli_options: RecordCliOptions,
+    config: &config::Config,
+    format: Format,
+) -> anyhow::Result<()> {
+    let (output_file, benchmarks, build) = cli_options.plan(config)?;
+    let mut res = results::read(&output_file)?;
+    let written = std::fs::metadata(&output_file)?.modified()?;
+    let total = benchmarks.len();
+    let mut outdated = Vec::new();
+    for benchmark in benchmarks {
+        let modified = match &benchmark.input {
+            Some(input) => std::fs::metadata(input)?.modified()? > written,
+            None => false,
+        };
+        let measured = res.results.get(&benchmark.key).is_some_and(|m| !m.skipped);
+        if modified || !measured {
+            outdated.push(benchmark);
+        }
+    }
+    tracing::info!(
+        "{} of {total} benchmark(s) missing or modified since the last recording",
+        outdated.len(),
+    );
+    cli_options.remeasure(&mut res, &outdated, build)?;
+    results::write_atomically(&output_file, &res)?;
+    cli_options.print(&res, format, config)
 }
 
-fn scale(
-    CountAndTime {
-        count,
-        time_enabled,
-        time_running,
-    }: CountAndTime,
-) -> u64 {
-    if time_running < time_enabled {
-        ((count as u128) * (time_enabled as u128) / (time_running as u128)) as u64
+fn record_rerun(
+    cli_options: RecordCliOptions,
+    config: &config::Config,
+    format: Format,
+) -> anyhow::Result<()> {
+    let previous_file = match (&cli_options.rerun_failed, &cli_options.rerun_noisy) {
+        (Some(failed), Some(noisy)) if failed != noisy => {
+            anyhow::bail!("--rerun-failed and --rerun-noisy must name the same results")
+        }
+        (Some(previous), _) | (None, Some(previous)) => previous.clone(),
+        (None, None) => unreachable!("called to rerun benchmarks"),
+    };
+    let (output_file, benchmarks, build) = cli_options.plan(config)?;
+    let mut res = results::read(&previous_file)?;
+    let total = benchmarks.len();
+    let (mut failed, mut noisy) = (0, 0);
+    let mut rerun = Vec::new();
+    for benchmark in benchmarks {
+        let Some(measure) = res.results.get(&benchmark.key) else {
+            continue;
+        };
+        if cli_options.rerun_failed.is_some()
+            && (measure.status.is_failure() || measure.incorrect || measure.limit_hit.is_some())
+        {
+            failed += 1;
+            rerun.push(benchmark);
+        } else if cli_options.rerun_noisy.is_some()
+            && (skip::is_noisy(measure) || measure.is_noisy())
+        {
+            noisy += 1;
+            rerun.push(benchmark);
+        }
+    }
+    tracing::info!(
+        "re-running {} of {total} benchmark(s): {failed} failed and {noisy} noisy in {}",
+        rerun.len(),
+        previous_file.display()
+    );
+    cli_options.remeasure(&mut res, &rerun, build)?;
+    if output_file == previous_file {
+        results::write_atomically(&output_file, &res)?;
     } else {
-        count
-    }
-}
-
-fn record(cli_options: RecordCliOptions) -> anyhow::Result<()> {
-    let mut ref_cycles = prf::Builder::new(Hardware::REF_CPU_CYCLES)
-        .inherit(true)
-        .enable_on_exec(true)
-        .build()?;
-    let mut instructions = prf::Builder::new(Hardware::INSTRUCTIONS)
-        .inherit(true)
-        .enable_on_exec(true)
-        .build()?;
-    let mut res = HashMap::new();
-    for wat_file in &cli_options.wat_files {
-        let mut command_words = cli_options.command.split_whitespace();
-        let command = command_words.next().expect("Non-empty command");
-        let mut command = std::process::Command::new(command);
-        command.args(command_words);
-        command.arg(wat_file);
-        for c in [&mut ref_cycles, &mut instructions] {
-            c.reset()?;
-        }
-        command.status()?;
-        for c in [&mut ref_cycles, &mut instructions] {
-            c.disable()?
-        }
-        let meas = Measure {
-            ref_cycles: scale(ref_cycles.read_count_and_time()?),
-            instructions: scale(instructions.read_count_and_time()?),
-            cpu_time: ref_cycles.read_count_and_time()?.time_enabled,
+        results::write_new(&output_file, &res)?;
+    }
+    cli_options.print(&res, format, config)
+}
+
+fn compare(cli_options: CompareCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let mut base = results::read(&cli_options.base_file)?;
+    let mut compared = results::read(&cli_options.compared_file)?;
+    let mut noise = match &cli_options.noise {
+        Some(path) => {
+            let mut noise = results::read(path)?.results;
+            results::remove_skipped(&mut noise);
+            Some(noise)
+        }
+        None => {
+            let noise = results::noise_run(&base.results);
+            if noise.is_some() {
+                tracing::info!("using the noise estimate recorded with the base");
+            }
+            noise
+        }
+    };
+    if let Some(mhz) = cli_options
+        .reference_frequency
+        .or(config.reference_frequency)
+    {
+        let base_name = cli_options.base_file.display().to_string();
+        invariant::add_normalized_cycles(&mut base, &base_name, mhz)?;
+        let compared_name = cli_options.compared_file.display().to_string();
+        invariant::add_normalized_cycles(&mut compared, &compared_name, mhz)?;
+    }
+    if let Some(reference) = &cli_options.normalize_to {
+        base.results = results::normalize(&base.results, reference)?;
+        compared.results = results::normalize(&compared.results, reference)?;
+        if let Some(noise) = &mut noise {
+            *noise = results::normalize(noise, reference)?;
+        }
+    }
+    if let Some(noise) = &noise {
+        let missing = base
+            .results
+            .keys()
+            .filter(|k| !noise.contains_key(*k))
+            .count();
+        if missing > 0 {
+            tracing::warn!(
+                "{missing} benchmark(s) of the base are not in the noise run, compared with the \
+                 thresholds only"
+            );
+        }
+    }
+    let thresholds = config.thresholds(cli_options.threshold, cli_options.fail_at);
+    let diff_format = cli_options
+        .diff_format
+        .or(config.diff_format)
+        .unwrap_or_default();
+    let format = cli_options
+        .format
+        .or(config.format)
+        .unwrap_or(Format::Table);
+    if cli_options.top.is_some() && format != Format::Table {
+        anyhow::bail!("--top is only supported with the table format");
+    }
+    let rows = compare::Rows {
+        top: cli_options.top,
+        group_by: cli_options
+            .group_by
+            .or(config.group_by.filter(|_| cli_options.top.is_none())),
+        total: cli_options.total || config.total.unwrap_or(false),
+    };
+    let recent = if cli_options.sparklines || config.sparklines.unwrap_or(false) {
+        Some(
+            history::History::open(cli_options.history_dir, config)?
+                .recent(compare::SPARKLINE_RUNS)?,
+        )
+    } else {
+        None
+    };
+    let style = compare::Style {
+        thresholds: &thresholds,
+        diff_format,
+        history: recent.as_ref(),
+        noise: noise.as_ref(),
+    };
+    let base_name = cli_options.base_file.display().to_string();
+    let compared_name = cli_options.compared_file.display().to_string();
+    if cli_options.post_github_comment || cli_options.post_gitlab_note {
+        let report = markdown_report(
+            (&base_name, &base),
+            (&compared_name, &compared),
+            style,
+            rows,
+        );
+        if cli_options.post_github_comment {
+            github::post_comment(&report, cli_options.pr)?;
+        }
+        if cli_options.post_gitlab_note {
+            gitlab::post_note(&report, cli_options.mr)?;
+        }
+    }
+    if let Some(url) = &cli_options.notify_webhook {
+        let regressions = compare::regressions(
+            &base.results,
+            &compared.results,
+            &thresholds,
+            noise.as_ref(),
+        );
+        if !regressions.is_empty() {
+            notify::webhook(
+                url,
+                cli_options.webhook_payload,
+                (&base_name, &compared_name),
+                thresholds.warn_at,
+                &regressions,
+            )?;
+        }
+    }
+    print_files_comparison(
+        (&base_name, base),
+        (&compared_name, compared),
+        style,
+        format,
+        (cli_options.functions, cli_options.explain),
+        rows,
+        config,
+    )
+}
+
+/// Prints the comparison of the results files `base` and `compared`, each with the name it
+/// is shown with, the table showing `rows` and at most the `functions` most changed functions
+/// of each benchmark, and of each regressed one if `explain`.
+fn print_files_comparison(
+    (base_name, mut base): (&str, results::ResultsFile),
+    (compared_name, mut compared): (&str, results::ResultsFile),
+    style: compare::Style,
+    format: Format,
+    (functions, explain): (usize, bool),
+    rows: compare::Rows,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    for (side, file) in [("base", &mut base), ("compared", &mut compared)] {
+        let skipped = results::remove_skipped(&mut file.results);
+        compare::warn_skipped(side, &skipped);
+    }
+    if let (Some(base), Some(compared)) = (&base.metadata, &compared.metadata) {
+        compare::warn_metadata_differences(base, compared);
+    }
+    compare::warn_metric_differences(&base.results, &compared.results);
+    compare::warn_host_differences(&base.results, &compared.results);
+    compare::warn_frequency_differences(&base.results, &compared.results);
+    if format == Format::Table {
+        for (label, name, file) in [
+            ("Base", base_name, &base),
+            ("Compared", compared_name, &compared),
+        ] {
+            match &file.metadata {
+                Some(metadata) => println!("{label}: {name} ({})", metadata.summary()),
+                None => println!("{label}: {name}"),
+            }
+        }
+        let git = |file: &results::ResultsFile| Some(file.metadata.as_ref()?.git.as_ref()?.short());
+        if let (Some(base), Some(compared)) = (git(&base), git(&compared)) {
+            println!("Revisions: base = {base}, compared = {compared}");
+        }
+    }
+    let (base, compared) = (base.results, compared.results);
+    let comparator = Comparator::new(&base, &compared, style.thresholds.warn_at)
+        .with_style(style)
+        .with_rows(rows);
+    match format {
+        Format::Table => {
+            comparator.print();
+            compare::print_function_deltas(&base, &compared, functions, config.display_options());
+            if explain {
+                compare::print_hot_function_deltas(
+                    &base,
+                    &compared,
+                    &comparator.regressions(),
+                    functions,
+                    config.display_options(),
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&comparator.json())?),
+        Format::Openmetrics => print!("{}", openmetrics::comparison(&base, &compared)),
+        Format::Influx => print!("{}", influx::comparison(&base, &compared)),
+        Format::Parquet => anyhow::bail!("comparisons cannot be written as Parquet"),
+    }
+    Ok(())
+}
+
+/// The comparison of the results files `base` and `compared` as a Markdown report, grouped as
+/// `rows`.
+fn markdown_report(
+    (base_name, base): (&str, &results::ResultsFile),
+    (compared_name, compared): (&str, &results::ResultsFile),
+    style: compare::Style,
+    rows: compare::Rows,
+) -> String {
+    let mut report = "### Performance comparison\n\n".to_owned();
+    for (label, name, file) in [
+        ("Base", base_name, base),
+        ("Compared", compared_name, compared),
+    ] {
+        report.push_str(&format!("- {label}: `{name}`"));
+        if let Some(metadata) = &file.metadata {
+            report.push_str(&format!(", {}", metadata.summary()));
+            if let Some(git) = &metadata.git {
+                report.push_str(&format!(" at {git}"));
+            }
+        }
+        report.push('\n');
+    }
+    report.push('\n');
+    report.push_str(
+        &Comparator::new(&base.results, &compared.results, style.thresholds.warn_at)
+            .with_style(style)
+            .with_rows(rows)
+            .markdown(),
+    );
+    report
+}
+
+fn watch(mut cli_options: WatchCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    cli_options.run.apply_config(config)?;
+    let thresholds = config.thresholds(None, None);
+    let program = command::program(&cli_options.command)?;
+    let mut watched = cli_options.watch.clone();
+    watched.extend(command::resolve_program(&program));
+    watched.extend(cli_options.wat_files.iter().cloned());
+    let mut watcher = watch::Watcher::new(&watched)?;
+    let benchmarks = file_benchmarks(
+        &cli_options.command,
+        &cli_options.wat_files,
+        &cli_options.run.key_format(),
+    )?;
+    check_unique_keys(&benchmarks)?;
+    let mut last = record::measure(&benchmarks, &cli_options.run)?;
+    record::print_measures(&last, config.display_options());
+    loop {
+        let changed = watcher.wait()?;
+        for path in &changed {
+            tracing::info!("{} changed, re-recording", path.display());
+        }
+        let res = record::measure(&benchmarks, &cli_options.run)?;
+        compare::print_comparison(
+            &last,
+            &res,
+            compare::Style {
+                thresholds: &thresholds,
+                diff_format: config.diff_format.unwrap_or_default(),
+                history: None,
+                noise: None,
+            },
+            false,
+        );
+        last = res;
+    }
+}
+
+fn compare_revs(
+    mut cli_options: CompareRevsCliOptions,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    let options = &mut cli_options.revs;
+    options.run.apply_config(config)?;
+    let benchmarks = options.benchmarks(config)?;
+    if let Some(dir) = &cli_options.output_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut files = Vec::new();
+    for (index, rev) in [&cli_options.base_rev, &cli_options.compared_rev]
+        .into_iter()
+        .enumerate()
+    {
+        let worktree = revs::Worktree::add(rev, index)?;
+        let res = options.record(&worktree, &benchmarks)?;
+        if let Some(dir) = &cli_options.output_dir {
+            let name = ["base.json", "compared.json"][index];
+            results::write_atomically(&dir.join(name), &res)?;
+        }
+        files.push((rev.as_str(), res));
+    }
+    let compared = files.pop().expect("two revisions are recorded");
+    let base = files.pop().expect("two revisions are recorded");
+    let thresholds = config.thresholds(cli_options.threshold, cli_options.fail_at);
+    let diff_format = cli_options
+        .diff_format
+        .or(config.diff_format)
+        .unwrap_or_default();
+    let format = cli_options
+        .format
+        .or(config.format)
+        .unwrap_or(Format::Table);
+    print_files_comparison(
+        base,
+        compared,
+        compare::Style {
+            thresholds: &thresholds,
+            diff_format,
+            history: None,
+            noise: None,
+        },
+        format,
+        (cli_options.functions, cli_options.explain),
+        compare::Rows {
+            group_by: config.group_by,
+            total: config.total.unwrap_or(false),
+            ..Default::default()
+        },
+        config,
+    )
+}
+
+fn bisect(mut cli_options: BisectCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let options = &mut cli_options.revs;
+    options.run.apply_config(config)?;
+    let metric = match &cli_options.metric {
+        Some(metric) => metric.clone(),
+        None => options.run.metrics()[0].clone(),
+    };
+    let benchmarks = options.benchmarks(config)?;
+    // Revisions such as HEAD~3 would be relative to the worktree once in it.
+    let (good_commit, bad_commit) = (
+        revs::resolve(&cli_options.good)?,
+        revs::resolve(&cli_options.bad)?,
+    );
+    let mut worktree = revs::Worktree::add(&good_commit, 0)?;
+    let good = options.record(&worktree, &benchmarks)?.results;
+    worktree.checkout(&bad_commit)?;
+    let bad = options.record(&worktree, &benchmarks)?.results;
+    let regression = bisect::change(&good, &bad, &metric)?;
+    let threshold = cli_options.threshold.unwrap_or(regression / 2.);
+    tracing::info!(
+        "{metric} changed by {regression:+.1}% from {} to {}, commits beyond {threshold:+.1}% \
+         are bad",
+        cli_options.good,
+        cli_options.bad
+    );
+    if regression <= threshold {
+        anyhow::bail!(
+            "{metric} of {} is not beyond {threshold:+.1}% of {}, there is no regression to \
+             bisect",
+            cli_options.bad,
+            cli_options.good
+        );
+    }
+    let mut step = worktree.bisect_start(&bad_commit, &good_commit)?;
+    let first_bad = loop {
+        let commit = match step {
+            revs::Bisection::Test(commit) => commit,
+            revs::Bisection::FirstBad(commit) => break commit,
+        };
+        let mark = match options.record(&worktree, &benchmarks) {
+            Ok(res) => {
+                let change = bisect::change(&good, &res.results, &metric)?;
+                let mark = if change > threshold { "bad" } else { "good" };
+                tracing::info!("{}: {metric} {change:+.1}%, {mark}", &commit[..7]);
+                mark
+            }
+            Err(e) => {
+                tracing::warn!("skipping {}: {e}", &commit[..7]);
+                "skip"
+            }
         };
-        res.insert(wat_file, meas);
-    }
-    let output = std::fs::OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(cli_options.output_file)?;
-    serde_json::to_writer_pretty(output, &res)?;
-    let mut table = prettytable::Table::new();
-    table.add_row(prettytable::row![
-        "File",
-        "Ref-cycles",
-        "Instructions",
-        "CPU Time (ms)"
-    ]);
-    for (input_file, meas) in res {
-        table.add_row(prettytable::row![
-            input_file.display(),
-            meas.ref_cycles,
-            meas.instructions,
-            meas.cpu_time
-        ]);
-    }
-    table.printstd();
+        step = worktree.bisect_mark(mark)?;
+    };
+    println!("First bad commit: {}", worktree.describe(&first_bad)?);
     Ok(())
 }
 
-fn compare(cli_options: CompareCliOptions) -> anyhow::Result<()> {
-    let base_file = std::fs::read_to_string(cli_options.base_file)?;
-    let compared_file = std::fs::read_to_string(cli_options.compared_file)?;
-    let base: HashMap<&Path, Measure> = serde_json::from_str(&base_file)?;
-    let compared: HashMap<&Path, Measure> = serde_json::from_str(&compared_file)?;
-    let mut table = prettytable::Table::new();
-    table.add_row(prettytable::row![
-        "File",
-        "Ref-cycles",
-        "Instructions",
-        "CPU Time (ms)"
-    ]);
-    fn rel_diff(base: u64, compared: u64) -> prettytable::Cell {
-        let diff = (((compared as f64) - (base as f64)) * 100.) / (base as f64);
-        let mut cell = prettytable::Cell::new(&format!("{diff:+.1}%",));
-        if diff > 0.1 {
-            cell.style(prettytable::Attr::ForegroundColor(prettytable::color::RED));
-        } else if diff < -0.1 {
-            cell.style(prettytable::Attr::ForegroundColor(
-                prettytable::color::GREEN,
-            ));
-        }
-        cell
-    }
-    for (&key, base_measure) in &base {
-        let Some(compared_measure) = compared.get(key) else { continue };
-        table.add_row(prettytable::Row::new(vec![
-            prettytable::Cell::new(&key.display().to_string()),
-            rel_diff(base_measure.ref_cycles, compared_measure.ref_cycles),
-            rel_diff(base_measure.instructions, compared_measure.instructions),
-            rel_diff(base_measure.cpu_time, compared_measure.cpu_time),
-        ]));
-    }
-    table.printstd();
+fn upload(cli_options: UploadCliOptions) -> anyhow::Result<()> {
+    let file = results::read(&cli_options.results_file)?;
+    match cli_options.to {
+        upload::Service::Bencher => upload::bencher(
+            &file,
+            &upload::BencherReport {
+                host: cli_options.url.as_deref(),
+                project: cli_options.project.as_deref(),
+                branch: cli_options.branch.as_deref(),
+                testbed: cli_options.testbed.as_deref(),
+            },
+        ),
+        upload::Service::Influxdb => {
+            let url = cli_options
+                .url
+                .ok_or_else(|| anyhow::anyhow!("missing the --url of the InfluxDB write API"))?;
+            upload::influxdb(&file, &url)
+        }
+        upload::Service::Pushgateway => {
+            let url = cli_options
+                .url
+                .ok_or_else(|| anyhow::anyhow!("missing the --url of the Pushgateway"))?;
+            upload::pushgateway(&file, &url)
+        }
+        upload::Service::Http => {
+            let url = cli_options
+                .url
+                .ok_or_else(|| anyhow::anyhow!("missing the --url to post the results to"))?;
+            upload::http(&file, &url, &cli_options.header)
+        }
+    }
+}
+
+fn fetch(cli_options: FetchCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let dir = match cli_options.dir {
+        Some(dir) => dir,
+        None => config
+            .cache_dir
+            .clone()
+            .or_else(cache::default_dir)
+            .ok_or_else(|| anyhow::anyhow!("cannot determine the cache directory, pass --dir"))?
+            .join("corpus"),
+    };
+    for path in corpus::Corpus::load(&cli_options.corpus)?.fetch(&dir)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn corpus_stats(cli_options: CorpusStatsCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let inputs = corpus_stats::collect(&cli_options.paths)?;
+    let prior = cli_options
+        .results
+        .as_deref()
+        .map(results::read)
+        .transpose()?;
+    let key_template = cli_options.key_template.or(config.key_template.clone());
+    let strip_prefix = cli_options.strip_prefix.or(config.strip_prefix.clone());
+    let key_format = record::KeyFormat {
+        template: key_template.as_deref().unwrap_or("{file}"),
+        strip_prefix: strip_prefix.as_deref(),
+    };
+    corpus_stats::print(
+        &inputs,
+        prior.as_ref().map(|file| &file.results),
+        &key_format,
+        config.display_options(),
+    )
+}
+
+fn import(cli_options: ImportCliOptions) -> anyhow::Result<()> {
+    let input = std::fs::read_to_string(&cli_options.input_file)?;
+    let res = match cli_options.from {
+        ForeignFormat::Hyperfine => {
+            hyperfine::import(serde_json::from_str(&input).map_err(|e| {
+                anyhow::anyhow!(
+                    "invalid hyperfine export {}: {e}",
+                    cli_options.input_file.display()
+                )
+            })?)
+        }
+    };
+    results::write_new(&cli_options.output_file, &res)
+}
+
+fn export(cli_options: ExportCliOptions) -> anyhow::Result<()> {
+    let file = results::read(&cli_options.results_file)?;
+    let exported = match cli_options.to {
+        ForeignFormat::Hyperfine => serde_json::to_string_pretty(&hyperfine::export(&file)?)?,
+    };
+    match &cli_options.output_file {
+        Some(path) => std::fs::write(path, exported)?,
+        None => println!("{exported}"),
+    }
+    Ok(())
+}
+
+fn export_samples(cli_options: ExportSamplesCliOptions) -> anyhow::Result<()> {
+    let file = results::read(&cli_options.results_file)?;
+    let csv = samples::csv(&file.results);
+    match &cli_options.output {
+        Some(path) => std::fs::write(path, csv)?,
+        None => print!("{csv}"),
+    }
+    Ok(())
+}
+
+fn history(cli_options: HistoryCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let history = history::History::open(cli_options.history_dir, config)?;
+    match cli_options.action {
+        HistoryAction::Add { results_files } => {
+            for path in results_files {
+                let stored = history.add(&results::read(&path)?)?;
+                println!("{} -> {}", path.display(), stored.display());
+            }
+        }
+        HistoryAction::List => {
+            for run in history.runs()? {
+                println!(
+                    "{}\t{}\t{} benchmark(s)",
+                    run.path.display(),
+                    run.label(),
+                    run.file.results.len()
+                );
+            }
+        }
+        HistoryAction::Prune {
+            keep_last,
+            keep_tagged,
+        } => {
+            let (removed, aggregates) = history.prune(keep_last, keep_tagged)?;
+            println!("Compacted {removed} run(s) into {aggregates} daily aggregate(s)");
+        }
+    }
+    Ok(())
+}
+
+fn trend(cli_options: TrendCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let history = history::History::open(cli_options.history_dir, config)?;
+    let runs = history.runs()?;
+    let points = trend::points(&runs, &cli_options.benchmark, &cli_options.metric);
+    if points.is_empty() {
+        anyhow::bail!(
+            "none of the {} run(s) in {} measured {} of {}",
+            runs.len(),
+            history.dir().display(),
+            cli_options.metric,
+            cli_options.benchmark
+        );
+    }
+    match &cli_options.svg {
+        Some(path) => std::fs::write(
+            path,
+            trend::svg(
+                &points,
+                &cli_options.benchmark,
+                &cli_options.metric,
+                config.display_options(),
+            ),
+        )?,
+        None => trend::print(&points, &cli_options.metric, config.display_options()),
+    }
+    Ok(())
+}
+
+fn pivot(cli_options: PivotCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    if cli_options.label.len() > cli_options.results_files.len() {
+        anyhow::bail!("more labels than results files");
+    }
+    let files = cli_options
+        .results_files
+        .iter()
+        .map(|path| results::read(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let columns: Vec<pivot::Column> = cli_options
+        .results_files
+        .iter()
+        .zip(&files)
+        .enumerate()
+        .map(|(i, (path, file))| pivot::Column {
+            label: cli_options.label.get(i).cloned().unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            }),
+            results: &file.results,
+        })
+        .collect();
+    let reference = match &cli_options.reference {
+        Some(label) => columns
+            .iter()
+            .position(|column| column.label == *label)
+            .ok_or_else(|| anyhow::anyhow!("no results file is labeled {label:?}"))?,
+        None => 0,
+    };
+    match cli_options
+        .format
+        .or(config.format)
+        .unwrap_or(Format::Table)
+    {
+        Format::Table => pivot::print(&columns, reference, &cli_options.metrics),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&pivot::json(&columns, reference, &cli_options.metrics))?
+        ),
+        format => anyhow::bail!(
+            "the pivot is printed as a table or JSON, not {}",
+            clap::ValueEnum::to_possible_value(&format)
+                .expect("no skipped variant")
+                .get_name()
+        ),
+    }
+    Ok(())
+}
+
+fn changepoints(
+    cli_options: ChangepointsCliOptions,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    let runs = history::History::open(cli_options.history_dir, config)?.runs()?;
+    let steps = changepoint::scan(
+        &runs,
+        &cli_options.benchmark,
+        &cli_options.metrics,
+        cli_options.min_change,
+    );
+    if steps.is_empty() {
+        println!("No changepoints in {} run(s)", runs.len());
+    }
+    let display = config.display_options();
+    for step in steps {
+        let change = step.change();
+        println!(
+            "{} for {} stepped {} {:.2}% at {} ({}): {} -> {}",
+            step.metric,
+            step.benchmark,
+            if change > 0. { "up" } else { "down" },
+            change.abs(),
+            step.label,
+            step.date,
+            metrics::display(&step.metric, step.before, display),
+            metrics::display(&step.metric, step.after, display),
+        );
+    }
+    Ok(())
+}
+
+fn query(cli_options: QueryCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let runs = if cli_options.results_files.is_empty() {
+        history::History::open(cli_options.history_dir, config)?.runs()?
+    } else {
+        cli_options
+            .results_files
+            .into_iter()
+            .map(|path| {
+                let file = results::read(&path)?;
+                Ok(history::Run { path, file })
+            })
+            .collect::<anyhow::Result<_>>()?
+    };
+    let rows = query::rows(&runs, &cli_options.filter.unwrap_or_default());
+    match cli_options.format {
+        query::Format::Csv => print!("{}", query::csv(&rows)),
+        query::Format::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        query::Format::Parquet => parquet::query_rows(&rows, &mut binary_stdout()?)?,
+    }
+    Ok(())
+}
+
+fn inspect(cli_options: InspectCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let file = results::read(&cli_options.results_file)?;
+    match &cli_options.show {
+        Some(key) => {
+            let measure = file.results.get(key).ok_or_else(|| {
+                anyhow::anyhow!("no entry {key:?} in {}", cli_options.results_file.display())
+            })?;
+            let efficiency = file
+                .metadata
+                .as_ref()
+                .and_then(|metadata| cores::parse_list(metadata.efficiency_cpus.as_deref()?))
+                .unwrap_or_default();
+            inspect::print_measure(key, measure, &efficiency, config.display_options());
+        }
+        None => inspect::print_summary(&cli_options.results_file, &file, config.display_options()),
+    }
+    Ok(())
+}
+
+fn top(cli_options: TopCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    let file = results::read(&cli_options.results_file)?;
+    let metric = match cli_options.metric {
+        Some(metric) => metric,
+        None => results::metric_names([&file.results])
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                anyhow::anyhow!("no metrics in {}", cli_options.results_file.display())
+            })?,
+    };
+    inspect::print_top(
+        &file.results,
+        &metric,
+        cli_options.n,
+        config.display_options(),
+    );
+    Ok(())
+}
+
+fn selftest(mut cli_options: SelftestCliOptions, config: &config::Config) -> anyhow::Result<()> {
+    cli_options.run.apply_config(config)?;
+    let run = &mut cli_options.run;
+    run.iterations = run.iterations.or(Some(selftest::DEFAULT_ITERATIONS));
+    let res = record::measure(&selftest::benchmarks(), run)?;
+    selftest::print(&res, config.display_options());
+    Ok(())
+}
+
+fn validate(cli_options: ValidateCliOptions) -> anyhow::Result<()> {
+    let path = &cli_options.results_file;
+    let file = results::read(path)?;
+    let raw: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let mut issues = validate::check_units(&raw);
+    issues.extend(validate::check(&file));
+    for issue in &issues {
+        println!("{issue}");
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == validate::Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!(
+            "{} is not safe to use as a baseline: {errors} error(s)",
+            path.display()
+        );
+    }
+    match issues.len() {
+        0 => println!("{} is safe to use as a baseline", path.display()),
+        warnings => println!(
+            "{} is safe to use as a baseline, with {warnings} warning(s)",
+            path.display()
+        ),
+    }
     Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    // let mut counter_group = prf::Group::new()?;
+    // Logged to the standard error, not to mix with results printed in other formats.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
     let cli_options = CliOptions::parse();
+    if let Command::Completions(opts) = &cli_options.command {
+        completions::print(opts.shell);
+        return Ok(());
+    }
+    let mut config = config::load(cli_options.config.as_deref())?;
+    if cli_options.raw_numbers {
+        config.raw_numbers = Some(true);
+    }
+    config.time_unit = cli_options.time_unit.or(config.time_unit);
     match cli_options.command {
-        Command::Record(cli_options) => record(cli_options),
-        Command::Compare(cli_options) => compare(cli_options),
+        Command::Record(cli_options) => record(*cli_options, &config),
+        Command::Compare(cli_options) => compare(cli_options, &config),
+        Command::Watch(cli_options) => watch(cli_options, &config),
+        Command::Inspect(cli_options) => inspect(cli_options, &config),
+        Command::Top(cli_options) => top(cli_options, &config),
+        Command::Validate(cli_options) => validate(cli_options),
+        Command::CompareRevs(cli_options) => compare_revs(cli_options, &config),
+        Command::Bisect(cli_options) => bisect(cli_options, &config),
+        Command::Upload(cli_options) => upload(cli_options),
+        Command::Fetch(cli_options) => fetch(cli_options, &config),
+        Command::CorpusStats(cli_options) => corpus_stats(cli_options, &config),
+        Command::Import(cli_options) => import(cli_options),
+        Command::Export(cli_options) => export(cli_options),
+        Command::ExportSamples(cli_options) => export_samples(cli_options),
+        Command::History(cli_options) => history(cli_options, &config),
+        Command::Trend(cli_options) => trend(cli_options, &config),
+        Command::Changepoints(cli_options) => changepoints(cli_options, &config),
+        Command::Query(cli_options) => query(cli_options, &config),
+        Command::Pivot(cli_options) => pivot(cli_options, &config),
+        Command::Serve(cli_options) => dashboard::serve(
+            &history::History::open(cli_options.history_dir, &config)?,
+            &cli_options.address,
+            cli_options.threshold.or(config.threshold).unwrap_or(0.1),
+            config.display_options(),
+        ),
+        Command::Selftest(cli_options) => selftest(cli_options, &config),
+        Command::Schema => {
+            println!("{}", serde_json::to_string_pretty(&schema::results_file())?);
+            Ok(())
+        }
+        Command::Completions(_) => unreachable!("handled before loading the configuration"),
     }
 }
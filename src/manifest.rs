@@ -0,0 +1,132 @@
+//! Manifests listing benchmarks explicitly, for those that are not an input file passed to a
+//! common command.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    child, hash,
+    record::{Benchmark, KeyFormat, Repetitions},
+};
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Command of the entries that don't specify their own.
+    pub command: Option<String>,
+    /// Shell command building the benchmarked programs before recording.
+    pub build: Option<String>,
+    benchmarks: Vec<Entry>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    /// Key of the results, derived from `input` with the key format if absent.
+    name: Option<String>,
+    command: Option<String>,
+    /// File passed as last argument to the command, relative to the manifest.
+    input: Option<PathBuf>,
+    /// SHA-256 of the standard output of a correct run.
+    expected_stdout_sha256: Option<String>,
+    /// File holding the standard output of a correct run, relative to the manifest.
+    expected_output: Option<PathBuf>,
+    /// Annotation shown with the results, e.g. "IO-bound".
+    note: Option<String>,
+    /// Iterations and warm-up runs of this benchmark, instead of those of the options.
+    iterations: Option<u32>,
+    warmup: Option<u32>,
+    /// Duration after which no further iteration of this benchmark starts, e.g. "10s".
+    #[serde(alias = "max-time")]
+    max_time: Option<String>,
+    /// Reason not to measure this benchmark, which is recorded as skipped.
+    skip: Option<String>,
+}
+
+/// Reads a notes file, a JSON object of annotations by benchmark key, and sets the note of
+/// the `benchmarks` it lists over that of their manifest entry.
+pub fn apply_notes(path: &Path, benchmarks: &mut [Benchmark]) -> anyhow::Result<()> {
+    let file = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+    let mut notes: BTreeMap<String, String> = serde_json::from_str(&file)
+        .map_err(|e| anyhow::anyhow!("invalid notes file {}: {e}", path.display()))?;
+    for benchmark in benchmarks {
+        if let Some(note) = notes.remove(&benchmark.key) {
+            benchmark.note = Some(note);
+        }
+    }
+    if !notes.is_empty() {
+        let keys: Vec<&str> = notes.keys().map(String::as_str).collect();
+        tracing::warn!(
+            "{} has notes of benchmarks that are not recorded: {}",
+            path.display(),
+            keys.join(", ")
+        );
+    }
+    Ok(())
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::read_to_string(path)?;
+        let mut manifest: Manifest = serde_json::from_str(&file)
+            .map_err(|e| anyhow::anyhow!("invalid manifest {}: {e}", path.display()))?;
+        manifest.dir = path.parent().unwrap_or(Path::new("")).to_owned();
+        Ok(manifest)
+    }
+
+    pub fn benchmarks(&self, key_format: &KeyFormat) -> anyhow::Result<Vec<Benchmark>> {
+        let mut benchmarks = Vec::new();
+        for (i, entry) in self.benchmarks.iter().enumerate() {
+            let input = entry.input.as_ref().map(|input| self.dir.join(input));
+            let key = match (&entry.name, &input) {
+                (Some(name), _) => name.clone(),
+                (None, Some(input)) => key_format.render(input)?,
+                (None, None) => anyhow::bail!("manifest entry {i} has neither a name nor an input"),
+            };
+            let command = entry
+                .command
+                .clone()
+                .or_else(|| self.command.clone())
+                .ok_or_else(|| anyhow::anyhow!("manifest entry {key:?} has no command"))?;
+            let expected_stdout =
+                match (&entry.expected_stdout_sha256, &entry.expected_output) {
+                    (Some(_), Some(_)) => anyhow::bail!(
+                        "manifest entry {key:?} has both an expected output and its hash"
+                    ),
+                    (Some(hash), None) => Some(hash.to_ascii_lowercase()),
+                    (None, Some(output)) => {
+                        let output = self.dir.join(output);
+                        Some(hash::sha256_file(&output).map_err(|e| {
+                            anyhow::anyhow!("cannot read {}: {e}", output.display())
+                        })?)
+                    }
+                    (None, None) => None,
+                };
+            let max_time = entry
+                .max_time
+                .as_deref()
+                .map(child::parse_duration)
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("manifest entry {key:?}: {e}"))?;
+            benchmarks.push(Benchmark {
+                key,
+                command,
+                input,
+                expected_stdout,
+                note: entry.note.clone(),
+                repetitions: Repetitions {
+                    iterations: entry.iterations,
+                    warmup: entry.warmup,
+                    max_time,
+                },
+                skip: entry.skip.clone(),
+            });
+        }
+        Ok(benchmarks)
+    }
+}
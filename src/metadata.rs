@@ -0,0 +1,344 @@
+//! Description of the machine and environment a results file was recorded on.
+
+use std::{collections::BTreeMap, path::Path, process::Command};
+
+fn read_trimmed(path: &str) -> Option<String> {
+    Some(std::fs::read_to_string(path).ok()?.trim().to_owned())
+}
+
+fn cpu_model() -> Option<String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == "model name").then(|| value.trim().to_owned())
+    })
+}
+
+fn aslr() -> Option<String> {
+    Some(
+        match read_trimmed("/proc/sys/kernel/randomize_va_space")?.as_str() {
+            "0" => "disabled",
+            "1" => "partial",
+            "2" => "full",
+            other => return Some(other.to_owned()),
+        }
+        .to_owned(),
+    )
+}
+
+/// Formats a time as RFC 3339 in UTC.
+fn format_utc(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch, after Howard Hinnant's algorithm.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parses a time formatted by `format_utc`, as seconds since the epoch.
+pub fn parse_utc(date: &str) -> Option<i64> {
+    let (date, time) = date.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    // Days since the epoch of a civil date, the inverse of the algorithm of `format_utc`.
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Build step run before recording.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Build {
+    pub command: String,
+    /// SHA-256 of each benchmarked program after the build, by path.
+    pub binaries: BTreeMap<String, String>,
+}
+
+/// Revision of the git repository the benchmarked code was built from.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Git {
+    pub commit: String,
+    /// `None` on a detached HEAD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Whether tracked files had uncommitted changes.
+    #[serde(default)]
+    pub dirty: bool,
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+impl Git {
+    /// Revision of the repository of the first of `binaries` inside one, or else of the
+    /// current directory.
+    pub fn detect<'a>(binaries: impl IntoIterator<Item = &'a Path>) -> Option<Self> {
+        let dirs = binaries
+            .into_iter()
+            .filter_map(Path::parent)
+            .chain([Path::new(".")]);
+        for dir in dirs {
+            let Some(commit) = git(dir, &["rev-parse", "HEAD"]) else {
+                continue;
+            };
+            return Some(Self {
+                commit,
+                branch: git(dir, &["symbolic-ref", "--short", "-q", "HEAD"]),
+                dirty: git(dir, &["status", "--porcelain", "--untracked-files=no"])
+                    .is_some_and(|status| !status.is_empty()),
+            });
+        }
+        None
+    }
+
+    /// Abbreviated commit, marked if the tree was dirty.
+    pub fn short(&self) -> String {
+        let commit = &self.commit[..self.commit.len().min(7)];
+        if self.dirty {
+            format!("{commit}-dirty")
+        } else {
+            commit.to_owned()
+        }
+    }
+}
+
+impl std::fmt::Display for Git {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.short())?;
+        if let Some(branch) = &self.branch {
+            write!(f, " ({branch})")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Metadata {
+    pub hostname: Option<String>,
+    pub cpu_model: Option<String>,
+    pub cores: Option<u64>,
+    pub kernel: Option<String>,
+    /// Frequency scaling governor of the first CPU.
+    pub governor: Option<String>,
+    pub aslr: Option<String>,
+    pub date: String,
+    pub version: String,
+    /// CPUs of each NUMA node, on machines with several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numa: Option<String>,
+    /// NUMA node the benchmarks were bound to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numa_node: Option<usize>,
+    /// Nominal frequency of the CPU in MHz, that of its reference cycles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nominal_mhz: Option<f64>,
+    /// Efficiency CPUs of a hybrid CPU, e.g. `8-15`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub efficiency_cpus: Option<String>,
+    /// Type of the cores of a hybrid CPU the benchmarks were pinned to, `p` or `e`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<Git>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<Build>,
+    /// User-provided labels of the recording, such as `turbo=off`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Subset of the corpus that was recorded, for quick runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<crate::sampling::Sample>,
+    /// Number of runs of the day whose medians these results are, when compacted in the
+    /// history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate_of: Option<usize>,
+    /// Metadata of each machine of a recording sharded over SSH, by destination, the rest of
+    /// the metadata being that of the first one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, Metadata>,
+}
+
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    let (key, value) = tag
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {tag:?}"))?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+impl Metadata {
+    pub fn collect() -> Self {
+        let cores = match unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) } {
+            n if n > 0 => Some(n as u64),
+            _ => None,
+        };
+        Self {
+            hostname: read_trimmed("/proc/sys/kernel/hostname"),
+            cpu_model: cpu_model(),
+            cores,
+            kernel: read_trimmed("/proc/sys/kernel/osrelease"),
+            governor: read_trimmed("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+            aslr: aslr(),
+            date: format_utc(std::time::SystemTime::now()),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            numa: crate::numa::topology(),
+            numa_node: None,
+            nominal_mhz: crate::invariant::nominal_mhz(),
+            efficiency_cpus: crate::cores::detect().map(|(_, e)| crate::cores::format_list(&e)),
+            core_type: None,
+            git: None,
+            build: None,
+            tags: BTreeMap::new(),
+            note: None,
+            sample: None,
+            aggregate_of: None,
+            hosts: BTreeMap::new(),
+        }
+    }
+
+    /// One-line summary of when and where the results were recorded, with their annotations.
+    pub fn summary(&self) -> String {
+        let host = if self.hosts.is_empty() {
+            self.hostname
+                .clone()
+                .unwrap_or_else(|| "unknown host".into())
+        } else {
+            let hosts: Vec<&str> = self.hosts.keys().map(String::as_str).collect();
+            hosts.join(", ")
+        };
+        let mut summary = format!("recorded {} on {host}", self.date);
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            summary.push_str(&format!(" [{}]", tags.join(", ")));
+        }
+        if let Some(sample) = &self.sample {
+            summary.push_str(&format!(" (sample of {sample})"));
+        }
+        if let Some(runs) = self.aggregate_of {
+            summary.push_str(&format!(" (daily aggregate of {runs} runs)"));
+        }
+        if let Some(note) = &self.note {
+            summary.push_str(&format!(": {note}"));
+        }
+        summary
+    }
+
+    /// Describes the differences with `other` that can make measurements incomparable.
+    pub fn differences(&self, other: &Metadata) -> Vec<String> {
+        if !self.hosts.is_empty() || !other.hosts.is_empty() {
+            return self.host_differences(other);
+        }
+        let mut differences = Vec::new();
+        let mut check = |name: &str, a: Option<String>, b: Option<String>| {
+            if a != b {
+                let show = |v: Option<String>| v.unwrap_or_else(|| "unknown".into());
+                differences.push(format!("{name}: {} vs {}", show(a), show(b)));
+            }
+        };
+        check("hostname", self.hostname.clone(), other.hostname.clone());
+        check("CPU model", self.cpu_model.clone(), other.cpu_model.clone());
+        check(
+            "cores",
+            self.cores.map(|n| n.to_string()),
+            other.cores.map(|n| n.to_string()),
+        );
+        check("kernel", self.kernel.clone(), other.kernel.clone());
+        check("governor", self.governor.clone(), other.governor.clone());
+        check("ASLR", self.aslr.clone(), other.aslr.clone());
+        check("NUMA nodes", self.numa.clone(), other.numa.clone());
+        if self.numa_node.is_some() || other.numa_node.is_some() {
+            check(
+                "NUMA node",
+                self.numa_node.map(|n| n.to_string()),
+                other.numa_node.map(|n| n.to_string()),
+            );
+        }
+        if self.core_type.is_some() || other.core_type.is_some() {
+            check("core type", self.core_type.clone(), other.core_type.clone());
+        }
+        if self.sample.is_some() || other.sample.is_some() {
+            let show = |sample: &Option<crate::sampling::Sample>| {
+                Some(sample.as_ref().map_or("all".to_owned(), |s| s.to_string()))
+            };
+            check("sample", show(&self.sample), show(&other.sample));
+        }
+        check(
+            "orchestrator version",
+            Some(self.version.clone()),
+            Some(other.version.clone()),
+        );
+        differences
+    }
+
+    /// The differences of sharded recordings: in their machines, and on each machine.
+    fn host_differences(&self, other: &Metadata) -> Vec<String> {
+        let hosts = |metadata: &Metadata| {
+            if metadata.hosts.is_empty() {
+                metadata
+                    .hostname
+                    .clone()
+                    .unwrap_or_else(|| "unknown".into())
+            } else {
+                metadata
+                    .hosts
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        };
+        let (hosts, other_hosts) = (hosts(self), hosts(other));
+        let mut differences = Vec::new();
+        if hosts != other_hosts {
+            differences.push(format!("hosts: {hosts} vs {other_hosts}"));
+        }
+        for (host, metadata) in &self.hosts {
+            if let Some(other) = other.hosts.get(host) {
+                differences.extend(
+                    metadata
+                        .differences(other)
+                        .into_iter()
+                        .map(|difference| format!("{host} {difference}")),
+                );
+            }
+        }
+        differences
+    }
+}
@@ -0,0 +1,955 @@
+//! The metrics that can be recorded, and the perf counters measuring them.
+
+use std::{collections::BTreeMap, time::Duration};
+
+#[cfg(not(target_os = "linux"))]
+use events::{Hardware, Software};
+#[cfg(target_os = "linux")]
+use perf_event as prf;
+#[cfg(target_os = "linux")]
+use prf::{
+    events::{Hardware, Software},
+    CountAndTime,
+};
+
+use crate::{backend, child::Outcome};
+
+pub type Metrics = BTreeMap<String, f64>;
+
+/// Serializes metrics, writing integral values as integers so that counts stay readable.
+pub fn serialize_metrics<S: serde::Serializer>(
+    metrics: &Metrics,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+    let mut map = serializer.serialize_map(Some(metrics.len()))?;
+    for (name, &value) in metrics {
+        if value.fract() == 0. && value.abs() < (1u64 << 53) as f64 {
+            map.serialize_entry(name, &(value as i64))?;
+        } else {
+            map.serialize_entry(name, &value)?;
+        }
+    }
+    map.end()
+}
+
+/// Deserializes metrics, leaving out the fields that aren't numbers, e.g. those of the measures
+/// of newer versions.
+pub fn deserialize_metrics<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Metrics, D::Error> {
+    let fields: BTreeMap<String, serde_json::Value> =
+        serde::Deserialize::deserialize(deserializer)?;
+    Ok(fields
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_f64()?)))
+        .collect())
+}
+
+/// Serializes metrics grouped by key, e.g. by thread, as [`serialize_metrics`] does.
+pub fn serialize_grouped_metrics<S: serde::Serializer>(
+    groups: &BTreeMap<String, Metrics>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    struct Group<'a>(&'a Metrics);
+    impl serde::Serialize for Group<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize_metrics(self.0, serializer)
+        }
+    }
+    serializer.collect_map(groups.iter().map(|(key, metrics)| (key, Group(metrics))))
+}
+
+/// Stand-ins for the perf events elsewhere than on Linux, naming the events of the metrics that
+/// only the Linux backends count.
+#[cfg(not(target_os = "linux"))]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+mod events {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum Hardware {
+        CPU_CYCLES,
+        INSTRUCTIONS,
+        CACHE_REFERENCES,
+        CACHE_MISSES,
+        BRANCH_INSTRUCTIONS,
+        BRANCH_MISSES,
+        BUS_CYCLES,
+        STALLED_CYCLES_FRONTEND,
+        STALLED_CYCLES_BACKEND,
+        REF_CPU_CYCLES,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum Software {
+        CPU_CLOCK,
+        PAGE_FAULTS_MIN,
+        PAGE_FAULTS_MAJ,
+        ALIGNMENT_FAULTS,
+        EMULATION_FAULTS,
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+enum Event {
+    Hardware(Hardware),
+    Software(Software),
+    /// CPU time of the benchmark process tree, counted by the task clock, or taken from its
+    /// resource usage by the portable backend.
+    TaskClock,
+    /// Elapsed real time between the start and the end of the benchmark process.
+    WallTime,
+    /// Peak resident set size of the benchmark process.
+    MaxRss,
+    /// Field of the I/O accounting of the benchmark process tree in `/proc/<pid>/io`.
+    Io(&'static str),
+    /// Sum of events of Valgrind's cache simulation.
+    Valgrind(&'static [&'static str]),
+    /// Instructions counted by QEMU's instruction-counting plugin.
+    QemuInstructions,
+    /// Kernel activity of the benchmark process tree traced with eBPF, whatever the backend.
+    Ebpf,
+    /// Size of a file of the benchmark, taken once rather than measured during the runs.
+    FileSize,
+    /// Model-specific counter of a class of instructions, by CPU vendor.
+    Raw(RawEvent),
+}
+
+/// Raw configs of a model-specific event on each CPU vendor that has one.
+#[derive(Clone, Copy)]
+struct RawEvent {
+    /// Event and unit mask of Intel CPUs since Skylake.
+    intel: Option<u64>,
+    /// Event and unit mask of AMD CPUs since Zen 2.
+    amd: Option<u64>,
+    /// Common event of the Arm PMUv3 architecture.
+    arm: Option<u64>,
+}
+
+impl RawEvent {
+    /// The config of the event on this CPU, if it has one.
+    fn config(&self) -> Option<u64> {
+        if cfg!(target_arch = "aarch64") {
+            return self.arm;
+        }
+        let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+        let vendor = cpuinfo.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            (name.trim() == "vendor_id").then(|| value.trim())
+        })?;
+        match vendor {
+            "GenuineIntel" => self.intel,
+            "AuthenticAMD" => self.amd,
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Count,
+    Nanoseconds,
+    Bytes,
+}
+
+pub struct Metric {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub unit: Unit,
+    event: Event,
+}
+
+pub const METRICS: &[Metric] = &[
+    Metric {
+        name: "ref_cycles",
+        description: "CPU cycles at the reference frequency, unaffected by frequency scaling",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::REF_CPU_CYCLES),
+    },
+    Metric {
+        name: "instructions",
+        description: "Retired instructions",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::INSTRUCTIONS),
+    },
+    Metric {
+        name: "cpu_time",
+        description: "CPU time of the benchmark and its children, in nanoseconds",
+        unit: Unit::Nanoseconds,
+        event: Event::TaskClock,
+    },
+    Metric {
+        name: "cycles",
+        description: "CPU cycles at the actual frequency",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::CPU_CYCLES),
+    },
+    Metric {
+        name: "cache_references",
+        description: "Last level cache accesses",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::CACHE_REFERENCES),
+    },
+    Metric {
+        name: "cache_misses",
+        description: "Last level cache misses",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::CACHE_MISSES),
+    },
+    Metric {
+        name: "branches",
+        description: "Retired branch instructions",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::BRANCH_INSTRUCTIONS),
+    },
+    Metric {
+        name: "branch_misses",
+        description: "Mispredicted branch instructions",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::BRANCH_MISSES),
+    },
+    Metric {
+        name: "bus_cycles",
+        description: "Bus cycles",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::BUS_CYCLES),
+    },
+    Metric {
+        name: "stalled_cycles_frontend",
+        description: "Cycles stalled in the frontend",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::STALLED_CYCLES_FRONTEND),
+    },
+    Metric {
+        name: "stalled_cycles_backend",
+        description: "Cycles stalled in the backend",
+        unit: Unit::Count,
+        event: Event::Hardware(Hardware::STALLED_CYCLES_BACKEND),
+    },
+    Metric {
+        name: "fp_ops",
+        description: "Retired scalar floating-point arithmetic instructions (operations on AMD, \
+                      speculatively executed on Arm)",
+        unit: Unit::Count,
+        event: Event::Raw(RawEvent {
+            intel: Some(0x03c7),
+            amd: Some(0xff03),
+            arm: Some(0x75),
+        }),
+    },
+    Metric {
+        name: "simd_ops",
+        description: "Retired packed floating-point arithmetic instructions on Intel, SSE and \
+                      AVX instructions on AMD, and speculatively executed Advanced SIMD ones on \
+                      Arm",
+        unit: Unit::Count,
+        event: Event::Raw(RawEvent {
+            intel: Some(0xfcc7),
+            amd: Some(0x04cb),
+            arm: Some(0x74),
+        }),
+    },
+    Metric {
+        name: "loads",
+        description: "Retired load instructions (dispatched on AMD)",
+        unit: Unit::Count,
+        event: Event::Raw(RawEvent {
+            intel: Some(0x81d0),
+            amd: Some(0x0129),
+            arm: Some(0x06),
+        }),
+    },
+    Metric {
+        name: "stores",
+        description: "Retired store instructions (dispatched on AMD)",
+        unit: Unit::Count,
+        event: Event::Raw(RawEvent {
+            intel: Some(0x82d0),
+            amd: Some(0x0229),
+            arm: Some(0x07),
+        }),
+    },
+    Metric {
+        name: "cpu_clock",
+        description: "CPU time measured by a software timer, in nanoseconds, available \
+                      without hardware counters",
+        unit: Unit::Nanoseconds,
+        event: Event::Software(Software::CPU_CLOCK),
+    },
+    Metric {
+        name: "alignment_faults",
+        description: "Unaligned memory accesses the kernel fixed up, on architectures without \
+                      hardware support for them",
+        unit: Unit::Count,
+        event: Event::Software(Software::ALIGNMENT_FAULTS),
+    },
+    Metric {
+        name: "emulation_faults",
+        description: "Unimplemented instructions the kernel emulated",
+        unit: Unit::Count,
+        event: Event::Software(Software::EMULATION_FAULTS),
+    },
+    Metric {
+        name: "minor_faults",
+        description: "Page faults served without I/O, e.g. from the page cache",
+        unit: Unit::Count,
+        event: Event::Software(Software::PAGE_FAULTS_MIN),
+    },
+    Metric {
+        name: "major_faults",
+        description: "Page faults that read from storage, a sign of inputs missing from the \
+                      page cache",
+        unit: Unit::Count,
+        event: Event::Software(Software::PAGE_FAULTS_MAJ),
+    },
+    Metric {
+        name: "wall_time",
+        description: "Elapsed real time, in nanoseconds",
+        unit: Unit::Nanoseconds,
+        event: Event::WallTime,
+    },
+    Metric {
+        name: "max_rss",
+        description: "Peak resident set size, in bytes",
+        unit: Unit::Bytes,
+        event: Event::MaxRss,
+    },
+    Metric {
+        name: "read_bytes",
+        description: "Bytes the benchmark process tree caused to be read from storage",
+        unit: Unit::Bytes,
+        event: Event::Io("read_bytes"),
+    },
+    Metric {
+        name: "write_bytes",
+        description: "Bytes the benchmark process tree caused to be written to storage",
+        unit: Unit::Bytes,
+        event: Event::Io("write_bytes"),
+    },
+    Metric {
+        name: "cancelled_write_bytes",
+        description: "Bytes of writes cancelled by truncating dirty page cache, e.g. of deleted \
+                      files",
+        unit: Unit::Bytes,
+        event: Event::Io("cancelled_write_bytes"),
+    },
+    Metric {
+        name: "cg_instructions",
+        description: "Instructions executed in user space, as counted by Valgrind",
+        unit: Unit::Count,
+        event: Event::Valgrind(&["Ir"]),
+    },
+    Metric {
+        name: "cg_data_reads",
+        description: "Memory reads, as counted by Valgrind",
+        unit: Unit::Count,
+        event: Event::Valgrind(&["Dr"]),
+    },
+    Metric {
+        name: "cg_data_writes",
+        description: "Memory writes, as counted by Valgrind",
+        unit: Unit::Count,
+        event: Event::Valgrind(&["Dw"]),
+    },
+    Metric {
+        name: "cg_l1_misses",
+        description: "First level instruction and data cache misses, as simulated by Valgrind",
+        unit: Unit::Count,
+        event: Event::Valgrind(&["I1mr", "D1mr", "D1mw"]),
+    },
+    Metric {
+        name: "cg_ll_misses",
+        description: "Last level cache misses, as simulated by Valgrind",
+        unit: Unit::Count,
+        event: Event::Valgrind(&["ILmr", "DLmr", "DLmw"]),
+    },
+    Metric {
+        name: "qemu_instructions",
+        description: "Guest instructions executed, as counted by a QEMU user-mode emulator",
+        unit: Unit::Count,
+        event: Event::QemuInstructions,
+    },
+    Metric {
+        name: "syscalls",
+        description: "System calls made by the benchmark process tree, traced with eBPF",
+        unit: Unit::Count,
+        event: Event::Ebpf,
+    },
+    Metric {
+        name: "off_cpu_time",
+        description: "Time the benchmark process tree spent switched out of the CPU, in \
+                      nanoseconds, traced with eBPF",
+        unit: Unit::Nanoseconds,
+        event: Event::Ebpf,
+    },
+    Metric {
+        name: "block_io_bytes",
+        description: "Bytes of block I/O requests issued by the benchmark process tree, traced \
+                      with eBPF",
+        unit: Unit::Bytes,
+        event: Event::Ebpf,
+    },
+    Metric {
+        name: "input_size",
+        description: "Size of the input file, in bytes",
+        unit: Unit::Bytes,
+        event: Event::FileSize,
+    },
+    Metric {
+        name: "compiled_size",
+        description: "Size of the output of the compile phase with --compile-cmd, in bytes",
+        unit: Unit::Bytes,
+        event: Event::FileSize,
+    },
+];
+
+pub const DEFAULT_METRICS: &[&str] = &["ref_cycles", "instructions", "cpu_time"];
+
+/// Default metrics of the portable backend, which has no counters.
+pub const PORTABLE_METRICS: &[&str] = &["wall_time", "cpu_time", "max_rss"];
+
+/// Default metrics of the Valgrind-based backends.
+pub const VALGRIND_METRICS: &[&str] = &["cg_instructions", "cg_l1_misses", "cg_ll_misses"];
+
+impl Metric {
+    /// Whether the metric is derived from how the process ended rather than counted by perf.
+    pub fn is_outcome(&self) -> bool {
+        matches!(self.event, Event::WallTime | Event::MaxRss | Event::Io(_))
+    }
+
+    /// Whether the metric can be measured on the cgroup of a container, rather than on the
+    /// container runtime's process.
+    pub fn in_container(&self) -> bool {
+        matches!(
+            self.event,
+            Event::Hardware(_)
+                | Event::Software(_)
+                | Event::Raw(_)
+                | Event::TaskClock
+                | Event::WallTime
+        )
+    }
+
+    /// Whether the metric is traced with eBPF alongside any backend.
+    pub fn is_ebpf(&self) -> bool {
+        matches!(self.event, Event::Ebpf)
+    }
+
+    /// Whether the metric is measured by the backend, rather than alongside it.
+    pub fn is_from_backend(&self) -> bool {
+        !matches!(self.event, Event::Ebpf | Event::FileSize)
+    }
+
+    /// Whether a zero value is plausible, rather than a sign of a broken measurement.
+    pub fn may_be_zero(&self) -> bool {
+        matches!(
+            self.event,
+            Event::Ebpf
+                | Event::Io(_)
+                | Event::FileSize
+                // Classes of instructions a benchmark may not use.
+                | Event::Raw(_)
+                | Event::Software(
+                    Software::ALIGNMENT_FAULTS
+                        | Software::EMULATION_FAULTS
+                        | Software::PAGE_FAULTS_MAJ
+                )
+        )
+    }
+
+    pub fn supported_by(&self, backend: backend::Kind) -> bool {
+        match backend {
+            backend::Kind::Perf => {
+                !matches!(self.event, Event::Valgrind(_) | Event::QemuInstructions)
+            }
+            backend::Kind::Portable => self.is_outcome() || matches!(self.event, Event::TaskClock),
+            backend::Kind::PerfStat => self.perf_event_name().is_some(),
+            backend::Kind::Cachegrind | backend::Kind::Callgrind => {
+                matches!(self.event, Event::Valgrind(_))
+            }
+            backend::Kind::Qemu => matches!(self.event, Event::QemuInstructions),
+        }
+    }
+
+    /// Name of the event counting the metric for the `perf` tool, which for the CPU time is
+    /// the clock of the benchmarked task.
+    pub fn perf_event_name(&self) -> Option<String> {
+        match self.event {
+            Event::Hardware(_) | Event::Software(_) => Some(self.name.replace('_', "-")),
+            Event::TaskClock => Some("task-clock".to_owned()),
+            Event::Raw(raw) => Some(format!("r{:x}", raw.config()?)),
+            Event::WallTime
+            | Event::MaxRss
+            | Event::Io(_)
+            | Event::Valgrind(_)
+            | Event::QemuInstructions
+            | Event::Ebpf
+            | Event::FileSize => None,
+        }
+    }
+
+    /// Valgrind events summed into the metric, if it is measured by Valgrind.
+    pub fn valgrind_events(&self) -> &'static [&'static str] {
+        match self.event {
+            Event::Valgrind(events) => events,
+            _ => &[],
+        }
+    }
+
+    /// Value of a portable metric for a run that took `wall_time` and ended with `outcome`.
+    pub fn outcome_value(&self, outcome: &Outcome, wall_time: Duration) -> Option<f64> {
+        match self.event {
+            Event::WallTime => Some(wall_time.as_nanos() as f64),
+            Event::MaxRss => Some(outcome.max_rss_bytes() as f64),
+            Event::Io(field) => outcome.io.get(field).map(|&bytes| bytes as f64),
+            Event::TaskClock => Some(outcome.cpu_time().as_nanos() as f64),
+            Event::Hardware(_)
+            | Event::Software(_)
+            | Event::Raw(_)
+            | Event::Valgrind(_)
+            | Event::QemuInstructions
+            | Event::Ebpf
+            | Event::FileSize => None,
+        }
+    }
+}
+
+pub fn lookup(name: &str) -> anyhow::Result<&'static Metric> {
+    METRICS.iter().find(|m| m.name == name).ok_or_else(|| {
+        let known: Vec<_> = METRICS
+            .iter()
+            .map(|m| format!("  {}: {}", m.name, m.description))
+            .collect();
+        anyhow::anyhow!(
+            "unknown metric {name:?}, expected one of:\n{}",
+            known.join("\n")
+        )
+    })
+}
+
+/// Formats `value` with three significant digits.
+fn significant(value: f64) -> String {
+    let magnitude = if value == 0. {
+        0
+    } else {
+        value.abs().log10().floor() as i32
+    };
+    let decimals = (2 - magnitude).max(0) as usize;
+    format!("{value:.decimals$}")
+}
+
+/// Formats `value` with the largest of `prefixes` (each worth a thousand times the previous
+/// one) that keeps it at least 1.
+fn with_prefix(value: f64, prefixes: &[&str]) -> String {
+    let mut scaled = value;
+    let mut prefix = 0;
+    while scaled.abs() >= 999.5 && prefix + 1 < prefixes.len() {
+        scaled /= 1000.;
+        prefix += 1;
+    }
+    format!("{} {}", significant(scaled), prefixes[prefix])
+}
+
+fn with_separators(value: f64) -> String {
+    let digits = format!("{:.0}", value.abs());
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if value < 0. {
+        grouped.insert(0, '-');
+    }
+    grouped
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl TimeUnit {
+    fn nanoseconds(self) -> f64 {
+        match self {
+            TimeUnit::Ns => 1.,
+            TimeUnit::Us => 1e3,
+            TimeUnit::Ms => 1e6,
+            TimeUnit::S => 1e9,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            TimeUnit::Ns => "ns",
+            TimeUnit::Us => "µs",
+            TimeUnit::Ms => "ms",
+            TimeUnit::S => "s",
+        }
+    }
+}
+
+impl Unit {
+    /// Name of the unit in results files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::Count => "count",
+            Unit::Nanoseconds => "ns",
+            Unit::Bytes => "bytes",
+        }
+    }
+}
+
+/// Phases of the benchmarks measured separately with `--compile-cmd` and `--run-cmd`, whose
+/// metrics are prefixed with the phase, as in `compile.cpu_time`.
+pub const PHASES: [&str; 2] = ["compile", "run"];
+
+/// Splits the phase off the name of a recorded metric.
+pub fn split_phase(name: &str) -> (Option<&str>, &str) {
+    match name.split_once('.') {
+        Some((phase, metric)) if PHASES.contains(&phase) => (Some(phase), metric),
+        _ => (None, name),
+    }
+}
+
+/// The metric recorded under `name`, in a phase or not, if known to this version.
+pub fn known(name: &str) -> Option<&'static Metric> {
+    lookup(split_phase(name).1).ok()
+}
+
+/// Unit of the metric `name`, counts for the metrics unknown to this version.
+pub fn unit(name: &str) -> Unit {
+    known(name).map_or(Unit::Count, |metric| metric.unit)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DisplayOptions {
+    /// Print the values as recorded rather than scaled to a readable unit.
+    pub raw: bool,
+    /// Unit all times are converted to, instead of the most readable one for each.
+    pub time_unit: Option<TimeUnit>,
+    /// Whether the values are ratios to a reference benchmark, without unit.
+    pub ratios: bool,
+}
+
+/// Column header of the metric `name`, with the unit its values are displayed in if all of
+/// them share it.
+pub fn header(name: &str, opts: DisplayOptions) -> String {
+    if opts.ratios {
+        return name.to_owned();
+    }
+    match (unit(name), opts.time_unit) {
+        (Unit::Nanoseconds, Some(time_unit)) => format!("{name} ({})", time_unit.symbol()),
+        (Unit::Nanoseconds, None) if opts.raw => format!("{name} (ns)"),
+        (Unit::Bytes, _) if opts.raw => format!("{name} (bytes)"),
+        _ => name.to_owned(),
+    }
+}
+
+/// Renders a value of the metric `name` for display.
+pub fn display(name: &str, value: f64, opts: DisplayOptions) -> String {
+    match (unit(name), opts.time_unit) {
+        _ if opts.ratios && opts.raw => value.to_string(),
+        _ if opts.ratios => format!("{value:.3}x"),
+        (Unit::Nanoseconds, Some(time_unit)) if opts.raw => {
+            (value / time_unit.nanoseconds()).to_string()
+        }
+        (Unit::Nanoseconds, Some(time_unit)) => significant(value / time_unit.nanoseconds()),
+        _ if opts.raw => value.to_string(),
+        (Unit::Count, _) if value.abs() < 1e6 => with_separators(value),
+        (Unit::Count, _) => with_prefix(value, &["", "K", "M", "G", "T", "P"]),
+        (Unit::Nanoseconds, None) => with_prefix(value, &["ns", "µs", "ms", "s"]),
+        (Unit::Bytes, _) => with_prefix(value, &["B", "kB", "MB", "GB", "TB"]),
+    }
+}
+
+/// The count of a multiplexed counter extrapolated to the time it was enabled, or `None` if
+/// it never ran, the others taking the hardware all along.
+#[cfg(target_os = "linux")]
+fn scale(
+    CountAndTime {
+        count,
+        time_enabled,
+        time_running,
+    }: CountAndTime,
+) -> Option<u64> {
+    if time_running == 0 && time_enabled > 0 {
+        None
+    } else if time_running < time_enabled {
+        Some(((count as u128) * (time_enabled as u128) / (time_running as u128)) as u64)
+    } else {
+        Some(count)
+    }
+}
+
+/// Counters measuring a set of metrics on the children of this process.
+#[cfg(target_os = "linux")]
+pub struct Counters {
+    counters: Vec<(&'static str, prf::Counter)>,
+    /// Whether the counters are enabled when the children exec.
+    on_exec: bool,
+    /// Requested metrics whose counters could not be opened, e.g. inside a virtual machine.
+    pub unavailable: Vec<&'static str>,
+}
+
+/// Builder of the perf counter measuring `event`, if one does.
+#[cfg(target_os = "linux")]
+fn builder(event: &Event) -> Option<prf::Builder<'static>> {
+    match event {
+        Event::Hardware(hw) => Some(prf::Builder::new(*hw)),
+        Event::Software(sw) => Some(prf::Builder::new(*sw)),
+        Event::TaskClock => Some(prf::Builder::new(Software::TASK_CLOCK)),
+        Event::Raw(raw) => Some(prf::Builder::new(prf::events::Raw::new(raw.config()?))),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn counter(builder: &mut prf::Builder, on_exec: bool) -> std::io::Result<prf::Counter> {
+    builder.inherit(true).enable_on_exec(on_exec).build()
+}
+
+#[cfg(target_os = "linux")]
+impl Counters {
+    /// Opens counters enabled when the children exec if `on_exec`, and otherwise left disabled
+    /// until the caller enables them through [`Self::fds`].
+    pub fn open(names: &[String], on_exec: bool) -> anyhow::Result<Self> {
+        let mut counters = Vec::new();
+        let mut unavailable = Vec::new();
+        for name in names {
+            let metric = lookup(name)?;
+            match metric.event {
+                Event::Valgrind(_) => anyhow::bail!("{name} is only measured under Valgrind"),
+                Event::QemuInstructions => anyhow::bail!("{name} is only measured under QEMU"),
+                _ => {}
+            }
+            let Some(mut builder) = builder(&metric.event) else {
+                if matches!(metric.event, Event::Raw(_)) {
+                    tracing::warn!("this CPU has no known {name} counter, leaving it out");
+                    unavailable.push(metric.name);
+                }
+                continue;
+            };
+            match counter(&mut builder, on_exec) {
+                Ok(counter) => counters.push((metric.name, counter)),
+                Err(e) => {
+                    tracing::warn!("cannot open the {name} counter ({e}), leaving it out");
+                    unavailable.push(metric.name);
+                }
+            }
+        }
+        if counters.is_empty() && !unavailable.is_empty() {
+            tracing::warn!("no requested counter is available, measuring cpu_clock instead");
+            counters.push((
+                "cpu_clock",
+                counter(&mut prf::Builder::new(Software::CPU_CLOCK), on_exec)?,
+            ));
+        }
+        Ok(Self {
+            counters,
+            on_exec,
+            unavailable,
+        })
+    }
+
+    /// Opens the counters again for a new run: the counts and times that exited children
+    /// inherited are added to them, and survive resetting them.
+    pub fn reopen(&mut self) -> std::io::Result<()> {
+        for (name, counter) in &mut self.counters {
+            let mut builder = known(name)
+                .and_then(|metric| builder(&metric.event))
+                .unwrap_or_else(|| unreachable!("{name} is counted by a perf event"));
+            *counter = self::counter(&mut builder, self.on_exec)?;
+        }
+        Ok(())
+    }
+
+    /// Opens disabled counters of the processes of `cgroup`, one per CPU of `cpus` whose counts
+    /// are summed.
+    pub fn open_cgroup(
+        names: &[String],
+        cgroup: &std::fs::File,
+        cpus: &[usize],
+    ) -> anyhow::Result<Self> {
+        let mut counters = Vec::new();
+        let mut unavailable = Vec::new();
+        for name in names {
+            let metric = lookup(name)?;
+            let builder = || builder(&metric.event);
+            if builder().is_none() {
+                if matches!(metric.event, Event::Raw(_)) {
+                    tracing::warn!("this CPU has no known {name} counter, leaving it out");
+                    unavailable.push(metric.name);
+                }
+                continue;
+            }
+            let opened: std::io::Result<Vec<prf::Counter>> = cpus
+                .iter()
+                .map(|&cpu| {
+                    builder()
+                        .expect("a counter")
+                        .observe_cgroup(cgroup)
+                        .one_cpu(cpu)
+                        .enabled(false)
+                        .build()
+                })
+                .collect();
+            match opened {
+                Ok(opened) => counters.extend(opened.into_iter().map(|c| (metric.name, c))),
+                Err(e) => {
+                    tracing::warn!(
+                        "cannot open the {name} counter of the cgroup ({e}), leaving it out"
+                    );
+                    unavailable.push(metric.name);
+                }
+            }
+        }
+        if counters.is_empty() && !unavailable.is_empty() {
+            anyhow::bail!("no requested counter can be opened on the cgroup");
+        }
+        Ok(Self {
+            counters,
+            on_exec: false,
+            unavailable,
+        })
+    }
+
+    fn all(&mut self) -> impl Iterator<Item = &mut prf::Counter> {
+        self.counters.iter_mut().map(|(_, c)| c)
+    }
+
+    /// File descriptors of the counters, for enabling and disabling them from another thread.
+    pub fn fds(&mut self) -> Vec<std::os::fd::RawFd> {
+        self.all()
+            .map(|c| std::os::fd::AsRawFd::as_raw_fd(c))
+            .collect()
+    }
+
+    pub fn enable(&mut self) -> std::io::Result<()> {
+        self.all().try_for_each(|c| c.enable())
+    }
+
+    pub fn disable(&mut self) -> std::io::Result<()> {
+        self.all().try_for_each(|c| c.disable())
+    }
+
+    pub fn read(&mut self) -> std::io::Result<Reading> {
+        let mut metrics = Metrics::new();
+        let mut multiplexed = false;
+        for (name, counter) in &mut self.counters {
+            let count_and_time = counter.read_count_and_time()?;
+            multiplexed |= count_and_time.time_running < count_and_time.time_enabled;
+            match scale(count_and_time) {
+                Some(count) => *metrics.entry(name.to_string()).or_default() += count as f64,
+                // Like the counters that cannot be opened.
+                None if !self.unavailable.contains(name) => {
+                    tracing::warn!(
+                        "the {name} counter never ran, the others taking the hardware, leaving \
+                         it out"
+                    );
+                    self.unavailable.push(name);
+                }
+                None => {}
+            }
+        }
+        Ok(Reading {
+            metrics,
+            multiplexed,
+            ..Reading::default()
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Reading {
+    pub metrics: Metrics,
+    /// Whether some counters shared the hardware with others and were scaled from the time
+    /// they actually ran, making them estimates.
+    pub multiplexed: bool,
+    /// Instructions executed in each function, for backends that attribute them.
+    pub functions: Metrics,
+}
+
+/// Median of each metric over `samples`.
+pub fn median(samples: &[Metrics]) -> Metrics {
+    let mut values: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for sample in samples {
+        for (name, &value) in sample {
+            values.entry(name).or_default().push(value);
+        }
+    }
+    values
+        .into_iter()
+        .map(|(name, values)| (name.to_owned(), median_of(values)))
+        .collect()
+}
+
+/// Median of `values`, which must not be empty.
+pub fn median_of(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.
+    } else {
+        values[mid]
+    }
+}
+
+/// Median of `values` counted as many times as their weight, which must not all be zero.
+pub fn weighted_median(mut values: Vec<(f64, usize)>) -> f64 {
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: usize = values.iter().map(|&(_, weight)| weight).sum();
+    // The value at `index` of the values repeated.
+    let nth = |index: usize| {
+        let mut seen = 0;
+        values
+            .iter()
+            .find(|&&(_, weight)| {
+                seen += weight;
+                seen > index
+            })
+            .expect("the index is below the total weight")
+            .0
+    };
+    (nth((total - 1) / 2) + nth(total / 2)) / 2.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    fn count(count: u64, time_enabled: u64, time_running: u64) -> CountAndTime {
+        CountAndTime {
+            count,
+            time_enabled,
+            time_running,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn scale_multiplexed_counts() {
+        assert_eq!(scale(count(100, 10, 10)), Some(100));
+        assert_eq!(scale(count(100, 10, 5)), Some(200));
+        assert_eq!(scale(count(0, 0, 0)), Some(0));
+        assert_eq!(scale(count(0, 10, 0)), None);
+    }
+
+    #[test]
+    fn weighted_medians() {
+        assert_eq!(weighted_median(vec![(3., 1), (1., 1), (2., 1)]), 2.);
+        assert_eq!(weighted_median(vec![(1., 1), (2., 1)]), 1.5);
+        assert_eq!(weighted_median(vec![(10., 1), (1., 5), (2., 1)]), 1.);
+        assert_eq!(weighted_median(vec![(1., 2), (3., 2)]), 2.);
+        assert_eq!(weighted_median(vec![(1., 0), (4., 3)]), 4.);
+        assert_eq!(
+            weighted_median(vec![(5., 2), (1., 1), (7., 3)]),
+            median_of(vec![5., 5., 1., 7., 7., 7.])
+        );
+    }
+}
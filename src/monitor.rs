@@ -0,0 +1,344 @@
+//! Sampling of the machine's background activity, CPU frequency and the CPUs the benchmark
+//! ran on around a measured run.
+
+use std::{collections::BTreeSet, sync::mpsc, time::Duration};
+
+/// Results with an interference score above this are flagged as taken on a busy machine.
+pub const INTERFERENCE_THRESHOLD: f64 = 0.05;
+
+#[derive(Clone, Copy)]
+struct CpuStat {
+    busy: u64,
+    total: u64,
+}
+
+#[cfg(target_os = "linux")]
+fn read_cpu_stat() -> std::io::Result<CpuStat> {
+    let stat = std::fs::read_to_string("/proc/stat")?;
+    let fields: Vec<u64> = stat
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .skip(1)
+        .map(|f| f.parse().unwrap_or(0))
+        .collect();
+    // user nice system idle iowait irq softirq steal
+    let get = |i: usize| fields.get(i).copied().unwrap_or(0);
+    let busy = get(0) + get(1) + get(2) + get(5) + get(6) + get(7);
+    Ok(CpuStat {
+        busy,
+        total: busy + get(3) + get(4),
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn read_load_avg() -> std::io::Result<f64> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg")?;
+    Ok(loadavg
+        .split_whitespace()
+        .next()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0.))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_cpu_stat() -> std::io::Result<CpuStat> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the CPU time of the machine is only read from /proc/stat",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_load_avg() -> std::io::Result<f64> {
+    let mut load = [0.];
+    if unsafe { libc::getloadavg(load.as_mut_ptr(), 1) } != 1 {
+        return Err(std::io::Error::other("cannot read the load average"));
+    }
+    Ok(load[0])
+}
+
+fn children_cpu_time() -> Duration {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    let usage = unsafe {
+        libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr());
+        usage.assume_init()
+    };
+    let tv = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    tv(usage.ru_utime) + tv(usage.ru_stime)
+}
+
+fn clock_ticks_per_sec() -> f64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        t if t > 0 => t as f64,
+        _ => 100.,
+    }
+}
+
+/// Background load observed during one run.
+pub struct Load {
+    /// Mean of the 1-minute load average at the start and end of the run.
+    pub load_avg: f64,
+    /// Fraction of the machine's total CPU capacity used by processes other than the
+    /// benchmark while it ran.
+    pub interference: f64,
+}
+
+pub struct LoadMonitor {
+    cpu: Option<CpuStat>,
+    load_avg: Option<f64>,
+    children: Duration,
+}
+
+impl LoadMonitor {
+    /// Starts monitoring; must be called right before the benchmark child is spawned.
+    pub fn start() -> Self {
+        Self {
+            cpu: read_cpu_stat().ok(),
+            load_avg: read_load_avg().ok(),
+            children: children_cpu_time(),
+        }
+    }
+
+    /// Stops monitoring; must be called right after the benchmark child has been reaped.
+    pub fn stop(self) -> Load {
+        let child_ticks =
+            (children_cpu_time() - self.children).as_secs_f64() * clock_ticks_per_sec();
+        let interference = match (self.cpu, read_cpu_stat().ok()) {
+            (Some(before), Some(after)) if after.total > before.total => {
+                let busy = (after.busy - before.busy) as f64;
+                let total = (after.total - before.total) as f64;
+                ((busy - child_ticks) / total).clamp(0., 1.)
+            }
+            _ => 0.,
+        };
+        let load_avg = match (self.load_avg, read_load_avg().ok()) {
+            (Some(before), Some(after)) => (before + after) / 2.,
+            (before, after) => before.or(after).unwrap_or(0.),
+        };
+        Load {
+            load_avg,
+            interference,
+        }
+    }
+}
+
+/// Interval between the samples of the CPU frequency.
+const FREQUENCY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Relative difference of mean frequencies beyond which measures are not comparable.
+pub const FREQUENCY_TOLERANCE: f64 = 0.05;
+
+/// CPU frequency during runs, in MHz.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Frequency {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl Frequency {
+    /// The frequency over all the `runs`, weighting them equally.
+    pub fn combine(runs: impl IntoIterator<Item = Frequency>) -> Option<Self> {
+        let runs: Vec<Frequency> = runs.into_iter().collect();
+        if runs.is_empty() {
+            return None;
+        }
+        Some(Frequency {
+            min: runs.iter().map(|f| f.min).fold(f64::INFINITY, f64::min),
+            mean: runs.iter().map(|f| f.mean).sum::<f64>() / runs.len() as f64,
+            max: runs.iter().map(|f| f.max).fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+/// The current frequency of each CPU in MHz, by CPU, from cpufreq or else from the
+/// approximation of /proc/cpuinfo.
+#[cfg(target_os = "linux")]
+fn read_frequencies() -> Vec<(usize, f64)> {
+    let cpufreq: Vec<(usize, f64)> = std::fs::read_dir("/sys/devices/system/cpu")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let cpu = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse()
+                .ok()?;
+            let khz: f64 = std::fs::read_to_string(entry.path().join("cpufreq/scaling_cur_freq"))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+            Some((cpu, khz / 1000.))
+        })
+        .collect();
+    if !cpufreq.is_empty() {
+        return cpufreq;
+    }
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let mut cpu = None;
+    let mut frequencies = Vec::new();
+    for line in cpuinfo.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim() {
+            "processor" => cpu = value.trim().parse().ok(),
+            "cpu MHz" => frequencies.extend(cpu.zip(value.trim().parse().ok())),
+            _ => {}
+        }
+    }
+    frequencies
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_frequencies() -> Vec<(usize, f64)> {
+    Vec::new()
+}
+
+/// The mean frequency of `cpus`, or of all of them if empty.
+fn sample_frequency(cpus: &[usize]) -> Option<f64> {
+    let frequencies: Vec<f64> = read_frequencies()
+        .into_iter()
+        .filter(|(cpu, _)| cpus.is_empty() || cpus.contains(cpu))
+        .map(|(_, mhz)| mhz)
+        .collect();
+    (!frequencies.is_empty()).then(|| frequencies.iter().sum::<f64>() / frequencies.len() as f64)
+}
+
+fn sample(
+    cpus: &[usize],
+    signals: mpsc::Receiver<()>,
+    frequencies: mpsc::Sender<Option<Frequency>>,
+) {
+    while signals.recv().is_ok() {
+        let mut samples: Vec<f64> = sample_frequency(cpus).into_iter().collect();
+        loop {
+            match signals.recv_timeout(FREQUENCY_INTERVAL) {
+                Err(mpsc::RecvTimeoutError::Timeout) => samples.extend(sample_frequency(cpus)),
+                Ok(()) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let frequency = (!samples.is_empty()).then(|| Frequency {
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        });
+        if frequencies.send(frequency).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sampler of the frequency of the CPUs benchmarks run on, by a thread started before the
+/// counters are opened so that they don't count it.
+pub struct FrequencySampler {
+    signals: mpsc::Sender<()>,
+    frequencies: mpsc::Receiver<Option<Frequency>>,
+}
+
+impl FrequencySampler {
+    /// Samples the frequency of the CPUs `pin`, or of all of them if empty.
+    pub fn new(pin: &[usize]) -> Self {
+        let (signals, received) = mpsc::channel();
+        let (sender, frequencies) = mpsc::channel();
+        let cpus = pin.to_vec();
+        std::thread::spawn(move || sample(&cpus, received, sender));
+        Self {
+            signals,
+            frequencies,
+        }
+    }
+
+    /// Starts sampling, right before the benchmark is spawned.
+    pub fn start(&self) {
+        let _ = self.signals.send(());
+    }
+
+    /// The frequency since [`FrequencySampler::start`], none if it cannot be read.
+    pub fn stop(&self) -> Option<Frequency> {
+        self.signals.send(()).ok()?;
+        self.frequencies.recv().ok()?
+    }
+}
+
+/// Interval between the samples of the CPUs a benchmark runs on.
+const CPU_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The CPU each thread of the process `pid` last ran on, from the `processor` field of their
+/// `/proc/<pid>/task/<tid>/stat`.
+#[cfg(target_os = "linux")]
+pub fn read_cpus(pid: u32) -> BTreeSet<usize> {
+    std::fs::read_dir(format!("/proc/{pid}/task"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|task| {
+            let stat = std::fs::read_to_string(task.path().join("stat")).ok()?;
+            // The fields after the command name, which may contain spaces, start at the state,
+            // the third field, and the processor is the 39th.
+            let (_, fields) = stat.rsplit_once(')')?;
+            fields.split_whitespace().nth(36)?.parse().ok()
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_cpus(_pid: u32) -> BTreeSet<usize> {
+    BTreeSet::new()
+}
+
+fn sample_cpus(pids: mpsc::Receiver<Option<u32>>, cpus: mpsc::Sender<BTreeSet<usize>>) {
+    while let Ok(pid) = pids.recv() {
+        let mut seen = BTreeSet::new();
+        if let Some(pid) = pid {
+            loop {
+                seen.extend(read_cpus(pid));
+                match pids.recv_timeout(CPU_INTERVAL) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Ok(_) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        }
+        if cpus.send(seen).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sampler of the CPUs the threads of a benchmark run on, to tell which cores of a hybrid CPU
+/// each run landed on.
+pub struct CpuSampler {
+    pids: mpsc::Sender<Option<u32>>,
+    cpus: mpsc::Receiver<BTreeSet<usize>>,
+}
+
+impl CpuSampler {
+    pub fn new() -> Self {
+        let (pids, received) = mpsc::channel();
+        let (sender, cpus) = mpsc::channel();
+        std::thread::spawn(move || sample_cpus(received, sender));
+        Self { pids, cpus }
+    }
+
+    /// Starts sampling the process `pid`, right after it is spawned.
+    pub fn start(&self, pid: u32) {
+        let _ = self.pids.send(Some(pid));
+    }
+
+    /// The CPUs seen since [`CpuSampler::start`], none if it was not started.
+    pub fn stop(&self) -> BTreeSet<usize> {
+        if self.pids.send(None).is_err() {
+            return BTreeSet::new();
+        }
+        self.cpus.recv().unwrap_or_default()
+    }
+}
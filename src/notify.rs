@@ -0,0 +1,50 @@
+//! Notifications of the regressions found by `compare`.
+
+use crate::{compare::Regression, http};
+
+/// Payload of the notifications posted to webhooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Payload {
+    /// JSON summary of the comparison and its regressions
+    Json,
+    /// Slack incoming webhook message
+    Slack,
+}
+
+/// Posts the `regressions` of `compared` over `base` to the webhook at `url`.
+pub fn webhook(
+    url: &str,
+    payload: Payload,
+    (base, compared): (&str, &str),
+    threshold: f64,
+    regressions: &[Regression],
+) -> anyhow::Result<()> {
+    let body = match payload {
+        Payload::Json => serde_json::json!({
+            "base": base,
+            "compared": compared,
+            "threshold": threshold,
+            "regressions": regressions
+                .iter()
+                .map(|r| serde_json::json!({
+                    "benchmark": r.key,
+                    "metric": r.metric,
+                    "change_percent": r.change,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+        Payload::Slack => {
+            let mut text = format!(
+                ":warning: {} regression(s) beyond {threshold}% in `{compared}` over `{base}`:",
+                regressions.len()
+            );
+            for r in regressions {
+                text.push_str(&format!("\n• `{}` {} {:+.1}%", r.key, r.metric, r.change));
+            }
+            serde_json::json!({ "text": text })
+        }
+    };
+    http::json_request("POST", url, &[], Some(&body))?;
+    tracing::info!("notified {} regression(s) to {url}", regressions.len());
+    Ok(())
+}
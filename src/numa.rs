@@ -0,0 +1,82 @@
+//! NUMA nodes: benchmarks bound to the CPUs and the memory of one node don't vary with the
+//! share of their memory traffic that crosses nodes.
+
+use crate::cores;
+
+const NODES: &str = "/sys/devices/system/node";
+
+// Of linux/mempolicy.h.
+#[cfg(target_os = "linux")]
+const MPOL_BIND: libc::c_int = 2;
+
+/// Number of bits of node masks, the most nodes Linux supports.
+const MAX_NODES: usize = 1024;
+
+/// The CPUs of the node `node`.
+pub fn cpus(node: usize) -> anyhow::Result<Vec<usize>> {
+    let path = format!("{NODES}/node{node}/cpulist");
+    let list = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("no NUMA node {node}: cannot read {path}: {e}"))?;
+    cores::parse_list(&list).ok_or_else(|| anyhow::anyhow!("cannot parse {path}: {list:?}"))
+}
+
+/// The CPUs of each node, as `node0: 0-15, node1: 16-31`, none on machines with a single
+/// node.
+pub fn topology() -> Option<String> {
+    let mut nodes: Vec<(usize, String)> = std::fs::read_dir(NODES)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let node = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse()
+                .ok()?;
+            let cpus = std::fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            Some((node, cpus.trim().to_owned()))
+        })
+        .collect();
+    if nodes.len() < 2 {
+        return None;
+    }
+    nodes.sort();
+    let nodes: Vec<String> = nodes
+        .iter()
+        .map(|(node, cpus)| format!("node{node}: {cpus}"))
+        .collect();
+    Some(nodes.join(", "))
+}
+
+/// A mask of the node `node`, to bind memory to with [`bind_memory`].
+pub fn mask(
+    node: usize,
+) -> std::io::Result<[libc::c_ulong; MAX_NODES / libc::c_ulong::BITS as usize]> {
+    if node >= MAX_NODES {
+        return Err(std::io::Error::other(format!("invalid NUMA node {node}")));
+    }
+    let mut mask = [0; MAX_NODES / libc::c_ulong::BITS as usize];
+    let bits = libc::c_ulong::BITS as usize;
+    mask[node / bits] |= 1 << (node % bits);
+    Ok(mask)
+}
+
+/// Makes the calling thread, and the processes it then executes, allocate their memory on
+/// the nodes of `mask` only. Async-signal-safe, to be called between fork and exec.
+#[cfg(target_os = "linux")]
+pub fn bind_memory(mask: &[libc::c_ulong]) -> std::io::Result<()> {
+    // The kernel reads one bit less than the given number.
+    let max_node = (mask.len() * libc::c_ulong::BITS as usize + 1) as libc::c_ulong;
+    if unsafe { libc::syscall(libc::SYS_set_mempolicy, MPOL_BIND, mask.as_ptr(), max_node) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn bind_memory(_mask: &[libc::c_ulong]) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "binding memory to a NUMA node is only available on Linux",
+    ))
+}
@@ -0,0 +1,131 @@
+//! OpenMetrics text exposition of results, for Prometheus and its Pushgateway.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    compare,
+    metrics::{self, Unit},
+    results::{self, Results, ResultsFile},
+};
+
+/// Prefix of the names of the exported metrics.
+const PREFIX: &str = "perf_bench";
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn labels(labels: &BTreeMap<String, String>) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", sanitize(name), escape(value)))
+        .collect();
+    labels.join(",")
+}
+
+/// Name, OpenMetrics unit and scale to the base unit of the gauge of `metric`.
+fn gauge(metric: &str) -> (String, Option<&'static str>, f64) {
+    let name = format!("{PREFIX}_{}", sanitize(metric));
+    let (unit, scale) = match metrics::unit(metric) {
+        Unit::Count => return (name, None, 1.),
+        Unit::Nanoseconds => ("seconds", 1e-9),
+        Unit::Bytes => ("bytes", 1.),
+    };
+    if name.ends_with(&format!("_{unit}")) {
+        (name, Some(unit), scale)
+    } else {
+        (format!("{name}_{unit}"), Some(unit), scale)
+    }
+}
+
+/// Labels shared by all the samples of `file`: its host, git revision and tags.
+fn file_labels(file: &ResultsFile) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    let Some(metadata) = &file.metadata else {
+        return labels;
+    };
+    for (name, value) in &metadata.tags {
+        labels.insert(name.clone(), value.clone());
+    }
+    if let Some(hostname) = &metadata.hostname {
+        labels.insert("host".into(), hostname.clone());
+    }
+    if let Some(git) = &metadata.git {
+        labels.insert("commit".into(), git.commit.clone());
+        if let Some(branch) = &git.branch {
+            labels.insert("branch".into(), branch.clone());
+        }
+    }
+    labels
+}
+
+/// Each metric of `file` as a gauge, labeled with the benchmark it was measured on.
+pub fn results(file: &ResultsFile) -> String {
+    let common = file_labels(file);
+    let mut text = String::new();
+    for metric in results::metric_names([&file.results]) {
+        let (name, unit, scale) = gauge(&metric);
+        text.push_str(&format!("# TYPE {name} gauge\n"));
+        if let Some(unit) = unit {
+            text.push_str(&format!("# UNIT {name} {unit}\n"));
+        }
+        if let Some(known) = metrics::known(&metric) {
+            // The unit the description may end with is not the one of the gauge.
+            let help = known.description.split(", in ").next().unwrap_or_default();
+            text.push_str(&format!("# HELP {name} {help}\n"));
+        }
+        for (key, measure) in &file.results {
+            let Some(value) = measure.metrics.get(&metric) else {
+                continue;
+            };
+            let mut labels = common.clone();
+            labels.insert("benchmark".into(), key.clone());
+            text.push_str(&format!(
+                "{name}{{{}}} {}\n",
+                self::labels(&labels),
+                value * scale
+            ));
+        }
+    }
+    text.push_str("# EOF\n");
+    text
+}
+
+/// The relative differences in percent of the comparison as a single gauge.
+pub fn comparison(base: &Results, compared: &Results) -> String {
+    let name = format!("{PREFIX}_change_percent");
+    let mut text = format!(
+        "# TYPE {name} gauge\n# HELP {name} Relative difference of a metric over the base \
+         results\n"
+    );
+    for (key, base_measure) in base {
+        let Some(compared_measure) = compared.get(key) else {
+            continue;
+        };
+        for (metric, &base) in &base_measure.metrics {
+            let Some(&compared) = compared_measure.metrics.get(metric) else {
+                continue;
+            };
+            let labels = BTreeMap::from([
+                ("benchmark".to_owned(), key.clone()),
+                ("metric".to_owned(), metric.clone()),
+            ]);
+            text.push_str(&format!(
+                "{name}{{{}}} {}\n",
+                self::labels(&labels),
+                compare::rel_diff(base, compared)
+            ));
+        }
+    }
+    text.push_str("# EOF\n");
+    text
+}
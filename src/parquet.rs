@@ -0,0 +1,332 @@
+//! Parquet tables of results, in a single row group of uncompressed, plainly encoded columns,
+//! for data frame libraries and DuckDB to load large result sets without parsing JSON.
+
+use std::io::Write;
+
+use crate::{query, results::Results, samples};
+
+const MAGIC: &[u8] = b"PAR1";
+
+const CREATED_BY: &str = concat!("perf-bench-orchestrator ", env!("CARGO_PKG_VERSION"));
+
+/// Size from which a page is ended, well below the `i32` sizes of page headers.
+const PAGE_SIZE: usize = 1 << 20;
+
+/// Values of a column, all present.
+pub enum Column {
+    Strings(Vec<String>),
+    Ints(Vec<i64>),
+    Doubles(Vec<f64>),
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Strings(values) => values.len(),
+            Column::Ints(values) => values.len(),
+            Column::Doubles(values) => values.len(),
+        }
+    }
+
+    /// The physical type of parquet.thrift.
+    fn physical_type(&self) -> i32 {
+        match self {
+            Column::Strings(_) => 6,
+            Column::Ints(_) => 2,
+            Column::Doubles(_) => 5,
+        }
+    }
+
+    /// Appends the value `i` to `data` in the PLAIN encoding.
+    fn plain(&self, i: usize, data: &mut Vec<u8>) -> std::io::Result<()> {
+        match self {
+            Column::Strings(values) => {
+                data.extend(size::<u32>(values[i].len())?.to_le_bytes());
+                data.extend(values[i].as_bytes());
+            }
+            Column::Ints(values) => data.extend(values[i].to_le_bytes()),
+            Column::Doubles(values) => data.extend(values[i].to_le_bytes()),
+        }
+        Ok(())
+    }
+}
+
+/// `value` as the integer type of a field of the file, failing if it does not fit.
+fn size<T: TryFrom<usize>>(value: usize) -> std::io::Result<T> {
+    T::try_from(value).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{value} is too large for a Parquet file"),
+        )
+    })
+}
+
+// Types of the fields of the Thrift compact protocol.
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+/// Encoder of Thrift structures in the compact protocol, the encoding of the metadata.
+#[derive(Default)]
+struct Thrift {
+    bytes: Vec<u8>,
+    /// Identifier of the last field of each struct being written.
+    last: Vec<i16>,
+}
+
+impl Thrift {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("in a struct");
+        let delta = id - std::mem::replace(last, id);
+        if (1..=15).contains(&delta) {
+            self.bytes.push((delta as u8) << 4 | kind);
+        } else {
+            self.bytes.push(kind);
+            self.varint(((id << 1) ^ (id >> 15)) as u16 as u64);
+        }
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, I32);
+        self.varint(((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, I64);
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, BINARY);
+        self.varint(value.len() as u64);
+        self.bytes.extend(value);
+    }
+
+    /// Starts a list field of `len` elements of type `kind`.
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.bytes.push((len as u8) << 4 | kind);
+        } else {
+            self.bytes.push(0xf0 | kind);
+            self.varint(len as u64);
+        }
+    }
+
+    /// Starts a struct, in a field `id` or as an element of a list if none.
+    fn begin(&mut self, id: Option<i16>) {
+        if let Some(id) = id {
+            self.field(id, STRUCT);
+        }
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.bytes.push(0);
+        self.last.pop();
+    }
+}
+
+/// Writes the `columns`, of the same length, as a Parquet file, each column in pages of
+/// about [`PAGE_SIZE`] bytes.
+pub fn write(columns: &[(&str, Column)], out: &mut impl Write) -> std::io::Result<()> {
+    let rows = columns.first().map_or(0, |(_, column)| column.len());
+    out.write_all(MAGIC)?;
+    let mut written = MAGIC.len();
+    // Offset and size of the chunk of each column.
+    let mut chunks = Vec::new();
+    let mut data = Vec::new();
+    for (_, column) in columns {
+        let offset = written;
+        let mut start = 0;
+        // An empty column still has a page.
+        loop {
+            data.clear();
+            let mut end = start;
+            while end < column.len() && data.len() < PAGE_SIZE {
+                column.plain(end, &mut data)?;
+                end += 1;
+            }
+            let mut header = Thrift::default();
+            header.begin(None);
+            header.i32(1, 0); // DATA_PAGE
+            header.i32(2, size(data.len())?);
+            header.i32(3, size(data.len())?);
+            header.begin(Some(5));
+            header.i32(1, size(end - start)?);
+            header.i32(2, 0); // PLAIN
+            header.i32(3, 3); // RLE
+            header.i32(4, 3);
+            header.end();
+            header.end();
+            out.write_all(&header.bytes)?;
+            out.write_all(&data)?;
+            written += header.bytes.len() + data.len();
+            start = end;
+            if start == column.len() {
+                break;
+            }
+        }
+        chunks.push((offset, written - offset));
+    }
+    let mut meta = Thrift::default();
+    meta.begin(None);
+    meta.i32(1, 1);
+    meta.list(2, STRUCT, columns.len() + 1);
+    meta.begin(None);
+    meta.binary(4, b"schema");
+    meta.i32(5, size(columns.len())?);
+    meta.end();
+    for (name, column) in columns {
+        meta.begin(None);
+        meta.i32(1, column.physical_type());
+        meta.i32(3, 0); // REQUIRED
+        meta.binary(4, name.as_bytes());
+        if let Column::Strings(_) = column {
+            meta.i32(6, 0); // UTF8
+            meta.begin(Some(10));
+            meta.begin(Some(1)); // STRING
+            meta.end();
+            meta.end();
+        }
+        meta.end();
+    }
+    meta.i64(3, rows as i64);
+    meta.list(4, STRUCT, 1);
+    meta.begin(None);
+    meta.list(1, STRUCT, columns.len());
+    for ((name, column), &(offset, size)) in columns.iter().zip(&chunks) {
+        meta.begin(None);
+        meta.i64(2, offset as i64);
+        meta.begin(Some(3));
+        meta.i32(1, column.physical_type());
+        meta.list(2, I32, 1);
+        meta.varint(0); // PLAIN, a zigzag-encoded 0
+        meta.list(3, BINARY, 1);
+        meta.varint(name.len() as u64);
+        meta.bytes.extend(name.as_bytes());
+        meta.i32(4, 0); // UNCOMPRESSED
+        meta.i64(5, column.len() as i64);
+        meta.i64(6, size as i64);
+        meta.i64(7, size as i64);
+        meta.i64(9, offset as i64);
+        meta.end();
+        meta.end();
+    }
+    meta.i64(2, chunks.iter().map(|&(_, size)| size as i64).sum());
+    meta.i64(3, rows as i64);
+    meta.end();
+    meta.binary(6, CREATED_BY.as_bytes());
+    meta.end();
+    out.write_all(&meta.bytes)?;
+    out.write_all(&size::<u32>(meta.bytes.len())?.to_le_bytes())?;
+    out.write_all(MAGIC)
+}
+
+/// A row per benchmark, iteration and metric, as [`samples::rows`].
+pub fn samples(res: &Results, out: &mut impl Write) -> std::io::Result<()> {
+    let rows: Vec<_> = samples::rows(res).collect();
+    write(
+        &[
+            (
+                "file",
+                Column::Strings(rows.iter().map(|row| row.0.clone()).collect()),
+            ),
+            (
+                "iteration",
+                Column::Ints(rows.iter().map(|row| row.1 as i64).collect()),
+            ),
+            (
+                "metric",
+                Column::Strings(rows.iter().map(|row| row.2.clone()).collect()),
+            ),
+            (
+                "value",
+                Column::Doubles(rows.iter().map(|row| row.3).collect()),
+            ),
+        ],
+        out,
+    )
+}
+
+/// The rows of a query, with the tags joined as in its CSV.
+pub fn query_rows(rows: &[query::Row], out: &mut impl Write) -> std::io::Result<()> {
+    let strings =
+        |field: fn(&query::Row) -> String| Column::Strings(rows.iter().map(field).collect());
+    write(
+        &[
+            ("date", strings(|row| row.date.clone())),
+            ("revision", strings(|row| row.revision.clone())),
+            ("host", strings(|row| row.host.clone())),
+            ("file", strings(|row| row.file.clone())),
+            ("metric", strings(|row| row.metric.clone())),
+            (
+                "value",
+                Column::Doubles(rows.iter().map(|row| row.value).collect()),
+            ),
+            ("tags", strings(query::Row::joined_tags)),
+        ],
+        out,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thrift_compact_protocol() {
+        let mut thrift = Thrift::default();
+        thrift.begin(None);
+        thrift.i32(1, -1);
+        thrift.i64(20, 300);
+        thrift.list(21, I32, 20);
+        thrift.end();
+        assert_eq!(
+            thrift.bytes,
+            [0x15, 0x01, 0x06, 0x28, 0xd8, 0x04, 0x19, 0xf5, 0x14, 0x00]
+        );
+    }
+
+    #[test]
+    fn single_int_column() {
+        let mut file = Vec::new();
+        write(&[("n", Column::Ints(vec![1]))], &mut file).unwrap();
+        let mut expected = b"PAR1".to_vec();
+        // The page header, then the value.
+        expected.extend([0x15, 0x00, 0x15, 0x10, 0x15, 0x10, 0x2c, 0x15, 0x02]);
+        expected.extend([0x15, 0x00, 0x15, 0x06, 0x15, 0x06, 0x00, 0x00]);
+        expected.extend(1i64.to_le_bytes());
+        let mut meta = vec![];
+        // The version and schema, with its root and column.
+        meta.extend([0x15, 0x02, 0x19, 0x2c]);
+        meta.extend([
+            0x48, 0x06, b's', b'c', b'h', b'e', b'm', b'a', 0x15, 0x02, 0x00,
+        ]);
+        meta.extend([0x15, 0x04, 0x25, 0x00, 0x18, 0x01, b'n', 0x00]);
+        // The number of rows, then the row group and its column chunk.
+        meta.extend([0x16, 0x02, 0x19, 0x1c, 0x19, 0x1c, 0x26, 0x08, 0x1c]);
+        meta.extend([
+            0x15, 0x04, 0x19, 0x15, 0x00, 0x19, 0x18, 0x01, b'n', 0x15, 0x00,
+        ]);
+        meta.extend([0x16, 0x02, 0x16, 0x32, 0x16, 0x32, 0x26, 0x08, 0x00, 0x00]);
+        meta.extend([0x16, 0x32, 0x16, 0x02, 0x00]);
+        meta.extend([0x28, CREATED_BY.len() as u8]);
+        meta.extend(CREATED_BY.as_bytes());
+        meta.push(0x00);
+        expected.extend(&meta);
+        expected.extend((meta.len() as u32).to_le_bytes());
+        expected.extend(b"PAR1");
+        assert_eq!(file, expected);
+    }
+}
@@ -0,0 +1,148 @@
+//! Backend running the benchmarks under `perf stat` and parsing its CSV output.
+
+use std::{path::PathBuf, process::Command, time::Duration};
+
+use crate::{
+    backend::Backend,
+    child::Outcome,
+    command,
+    metrics::{Metric, Reading},
+    roi,
+    temp::TempDir,
+};
+
+pub struct PerfStat {
+    /// Metrics with the name of their perf event.
+    events: Vec<(&'static Metric, String)>,
+    /// Private directory of the output of perf and the control FIFO.
+    dir: TempDir,
+    /// FIFO through which the benchmarks enable the events, with --roi, read by perf itself.
+    control: Option<PathBuf>,
+    /// Time the events are left disabled after the benchmarks start, with --skip-start.
+    delay: Option<Duration>,
+    unavailable: Vec<&'static str>,
+}
+
+impl PerfStat {
+    pub fn new(
+        metrics: Vec<&'static Metric>,
+        roi: bool,
+        delay: Option<Duration>,
+    ) -> anyhow::Result<Self> {
+        if command::resolve_program("perf").is_none() {
+            anyhow::bail!("cannot find perf, which the backend runs the benchmarks under");
+        }
+        let dir = TempDir::new("perf-bench-stat")?;
+        let control = if roi {
+            let path = dir.path().join("control");
+            roi::make_fifo(&path)?;
+            Some(path)
+        } else {
+            None
+        };
+        Ok(Self {
+            events: metrics
+                .into_iter()
+                .filter_map(|metric| Some((metric, metric.perf_event_name()?)))
+                .collect(),
+            dir,
+            control,
+            delay,
+            unavailable: Vec::new(),
+        })
+    }
+
+    fn out_file(&self) -> PathBuf {
+        self.dir.path().join("stat.csv")
+    }
+}
+
+/// A line of `perf stat -x,` output.
+struct Count<'a> {
+    event: &'a str,
+    /// `None` if the event was not supported or not counted.
+    value: Option<f64>,
+    unit: &'a str,
+    /// Percentage of the time the event was actually counted, below 100 when multiplexed.
+    running: f64,
+}
+
+fn parse_line(line: &str) -> Option<Count<'_>> {
+    // value,unit,event,run time,running percentage,...
+    let fields: Vec<&str> = line.split(',').collect();
+    let [value, unit, event, _, running, ..] = fields[..] else {
+        return None;
+    };
+    Some(Count {
+        event,
+        value: value.parse().ok(),
+        unit,
+        running: running.parse().unwrap_or(100.),
+    })
+}
+
+impl Backend for PerfStat {
+    fn wrap(&mut self, mut command: Command) -> Command {
+        let events: Vec<&str> = self.events.iter().map(|(_, e)| e.as_str()).collect();
+        let mut options = vec![
+            "stat".to_owned(),
+            "-x,".to_owned(),
+            "-o".to_owned(),
+            self.out_file().display().to_string(),
+            "-e".to_owned(),
+            events.join(","),
+        ];
+        if let Some(control) = &self.control {
+            command.env(roi::ENV, control);
+            // Starting with the events disabled.
+            options.push("--delay=-1".to_owned());
+            options.push(format!("--control=fifo:{}", control.display()));
+        }
+        if let Some(delay) = self.delay {
+            options.push(format!("--delay={}", delay.as_millis()));
+        }
+        options.push("--".to_owned());
+        command::wrap(command, "perf", &options)
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, _outcome: &Outcome) -> std::io::Result<Reading> {
+        let contents = std::fs::read_to_string(self.out_file())?;
+        std::fs::remove_file(self.out_file())?;
+        let counts: Vec<Count> = contents
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+            .filter_map(parse_line)
+            .collect();
+        let mut reading = Reading::default();
+        for (metric, event) in &self.events {
+            // perf may add a modifier such as `:u` to the event names it reports.
+            let count = counts
+                .iter()
+                .find(|count| count.event.split(':').next() == Some(event.as_str()));
+            match count.and_then(|count| Some((count.value?, count))) {
+                Some((value, count)) => {
+                    let value = match count.unit {
+                        "msec" => value * 1e6,
+                        _ => value,
+                    };
+                    reading.metrics.insert(metric.name.to_owned(), value);
+                    reading.multiplexed |= count.running < 100.;
+                }
+                None if !self.unavailable.contains(&metric.name) => {
+                    tracing::warn!("perf could not count {event}, leaving {} out", metric.name);
+                    self.unavailable.push(metric.name);
+                }
+                None => {}
+            }
+        }
+        Ok(reading)
+    }
+
+    fn unavailable(&self) -> &[&'static str] {
+        &self.unavailable
+    }
+}
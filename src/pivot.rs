@@ -0,0 +1,119 @@
+//! Pivot of recordings of different commands over the same corpus: a table per metric with a
+//! column per command, giving its ratio to a reference command on each benchmark.
+
+use crate::results::{self, Results};
+
+/// A recording of one command, with the label of its column.
+pub struct Column<'a> {
+    pub label: String,
+    pub results: &'a Results,
+}
+
+/// Geometric mean of `ratios`, none if empty.
+fn geomean(ratios: &[f64]) -> Option<f64> {
+    if ratios.is_empty() {
+        return None;
+    }
+    Some((ratios.iter().map(|r| r.ln()).sum::<f64>() / ratios.len() as f64).exp())
+}
+
+/// Ratios of the metric `name` of each column to the reference column, by benchmark key, for
+/// the benchmarks the reference measured.
+fn ratios(columns: &[Column], reference: usize, name: &str) -> Vec<(String, Vec<Option<f64>>)> {
+    columns[reference]
+        .results
+        .iter()
+        .filter_map(|(key, measure)| {
+            let base = *measure.metrics.get(name)?;
+            let row = columns
+                .iter()
+                .map(|column| {
+                    let value = column.results.get(key)?.metrics.get(name)?;
+                    (base != 0.).then(|| value / base)
+                })
+                .collect();
+            Some((key.clone(), row))
+        })
+        .collect()
+}
+
+/// The metrics to pivot: `requested`, or all those of the reference.
+fn metric_names(columns: &[Column], reference: usize, requested: &[String]) -> Vec<String> {
+    if requested.is_empty() {
+        results::metric_names([columns[reference].results])
+    } else {
+        requested.to_vec()
+    }
+}
+
+/// Geometric means of each column over the rows where it has a ratio.
+fn column_geomeans(columns: &[Column], rows: &[(String, Vec<Option<f64>>)]) -> Vec<Option<f64>> {
+    (0..columns.len())
+        .map(|i| {
+            let ratios: Vec<f64> = rows
+                .iter()
+                .filter_map(|(_, row)| row[i])
+                .filter(|&ratio| ratio > 0.)
+                .collect();
+            geomean(&ratios)
+        })
+        .collect()
+}
+
+/// Prints a table per metric of the ratio of each column to the `reference` one, with their
+/// geometric mean over the benchmarks.
+pub fn print(columns: &[Column], reference: usize, metrics: &[String]) {
+    for name in metric_names(columns, reference, metrics) {
+        let rows = ratios(columns, reference, &name);
+        if rows.is_empty() {
+            tracing::warn!("{} has no {name} to compare with", columns[reference].label);
+            continue;
+        }
+        println!(
+            "{name}, relative to {} (lower is better):",
+            columns[reference].label
+        );
+        let mut table = prettytable::Table::new();
+        let mut header = vec![prettytable::Cell::new("File")];
+        header.extend(columns.iter().map(|c| prettytable::Cell::new(&c.label)));
+        table.add_row(prettytable::Row::new(header));
+        let cell = |ratio: Option<f64>| match ratio {
+            Some(ratio) => prettytable::Cell::new(&format!("{ratio:.2}x")),
+            None => prettytable::Cell::new("-"),
+        };
+        for (key, row) in &rows {
+            let mut cells = vec![prettytable::Cell::new(key)];
+            cells.extend(row.iter().map(|&ratio| cell(ratio)));
+            table.add_row(prettytable::Row::new(cells));
+        }
+        let mut cells = vec![prettytable::Cell::new("Geomean")];
+        cells.extend(column_geomeans(columns, &rows).into_iter().map(cell));
+        table.add_row(prettytable::Row::new(cells));
+        table.printstd();
+    }
+}
+
+/// The ratios by metric, then benchmark key or `geomean`, then column label.
+pub fn json(columns: &[Column], reference: usize, metrics: &[String]) -> serde_json::Value {
+    let by_label = |row: &[Option<f64>]| -> serde_json::Map<String, serde_json::Value> {
+        columns
+            .iter()
+            .zip(row)
+            .filter_map(|(column, ratio)| Some((column.label.clone(), (*ratio)?.into())))
+            .collect()
+    };
+    let mut pivot = serde_json::Map::new();
+    for name in metric_names(columns, reference, metrics) {
+        let rows = ratios(columns, reference, &name);
+        let mut table: serde_json::Map<_, _> = rows
+            .iter()
+            .map(|(key, row)| (key.clone(), by_label(row).into()))
+            .collect();
+        table.insert(
+            "geomean".to_owned(),
+            by_label(&column_geomeans(columns, &rows)).into(),
+        );
+        pivot.insert(name, table.into());
+    }
+    pivot.into()
+}
@@ -0,0 +1,116 @@
+//! Backend running the benchmarks under a QEMU user-mode emulator with its instruction-counting
+//! plugin.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    backend::Backend,
+    child::Outcome,
+    command,
+    metrics::{Metric, Reading},
+    temp::TempDir,
+};
+
+pub struct Qemu {
+    metrics: Vec<&'static Metric>,
+    qemu: String,
+    plugin: PathBuf,
+    /// Private directory of the log of the plugin.
+    dir: TempDir,
+}
+
+impl Qemu {
+    pub fn new(
+        metrics: Vec<&'static Metric>,
+        qemu: Option<&str>,
+        plugin: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let qemu = qemu.ok_or_else(|| {
+            anyhow::anyhow!("the qemu backend needs the emulator to use, e.g. --qemu qemu-aarch64")
+        })?;
+        if command::resolve_program(qemu).is_none() {
+            anyhow::bail!("cannot find {qemu}");
+        }
+        let plugin = plugin.ok_or_else(|| {
+            anyhow::anyhow!("the qemu backend needs the path of libinsn.so, with --qemu-plugin")
+        })?;
+        if !plugin.is_file() {
+            anyhow::bail!("cannot find the QEMU plugin {}", plugin.display());
+        }
+        Ok(Self {
+            metrics,
+            qemu: qemu.to_owned(),
+            plugin: plugin.to_owned(),
+            dir: TempDir::new("perf-bench-qemu")?,
+        })
+    }
+
+    fn log_file(&self) -> PathBuf {
+        self.dir.path().join("qemu.log")
+    }
+}
+
+/// Reads the instruction count from the log of the plugin, which reports either a total or
+/// one count per virtual CPU.
+fn parse_instructions(log: &str) -> std::io::Result<u64> {
+    let count = |line: &str| -> Option<u64> {
+        let (_, count) = line.rsplit_once("insns:")?;
+        count.trim().parse().ok()
+    };
+    if let Some(total) = log
+        .lines()
+        .find(|line| line.starts_with("total insns:"))
+        .and_then(count)
+    {
+        return Ok(total);
+    }
+    let counts: Vec<u64> = log.lines().filter_map(count).collect();
+    if counts.is_empty() {
+        return Err(std::io::Error::other(
+            "no instruction count in the QEMU plugin output",
+        ));
+    }
+    Ok(counts.into_iter().sum())
+}
+
+impl Backend for Qemu {
+    fn wrap(&mut self, command: Command) -> Command {
+        let options = [
+            "-plugin".to_owned(),
+            self.plugin.display().to_string(),
+            "-d".to_owned(),
+            "plugin".to_owned(),
+            "-D".to_owned(),
+            self.log_file().display().to_string(),
+        ];
+        command::wrap(
+            command::with_resolved_program(command),
+            &self.qemu,
+            &options,
+        )
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, _outcome: &Outcome) -> std::io::Result<Reading> {
+        let log = std::fs::read_to_string(self.log_file())?;
+        std::fs::remove_file(self.log_file())?;
+        let instructions = parse_instructions(&log)?;
+        let mut reading = Reading::default();
+        for metric in &self.metrics {
+            reading
+                .metrics
+                .insert(metric.name.to_owned(), instructions as f64);
+        }
+        Ok(reading)
+    }
+
+    fn unavailable(&self) -> &[&'static str] {
+        &[]
+    }
+}
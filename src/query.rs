@@ -0,0 +1,311 @@
+//! Filtering of the measurements stored in the history, a row per run, benchmark and metric.
+
+use std::collections::BTreeMap;
+
+use crate::history::Run;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+    Parquet,
+}
+
+/// A measurement of a run.
+#[derive(serde::Serialize)]
+pub struct Row {
+    pub date: String,
+    pub revision: String,
+    pub host: String,
+    pub file: String,
+    pub metric: String,
+    pub value: f64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Row {
+    /// The tags as `key=value` pairs joined with semicolons.
+    pub fn joined_tags(&self) -> String {
+        let tags: Vec<String> = self.tags.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        tags.join(";")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    NotContains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Operators of the conditions, the longest one at a position being read, so that `<=` isn't
+/// read as `<`.
+const OPS: &[(&str, Op)] = &[
+    ("!=", Op::Ne),
+    ("!~", Op::NotContains),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("~", Op::Contains),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+#[derive(Debug, Clone)]
+struct Condition {
+    field: String,
+    op: Op,
+    value: String,
+}
+
+/// Conditions all rows must meet, such as `file ~ simd/ and metric = instructions`.
+///
+/// The fields are `file`, `metric`, `value`, `date`, `revision`, `host` and `run.KEY` for
+/// the tag KEY, with `run.tag` matching any tag by key, value or KEY=VALUE.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    conditions: Vec<Condition>,
+}
+
+impl std::str::FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut conditions = Vec::new();
+        for condition in s.split(" and ") {
+            let Some((position, symbol, op)) = OPS
+                .iter()
+                .filter_map(|&(symbol, op)| Some((condition.find(symbol)?, symbol, op)))
+                .min_by_key(|&(position, symbol, _)| (position, std::cmp::Reverse(symbol.len())))
+            else {
+                anyhow::bail!("expected FIELD OP VALUE, got {condition:?}");
+            };
+            let field = condition[..position].trim();
+            let value = condition[position + symbol.len()..].trim();
+            if !matches!(
+                field,
+                "file" | "metric" | "value" | "date" | "revision" | "host"
+            ) && !field.starts_with("run.")
+            {
+                anyhow::bail!(
+                    "unknown field {field:?}, expected file, metric, value, date, revision, host \
+                     or run.TAG"
+                );
+            }
+            if field == "value" && value.parse::<f64>().is_err() {
+                anyhow::bail!("value is compared to a number, got {value:?}");
+            }
+            conditions.push(Condition {
+                field: field.to_owned(),
+                op,
+                value: value.to_owned(),
+            });
+        }
+        Ok(Filter { conditions })
+    }
+}
+
+impl Condition {
+    fn test(&self, text: &str) -> bool {
+        match self.op {
+            Op::Eq => text == self.value,
+            Op::Ne => text != self.value,
+            Op::Contains => text.contains(&self.value),
+            Op::NotContains => !text.contains(&self.value),
+            Op::Lt => text < self.value.as_str(),
+            Op::Le => text <= self.value.as_str(),
+            Op::Gt => text > self.value.as_str(),
+            Op::Ge => text >= self.value.as_str(),
+        }
+    }
+
+    fn test_number(&self, value: f64) -> bool {
+        let bound: f64 = self.value.parse().expect("checked when parsing");
+        match self.op {
+            Op::Eq => value == bound,
+            Op::Ne => value != bound,
+            Op::Lt => value < bound,
+            Op::Le => value <= bound,
+            Op::Gt => value > bound,
+            Op::Ge => value >= bound,
+            Op::Contains | Op::NotContains => self.test(&value.to_string()),
+        }
+    }
+
+    fn matches(&self, row: &Row) -> bool {
+        match self.field.as_str() {
+            "file" => self.test(&row.file),
+            "metric" => self.test(&row.metric),
+            "value" => self.test_number(row.value),
+            "date" => self.test(&row.date),
+            "revision" => self.test(&row.revision),
+            "host" => self.test(&row.host),
+            "run.tag" => {
+                let any = |f: &dyn Fn(&str) -> bool| {
+                    row.tags
+                        .iter()
+                        .any(|(k, v)| f(k) || f(v) || f(&format!("{k}={v}")))
+                };
+                match self.op {
+                    // The tags mustn't contain the value, rather than one of them not being it.
+                    Op::Ne | Op::NotContains => {
+                        let positive = Condition {
+                            field: self.field.clone(),
+                            op: if self.op == Op::Ne {
+                                Op::Eq
+                            } else {
+                                Op::Contains
+                            },
+                            value: self.value.clone(),
+                        };
+                        !any(&|text| positive.test(text))
+                    }
+                    _ => any(&|text| self.test(text)),
+                }
+            }
+            field => {
+                let tag = &field["run.".len()..];
+                match row.tags.get(tag) {
+                    Some(value) => self.test(value),
+                    None => matches!(self.op, Op::Ne | Op::NotContains),
+                }
+            }
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, row: &Row) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(row))
+    }
+}
+
+/// The measurements of `runs` that pass `filter`.
+pub fn rows(runs: &[Run], filter: &Filter) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for run in runs {
+        let metadata = run.file.metadata.as_ref();
+        for (key, measure) in &run.file.results {
+            for (metric, &value) in &measure.metrics {
+                let row = Row {
+                    date: metadata.map_or_else(String::new, |m| m.date.clone()),
+                    revision: metadata
+                        .and_then(|m| m.git.as_ref())
+                        .map_or_else(String::new, |git| git.short()),
+                    host: metadata
+                        .and_then(|m| m.hostname.clone())
+                        .unwrap_or_default(),
+                    file: key.clone(),
+                    metric: metric.clone(),
+                    value,
+                    tags: metadata.map(|m| m.tags.clone()).unwrap_or_default(),
+                };
+                if filter.matches(&row) {
+                    rows.push(row);
+                }
+            }
+        }
+    }
+    rows
+}
+
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+pub fn csv(rows: &[Row]) -> String {
+    let mut csv = String::from("date,revision,host,file,metric,value,tags\n");
+    for row in rows {
+        let fields = [
+            row.date.clone(),
+            row.revision.clone(),
+            row.host.clone(),
+            row.file.clone(),
+            row.metric.clone(),
+            row.value.to_string(),
+            row.joined_tags(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> Row {
+        Row {
+            date: "2024-05-01T12:00:00Z".into(),
+            revision: "abc1234".into(),
+            host: "bench1".into(),
+            file: "inputs/simd/sum.txt".into(),
+            metric: "instructions".into(),
+            value: 1500.,
+            tags: BTreeMap::from([("cc".into(), "gcc".into()), ("opt".into(), "O2".into())]),
+        }
+    }
+
+    fn matches(filter: &str) -> bool {
+        filter.parse::<Filter>().unwrap().matches(&row())
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("".parse::<Filter>().is_err());
+        assert!("file".parse::<Filter>().is_err());
+        assert!("name = a".parse::<Filter>().is_err());
+        assert!("value > many".parse::<Filter>().is_err());
+        assert!("file ~ a and metric".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn fields_and_operators() {
+        assert!(matches("file ~ simd/"));
+        assert!(!matches("file !~ simd/"));
+        assert!(matches("metric = instructions"));
+        assert!(!matches("metric != instructions"));
+        assert!(matches("host=bench1"));
+        assert!(matches("revision ~ abc"));
+        assert!(matches("date >= 2024-05-01"));
+        assert!(!matches("date < 2024-05-01"));
+        assert!(matches("file ~ simd/ and metric = instructions"));
+        assert!(!matches("file ~ simd/ and metric = cycles"));
+    }
+
+    #[test]
+    fn values_compare_as_numbers() {
+        assert!(matches("value > 200"));
+        assert!(matches("value <= 1500"));
+        assert!(!matches("value < 1500"));
+        assert!(matches("value = 1.5e3"));
+        assert!(matches("value != 1"));
+    }
+
+    #[test]
+    fn tags() {
+        assert!(matches("run.cc = gcc"));
+        assert!(!matches("run.cc = clang"));
+        assert!(matches("run.arch != x86"));
+        assert!(!matches("run.arch = x86"));
+        assert!(matches("run.tag = opt=O2"));
+        assert!(matches("run.tag = gcc"));
+        assert!(matches("run.tag ~ cc"));
+        assert!(!matches("run.tag != gcc"));
+        assert!(matches("run.tag !~ clang"));
+    }
+}
@@ -0,0 +1,1041 @@
+//! Running benchmarks and measuring them.
+
+use std::{
+    collections::{btree_map, BTreeMap, BTreeSet, HashMap},
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend::{self, Backend},
+    cache, child,
+    collector::{self, Collector},
+    command, cores, ebpf,
+    group::{self, GroupBy},
+    hash, hot_functions, metadata,
+    metrics::{self, Metrics},
+    monitor, numa,
+    results::{Measure, Results, Sample, Status},
+    smt, tasks, temp, wasm, RunOptions,
+};
+#[cfg(target_os = "linux")]
+use crate::{breakpoint, uprobe};
+
+#[derive(Clone)]
+pub struct Benchmark {
+    pub key: String,
+    pub command: String,
+    pub input: Option<PathBuf>,
+    /// SHA-256 of the standard output of a correct run.
+    pub expected_stdout: Option<String>,
+    /// Free-text annotation carried into the results, e.g. "known flaky".
+    pub note: Option<String>,
+    pub repetitions: Repetitions,
+    /// Reason not to measure the benchmark, recording it as skipped.
+    pub skip: Option<String>,
+}
+
+/// How many times a benchmark runs, overriding the options of the recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Repetitions {
+    pub iterations: Option<u32>,
+    pub warmup: Option<u32>,
+    /// Time after which no further iteration starts, the first one always running.
+    pub max_time: Option<std::time::Duration>,
+}
+
+/// Lexically normalizes `path`, dropping `.` components and resolving `..` ones.
+fn normalize(path: &Path) -> PathBuf {
+    use std::path::Component;
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// How result keys are derived from input files.
+pub struct KeyFormat<'a> {
+    /// Template with placeholders {file}, {file.name}, {file.stem}, {file.ext} and {file.dir}.
+    pub template: &'a str,
+    /// Directory the paths of the files are made relative to, when they are inside it.
+    pub strip_prefix: Option<&'a Path>,
+}
+
+impl KeyFormat<'_> {
+    /// The path of `file` as used in keys, independent of how it was spelled.
+    fn key_path(&self, file: &Path) -> anyhow::Result<PathBuf> {
+        let file = normalize(file);
+        let Some(prefix) = self.strip_prefix else {
+            return Ok(file);
+        };
+        let cwd = std::env::current_dir()?;
+        let absolute = normalize(&cwd.join(&file));
+        Ok(match absolute.strip_prefix(normalize(&cwd.join(prefix))) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => file,
+        })
+    }
+
+    pub fn render(&self, file: &Path) -> anyhow::Result<String> {
+        let file = self.key_path(file)?;
+        let os = |s: Option<&std::ffi::OsStr>| s.unwrap_or_default().to_string_lossy().into_owned();
+        let slashes = |path: &Path| path.display().to_string().replace('\\', "/");
+        let template = self.template;
+        let mut key = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            key.push_str(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(|| {
+                anyhow::anyhow!("unclosed placeholder in key template {template:?}")
+            })?;
+            key.push_str(&match &rest[start + 1..start + end] {
+                "file" => slashes(&file),
+                "file.name" => os(file.file_name()),
+                "file.stem" => os(file.file_stem()),
+                "file.ext" => os(file.extension()),
+                "file.dir" => slashes(file.parent().unwrap_or(Path::new(""))),
+                other => anyhow::bail!("unknown placeholder {{{other}}} in key template"),
+            });
+            rest = &rest[start + end + 1..];
+        }
+        key.push_str(rest);
+        Ok(key)
+    }
+}
+
+pub fn file_benchmarks(
+    command: &str,
+    wat_files: &[PathBuf],
+    key_format: &KeyFormat,
+) -> anyhow::Result<Vec<Benchmark>> {
+    wat_files
+        .iter()
+        .map(|wat_file| {
+            Ok(Benchmark {
+                key: key_format.render(wat_file)?,
+                command: command.to_owned(),
+                input: Some(wat_file.clone()),
+                expected_stdout: None,
+                note: None,
+                repetitions: Repetitions::default(),
+                skip: None,
+            })
+        })
+        .collect()
+}
+
+pub fn check_unique_keys(benchmarks: &[Benchmark]) -> anyhow::Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for benchmark in benchmarks {
+        if !seen.insert(&benchmark.key) {
+            anyhow::bail!(
+                "several benchmarks have key {:?}, the key template must distinguish them",
+                benchmark.key
+            );
+        }
+    }
+    Ok(())
+}
+
+fn drop_caches() -> std::io::Result<()> {
+    unsafe { libc::sync() };
+    std::fs::write("/proc/sys/vm/drop_caches", "3")
+}
+
+/// Copies `file` into a new path and evicts the copy from the page cache, so that reading it
+/// in the benchmark hits the disk.
+#[cfg(target_os = "linux")]
+fn fresh_copy(file: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    use std::os::fd::AsRawFd;
+    let dest = dir.join(file.file_name().unwrap_or(file.as_os_str()));
+    let _ = std::fs::remove_file(&dest);
+    std::fs::copy(file, &dest)?;
+    let copy = std::fs::File::open(&dest)?;
+    copy.sync_all()?;
+    let ret = unsafe { libc::posix_fadvise(copy.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if ret != 0 {
+        return Err(std::io::Error::from_raw_os_error(ret));
+    }
+    Ok(dest)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn fresh_copy(_file: &Path, _dir: &Path) -> std::io::Result<PathBuf> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "evicting a file from the page cache is only available on Linux",
+    ))
+}
+
+/// State shared by all the runs of a [`measure`] call.
+struct Runner<'a> {
+    opts: &'a RunOptions,
+    backend: Box<dyn Backend>,
+    collectors: Vec<Box<dyn Collector>>,
+    tracer: Option<ebpf::Tracer>,
+    tasks: Option<tasks::Tasks>,
+    profiler: Option<hot_functions::Profiler>,
+    frequency: monitor::FrequencySampler,
+    cpus: monitor::CpuSampler,
+    rlimits: Vec<child::Rlimit>,
+    /// Directory of the file the standard output of the benchmarks is redirected to.
+    stdout_dir: temp::TempDir,
+    /// Directory of the fresh copies of the inputs, with `--cold`.
+    fresh_dir: Option<temp::TempDir>,
+    can_drop_caches: bool,
+    /// Binary modules the `.wat` inputs were precompiled to, with `--precompile`.
+    precompiled: HashMap<PathBuf, PathBuf>,
+    /// Directory of the `precompiled` modules.
+    precompiled_dir: Option<temp::TempDir>,
+    /// Directory of the outputs of the compile phases, with `--compile-cmd`.
+    artifact_dir: Option<temp::TempDir>,
+}
+
+struct Run {
+    metrics: Metrics,
+    multiplexed: bool,
+    functions: Metrics,
+    tasks: tasks::Breakdown,
+    hot_functions: BTreeMap<String, Metrics>,
+    load: monitor::Load,
+    frequency: Option<monitor::Frequency>,
+    /// CPUs its threads were seen running on.
+    cpus: BTreeSet<usize>,
+    limit_hit: Option<&'static str>,
+    exit: std::process::ExitStatus,
+    /// Whether the standard output differed from the expected one.
+    incorrect: bool,
+}
+
+impl Runner<'_> {
+    fn run(&mut self, benchmark: &Benchmark) -> anyhow::Result<Run> {
+        let original = benchmark
+            .input
+            .as_ref()
+            .map(|input| self.precompiled.get(input).unwrap_or(input).clone());
+        let mut input = original.clone();
+        if self.opts.cold {
+            if self.can_drop_caches {
+                if let Err(e) = drop_caches() {
+                    tracing::warn!(
+                        "cannot drop the page cache ({e}), copying inputs to fresh paths instead"
+                    );
+                    self.can_drop_caches = false;
+                }
+            }
+            if let (false, Some(wat_file)) = (self.can_drop_caches, &original) {
+                let fresh_dir = self.fresh_dir.as_ref().expect("created with --cold");
+                input = Some(fresh_copy(wat_file, fresh_dir.path())?);
+            }
+        }
+        let mut command = self.backend.wrap(command::build(
+            &benchmark.command,
+            input.as_deref(),
+            self.opts.shell,
+        )?);
+        let stdout = collector::stdout_path(self.stdout_dir.path());
+        if benchmark.expected_stdout.is_some() {
+            // The collectors reading the output redirect it to the same file.
+            command.stdout(std::fs::File::create(&stdout)?);
+        }
+        for collector in &mut self.collectors {
+            collector.start(&mut command)?;
+        }
+        let trace = self.tracer.as_ref().map(ebpf::Tracer::start).transpose()?;
+        if let Some(tasks) = &mut self.tasks {
+            tasks.start();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.start();
+        }
+        self.backend.start()?;
+        let load_monitor = monitor::LoadMonitor::start();
+        self.frequency.start();
+        let outcome = child::run(
+            &mut command,
+            &self.rlimits,
+            self.opts.timeout,
+            &self.opts.pin,
+            self.opts.numa_node,
+            |pid| self.cpus.start(pid),
+        );
+        let frequency = self.frequency.stop();
+        let mut cpus = self.cpus.stop();
+        let outcome = outcome?;
+        cpus.extend(&outcome.last_cpus);
+        let mut reading = self.backend.finish(&outcome)?;
+        let tasks = self
+            .tasks
+            .as_mut()
+            .map(tasks::Tasks::read)
+            .unwrap_or_default();
+        let hot_functions = self
+            .profiler
+            .as_mut()
+            .map(hot_functions::Profiler::read)
+            .unwrap_or_default();
+        let load = load_monitor.stop();
+        let recorded = self.opts.metrics();
+        for collector in &mut self.collectors {
+            collector.stop(&outcome)?;
+            for (name, value) in collector.read()? {
+                // E.g. a benchmark printing instructions=… with --parse-output.
+                if recorded.contains(&name) || reading.metrics.insert(name.clone(), value).is_some()
+                {
+                    anyhow::bail!(
+                        "{} reported the metric {name} besides the recorded one, rename it",
+                        benchmark.key
+                    );
+                }
+            }
+        }
+        if let (Some(tracer), Some(trace)) = (&self.tracer, trace) {
+            reading.metrics.extend(tracer.stop(trace)?);
+        }
+        let incorrect = match &benchmark.expected_stdout {
+            Some(expected) => {
+                let incorrect = hash::sha256_file(&stdout)? != *expected;
+                std::fs::remove_file(&stdout)?;
+                incorrect
+            }
+            None => false,
+        };
+        if input != original {
+            std::fs::remove_file(input.expect("fresh copies are of an input"))?;
+        }
+        Ok(Run {
+            metrics: reading.metrics,
+            multiplexed: reading.multiplexed,
+            functions: reading.functions,
+            tasks,
+            hot_functions,
+            load,
+            frequency,
+            cpus,
+            limit_hit: outcome.limit_hit(&self.rlimits),
+            exit: outcome.status,
+            incorrect,
+        })
+    }
+
+    /// Measures the shell command `compile` on the input of `benchmark`, then `benchmark`
+    /// itself on what it compiled, with their metrics prefixed with their phase.
+    ///
+    /// `{output}` in `compile` is replaced with the path of a file in which case `benchmark`
+    /// runs on it, otherwise it runs on the input.
+    fn measure_phases(&mut self, benchmark: &Benchmark, compile: &str) -> anyhow::Result<Measure> {
+        let mut run = Benchmark {
+            key: benchmark.key.clone(),
+            command: benchmark.command.clone(),
+            input: benchmark.input.clone(),
+            expected_stdout: benchmark.expected_stdout.clone(),
+            note: None,
+            repetitions: benchmark.repetitions,
+            skip: None,
+        };
+        let mut compile = compile.to_owned();
+        if compile.contains("{output}") {
+            let artifact_dir = self
+                .artifact_dir
+                .as_ref()
+                .expect("created with --compile-cmd");
+            let artifact = artifact_dir.path().join("output");
+            compile = compile.replace("{output}", &artifact.display().to_string());
+            run.input = Some(artifact);
+        }
+        let compile = Benchmark {
+            key: benchmark.key.clone(),
+            command: compile,
+            input: benchmark.input.clone(),
+            expected_stdout: None,
+            note: None,
+            repetitions: benchmark.repetitions,
+            skip: None,
+        };
+        let [compile_phase, run_phase] = metrics::PHASES;
+        let mut compiled = self.measure(&compile)?;
+        let compiled_size = match &run.input {
+            Some(artifact) if run.input != benchmark.input => {
+                Some(std::fs::metadata(artifact)?.len() as f64)
+            }
+            _ => None,
+        };
+        let ran = self.measure(&run)?;
+        let prefix = |phase: &str, metrics: Metrics| -> Metrics {
+            metrics
+                .into_iter()
+                .map(|(name, value)| (format!("{phase}.{name}"), value))
+                .collect()
+        };
+        compiled.metrics = prefix(compile_phase, compiled.metrics);
+        compiled.metrics.extend(prefix(run_phase, ran.metrics));
+        compiled.functions = prefix(compile_phase, compiled.functions);
+        compiled.functions.extend(prefix(run_phase, ran.functions));
+        let prefix_keys = |phase: &str, threads: BTreeMap<String, Metrics>| {
+            threads
+                .into_iter()
+                .map(|(name, metrics)| (format!("{phase}.{name}"), metrics))
+                .collect::<BTreeMap<_, _>>()
+        };
+        compiled.threads = prefix_keys(compile_phase, compiled.threads);
+        compiled.threads.extend(prefix_keys(run_phase, ran.threads));
+        compiled.processes = prefix_keys(compile_phase, compiled.processes);
+        compiled
+            .processes
+            .extend(prefix_keys(run_phase, ran.processes));
+        compiled.hot_functions = prefix_keys(compile_phase, compiled.hot_functions);
+        compiled
+            .hot_functions
+            .extend(prefix_keys(run_phase, ran.hot_functions));
+        for (compile_sample, run_sample) in compiled.samples.iter_mut().zip(ran.samples) {
+            compile_sample.metrics =
+                prefix(compile_phase, std::mem::take(&mut compile_sample.metrics));
+            compile_sample
+                .metrics
+                .extend(prefix(run_phase, run_sample.metrics));
+            compile_sample.cpus = union(compile_sample.cpus.iter().chain(&run_sample.cpus));
+        }
+        compiled.load_avg = (compiled.load_avg + ran.load_avg) / 2.;
+        compiled.interference = compiled.interference.max(ran.interference);
+        compiled.frequency =
+            monitor::Frequency::combine(compiled.frequency.into_iter().chain(ran.frequency));
+        compiled.cpus = union(compiled.cpus.iter().chain(&ran.cpus));
+        compiled.limit_hit = compiled.limit_hit.or(ran.limit_hit);
+        compiled.multiplexed |= ran.multiplexed;
+        compiled.incorrect |= ran.incorrect;
+        if compiled.status.is_ok() {
+            compiled.status = ran.status;
+        }
+        if let (true, Some(size)) = (self.requested("compiled_size"), compiled_size) {
+            compiled.metrics.insert("compiled_size".to_owned(), size);
+        }
+        Ok(compiled)
+    }
+
+    fn requested(&self, metric: &str) -> bool {
+        self.opts.metrics().iter().any(|name| name == metric)
+    }
+
+    fn measure(&mut self, benchmark: &Benchmark) -> anyhow::Result<Measure> {
+        let repetitions = benchmark.repetitions;
+        let iterations = match repetitions.iterations {
+            Some(iterations) => iterations.max(1),
+            None => self.opts.iterations(),
+        };
+        for _ in 0..repetitions.warmup.or(self.opts.warmup).unwrap_or(0) {
+            self.run(benchmark)?;
+        }
+        let start = std::time::Instant::now();
+        let mut runs = Vec::new();
+        for _ in 0..iterations {
+            if let (Some(max_time), false) = (repetitions.max_time, runs.is_empty()) {
+                if start.elapsed() >= max_time {
+                    tracing::info!(
+                        "stopping {} after {} of {iterations} iteration(s), past its maximum time",
+                        benchmark.key,
+                        runs.len()
+                    );
+                    break;
+                }
+            }
+            runs.push(self.run(benchmark)?);
+        }
+        let limit_hit = runs.iter().find_map(|run| run.limit_hit);
+        if let Some(limit) = limit_hit {
+            tracing::warn!("{} most likely hit the {limit} limit", benchmark.key);
+        }
+        let incorrect = runs.iter().filter(|run| run.incorrect).count();
+        if incorrect > 0 {
+            tracing::warn!(
+                "{} printed a wrong output in {incorrect} of {} run(s)",
+                benchmark.key,
+                runs.len()
+            );
+        }
+        let unavailable: Vec<String> = self
+            .backend
+            .unavailable()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let status = match runs.iter().find(|run| !run.exit.success()) {
+            Some(run) if matches!(run.limit_hit, Some("cpu" | "time")) => Status::Timeout,
+            Some(run) => match (run.exit.code(), run.exit.signal()) {
+                (Some(code), _) => Status::NonzeroExit { code },
+                (None, Some(signal)) => Status::Signal {
+                    signal,
+                    core_dumped: run.exit.core_dumped(),
+                },
+                (None, None) => Status::NonzeroExit { code: -1 },
+            },
+            None if !unavailable.is_empty() => Status::CountersUnavailable,
+            None => Status::Ok,
+        };
+        if status.is_failure() {
+            tracing::warn!("{} failed: {status}", benchmark.key);
+        }
+        let samples: Vec<Metrics> = runs.iter().map(|run| run.metrics.clone()).collect();
+        let mut cpus = runs.iter().map(|run| union(&run.cpus));
+        Ok(Measure {
+            metrics: metrics::median(&samples),
+            load_avg: runs.iter().map(|run| run.load.load_avg).sum::<f64>() / runs.len() as f64,
+            interference: runs
+                .iter()
+                .map(|run| run.load.interference)
+                .fold(0., f64::max),
+            frequency: monitor::Frequency::combine(runs.iter().filter_map(|run| run.frequency)),
+            cpus: union(runs.iter().flat_map(|run| &run.cpus)),
+            limit_hit: limit_hit.map(str::to_owned),
+            multiplexed: runs.iter().any(|run| run.multiplexed),
+            incorrect: incorrect > 0,
+            functions: metrics::median(
+                &runs
+                    .iter()
+                    .map(|run| run.functions.clone())
+                    .collect::<Vec<_>>(),
+            ),
+            threads: if self.opts.per_thread {
+                median_by_task(runs.iter().map(|run| &run.tasks.threads))
+            } else {
+                BTreeMap::new()
+            },
+            processes: if self.opts.per_process {
+                median_by_task(runs.iter().map(|run| &run.tasks.processes))
+            } else {
+                BTreeMap::new()
+            },
+            hot_functions: median_by_task(runs.iter().map(|run| &run.hot_functions)),
+            status,
+            unavailable,
+            samples: if runs.len() > 1 {
+                samples
+                    .into_iter()
+                    .map(|metrics| Sample {
+                        metrics,
+                        cpus: cpus.next().unwrap_or_default(),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            ..Measure::default()
+        })
+    }
+}
+
+/// The distinct CPUs of `cpus`, in order.
+fn union<'a>(cpus: impl IntoIterator<Item = &'a usize>) -> Vec<usize> {
+    let cpus: BTreeSet<usize> = cpus.into_iter().copied().collect();
+    cpus.into_iter().collect()
+}
+
+/// Median of each metric of each task over the breakdowns of runs, among the runs the task
+/// was in.
+fn median_by_task<'a>(
+    runs: impl Iterator<Item = &'a BTreeMap<String, Metrics>>,
+) -> BTreeMap<String, Metrics> {
+    let mut samples: BTreeMap<&str, Vec<Metrics>> = BTreeMap::new();
+    for tasks in runs {
+        for (name, metrics) in tasks {
+            samples.entry(name).or_default().push(metrics.clone());
+        }
+    }
+    samples
+        .into_iter()
+        .map(|(name, samples)| (name.to_owned(), metrics::median(&samples)))
+        .collect()
+}
+
+/// Runs the shell command `build` from the current directory, then hashes the programs of
+/// the `benchmarks` it built.
+pub fn build(build: &str, benchmarks: &[Benchmark]) -> anyhow::Result<metadata::Build> {
+    tracing::info!("building: {build}");
+    command::run_build(build, Path::new("."))?;
+    let mut binaries = BTreeMap::new();
+    for benchmark in benchmarks {
+        let program = command::program(&benchmark.command)?;
+        if let Some(binary) = command::resolve_program(&program) {
+            if let btree_map::Entry::Vacant(entry) = binaries.entry(binary.display().to_string()) {
+                entry.insert(hash::sha256_file(&binary)?);
+            }
+        }
+    }
+    Ok(metadata::Build {
+        command: build.to_owned(),
+        binaries,
+    })
+}
+
+pub fn measure(benchmarks: &[Benchmark], opts: &RunOptions) -> anyhow::Result<Results> {
+    let stdout_dir = temp::TempDir::new("perf-bench-stdout")?;
+    let stdout = collector::stdout_path(stdout_dir.path());
+    let mut collectors: Vec<Box<dyn Collector>> = Vec::new();
+    if !opts.extra_metric.is_empty() {
+        collectors.push(Box::new(collector::Scripts::new(
+            opts.extra_metric.clone(),
+            stdout.clone(),
+        )));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if !opts.breakpoint.is_empty() || !opts.count_calls.is_empty() {
+        anyhow::bail!(
+            "--breakpoint and --count-calls count with perf events, only available on Linux"
+        );
+    }
+    #[cfg(target_os = "linux")]
+    if !opts.breakpoint.is_empty() {
+        collectors.push(Box::new(breakpoint::Breakpoints::new(
+            opts.breakpoint.clone(),
+        )));
+    }
+    #[cfg(target_os = "linux")]
+    if !opts.count_calls.is_empty() {
+        collectors.push(Box::new(uprobe::CallCounters::new(&opts.count_calls)?));
+    }
+    if let Some(format) = opts.parse_output {
+        collectors.push(Box::new(collector::OutputParser::new(format, stdout)));
+    }
+    measure_in(benchmarks, opts, collectors, stdout_dir)
+}
+
+/// Like [`measure`], also reporting the metrics of `collectors` for each run.
+pub fn measure_with(
+    benchmarks: &[Benchmark],
+    opts: &RunOptions,
+    collectors: Vec<Box<dyn Collector>>,
+) -> anyhow::Result<Results> {
+    let stdout_dir = temp::TempDir::new("perf-bench-stdout")?;
+    measure_in(benchmarks, opts, collectors, stdout_dir)
+}
+
+/// Like [`measure_with`], the standard output of the benchmarks going to the file
+/// [`collector::stdout_path`] of `stdout_dir`.
+fn measure_in(
+    benchmarks: &[Benchmark],
+    opts: &RunOptions,
+    collectors: Vec<Box<dyn Collector>>,
+    stdout_dir: temp::TempDir,
+) -> anyhow::Result<Results> {
+    let pinned;
+    let opts = match (opts.core_type, opts.numa_node) {
+        (Some(core_type), _) => {
+            pinned = RunOptions {
+                pin: cores::cpus(core_type)?,
+                ..opts.clone()
+            };
+            &pinned
+        }
+        (None, Some(node)) if opts.pin.is_empty() => {
+            pinned = RunOptions {
+                pin: numa::cpus(node)?,
+                ..opts.clone()
+            };
+            &pinned
+        }
+        _ => {
+            if opts.pin.is_empty() && cores::detect().is_some() {
+                tracing::warn!(
+                    "the CPU is hybrid and the benchmarks may run on either type of cores, \
+                     pin them with --core-type or --pin"
+                );
+            }
+            opts
+        }
+    };
+    if opts.exclusive_core && opts.pin.is_empty() {
+        anyhow::bail!(
+            "--exclusive-core needs the benchmarks pinned with --pin, --core-type or --numa-node"
+        );
+    }
+    let isolation = opts.exclusive_core.then(|| smt::Isolation::new(&opts.pin));
+    collector::check_names(&collectors, &opts.metrics())?;
+    let mut fingerprint = opts.fingerprint();
+    for collector in &collectors {
+        fingerprint.push_str(&format!(" {:?}", collector.metrics()));
+    }
+    let cache = if opts.cache {
+        let dir = opts
+            .cache_dir
+            .clone()
+            .or_else(cache::default_dir)
+            .ok_or_else(|| anyhow::anyhow!("cannot determine the cache directory"))?;
+        Some(cache::Cache::open(dir)?)
+    } else {
+        None
+    };
+    // Spawned before the counters are opened, which then don't count its thread.
+    let frequency = monitor::FrequencySampler::new(&opts.pin);
+    let cpus = monitor::CpuSampler::new();
+    let mut runner = Runner {
+        opts,
+        backend: backend::open(opts)?,
+        collectors,
+        tracer: ebpf::Tracer::new(&opts.metrics())?,
+        tasks: if opts.per_thread || opts.per_process {
+            Some(tasks::Tasks::open()?)
+        } else {
+            None
+        },
+        profiler: if opts.hot_functions {
+            Some(hot_functions::Profiler::open()?)
+        } else {
+            None
+        },
+        frequency,
+        cpus,
+        rlimits: opts.rlimits(),
+        stdout_dir,
+        fresh_dir: opts
+            .cold
+            .then(|| temp::TempDir::new("perf-bench-cold"))
+            .transpose()?,
+        can_drop_caches: true,
+        precompiled: HashMap::new(),
+        precompiled_dir: None,
+        artifact_dir: opts
+            .compile_cmd
+            .as_ref()
+            .map(|_| temp::TempDir::new("perf-bench-compiled"))
+            .transpose()?,
+    };
+    if opts.precompile {
+        let dir = runner
+            .precompiled_dir
+            .insert(temp::TempDir::new("perf-bench-wasm")?);
+        runner.precompiled = wasm::precompile(
+            benchmarks.iter().filter_map(|b| b.input.as_deref()),
+            dir.path(),
+        )?;
+    }
+    let mut binaries: HashMap<String, Option<(PathBuf, String)>> = HashMap::new();
+    let mut reused = 0;
+    // Key of the first benchmark of each command, input content and repetitions.
+    let mut originals: HashMap<(String, String, Repetitions), String> = HashMap::new();
+    // Keys of the duplicates of each original.
+    let mut duplicates: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut skipped = 0;
+    let start = std::time::Instant::now();
+    let mut res = Results::new();
+    for benchmark in benchmarks {
+        let out_of_time = opts
+            .max_total_time
+            .is_some_and(|max| start.elapsed() >= max);
+        if benchmark.skip.is_some() || out_of_time {
+            let meas = Measure {
+                command: Some(command::command_line(
+                    &benchmark.command,
+                    benchmark.input.as_deref(),
+                )),
+                note: benchmark.note.clone(),
+                status: Status::Skipped,
+                skipped: true,
+                skip_reason: match &benchmark.skip {
+                    Some(reason) => Some(reason.clone()),
+                    None => Some("the recording ran out of time".to_owned()),
+                },
+                ..Measure::default()
+            };
+            res.insert(benchmark.key.clone(), meas);
+            skipped += usize::from(benchmark.skip.is_none());
+            continue;
+        }
+        if let (true, Some(input)) = (opts.dedup, &benchmark.input) {
+            let content = (
+                benchmark.command.clone(),
+                hash::sha256_file(input)?,
+                benchmark.repetitions,
+            );
+            if let Some(original) = originals.get(&content) {
+                let mut meas = res[original].clone();
+                meas.command = Some(command::command_line(&benchmark.command, Some(input)));
+                meas.duplicate_of = Some(original.clone());
+                meas.note = benchmark.note.clone();
+                res.insert(benchmark.key.clone(), meas);
+                duplicates
+                    .entry(original.clone())
+                    .or_default()
+                    .push(benchmark.key.clone());
+                continue;
+            }
+            originals.insert(content, benchmark.key.clone());
+        }
+        let program = command::program(&benchmark.command)?;
+        if !binaries.contains_key(&program) {
+            let binary = match command::resolve_program(&program) {
+                Some(binary) => {
+                    let hash = hash::sha256_file(&binary)?;
+                    Some((binary, hash))
+                }
+                None => None,
+            };
+            binaries.insert(program.clone(), binary);
+        }
+        let binary = &binaries[&program];
+        let cache_key = match (&cache, binary) {
+            (Some(_), Some((_, binary_hash))) => Some(cache::Cache::key(
+                &benchmark.command,
+                binary_hash,
+                benchmark.input.as_deref(),
+                &match benchmark.repetitions {
+                    repetitions if repetitions == Repetitions::default() => fingerprint.clone(),
+                    repetitions => format!("{fingerprint} {repetitions:?}"),
+                },
+            )?),
+            (Some(_), None) => anyhow::bail!("cannot find {program} to hash it"),
+            (None, _) => None,
+        };
+        if let (Some(cache), Some(cache_key)) = (&cache, &cache_key) {
+            if let Some(mut meas) = cache.get::<Measure>(cache_key) {
+                meas.note = benchmark.note.clone();
+                res.insert(benchmark.key.clone(), meas);
+                reused += 1;
+                continue;
+            }
+        }
+        let mut meas = match &opts.compile_cmd {
+            Some(compile) => runner.measure_phases(benchmark, compile)?,
+            None => runner.measure(benchmark)?,
+        };
+        meas.smt_shared = isolation.as_ref().is_some_and(smt::Isolation::shared);
+        if let (true, Some(input)) = (runner.requested("input_size"), &benchmark.input) {
+            let size = std::fs::metadata(input)?.len() as f64;
+            meas.metrics.insert("input_size".to_owned(), size);
+        }
+        meas.command = Some(command::command_line(
+            &benchmark.command,
+            benchmark.input.as_deref(),
+        ));
+        if let Some((path, hash)) = binary {
+            meas.binary = Some(path.clone());
+            meas.binary_sha256 = Some(hash.clone());
+        }
+        meas.note = benchmark.note.clone();
+        if let (Some(cache), Some(cache_key)) = (&cache, &cache_key) {
+            cache.put(cache_key, &meas)?;
+        }
+        res.insert(benchmark.key.clone(), meas);
+    }
+    if reused > 0 {
+        tracing::info!("reused {reused} cached measurement(s)");
+    }
+    if skipped > 0 {
+        tracing::warn!(
+            "the recording ran out of time, skipping {skipped} benchmark(s), left for \
+             `record --update` to measure"
+        );
+    }
+    summarize_failures(&res);
+    report_duplicates(&duplicates);
+    Ok(res)
+}
+
+/// Lists the benchmarks measured once for their `duplicates`, the keys of those given their
+/// results.
+fn report_duplicates(duplicates: &BTreeMap<String, Vec<String>>) {
+    if duplicates.is_empty() {
+        return;
+    }
+    let mut report = format!(
+        "{} benchmark(s) duplicated others, measured once and given their results:",
+        duplicates.values().map(Vec::len).sum::<usize>()
+    );
+    for (original, keys) in duplicates {
+        report.push_str(&format!("\n  {original}: {}", keys.join(", ")));
+    }
+    tracing::info!("{report}");
+}
+
+/// Warns about the benchmarks that failed, with the number of crashes by signal.
+fn summarize_failures(res: &Results) {
+    let mut signals: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut timeouts, mut exits) = (0, 0);
+    for measure in res.values() {
+        match measure.status {
+            Status::Signal { signal, .. } => {
+                *signals.entry(child::signal_name(signal)).or_default() += 1;
+            }
+            Status::Timeout => timeouts += 1,
+            Status::NonzeroExit { .. } => exits += 1,
+            _ => {}
+        }
+    }
+    let mut failures = Vec::new();
+    if !signals.is_empty() {
+        let crashes: Vec<String> = signals
+            .iter()
+            .map(|(name, n)| format!("{n} {name}"))
+            .collect();
+        failures.push(format!(
+            "{} crash(es) ({})",
+            signals.values().sum::<usize>(),
+            crashes.join(", ")
+        ));
+    }
+    if timeouts > 0 {
+        failures.push(format!("{timeouts} timeout(s)"));
+    }
+    if exits > 0 {
+        failures.push(format!("{exits} nonzero exit(s)"));
+    }
+    if !failures.is_empty() {
+        tracing::warn!("failed benchmarks: {}", failures.join(", "));
+    }
+}
+
+pub fn print_measures(res: &Results, display: metrics::DisplayOptions) {
+    print_rows(res, res.keys(), None, display);
+}
+
+/// Like [`print_measures`], with the benchmarks grouped `by` and the subtotal of each group
+/// after its benchmarks if any, and with the `total` of all of them last.
+pub fn print_summed_measures(
+    res: &Results,
+    by: Option<GroupBy>,
+    total: bool,
+    display: metrics::DisplayOptions,
+) {
+    let total = total.then(|| group::sum(res, res.keys()));
+    match by {
+        Some(by) => {
+            let (res, order) = group::with_subtotals(res, &group::keys(res, by));
+            print_rows(&res, &order, total, display);
+        }
+        None => print_rows(res, res.keys(), total, display),
+    }
+}
+
+/// Prints the measures of the benchmarks `keys` of `res`, in that order, followed by the
+/// `total` of all of them if any.
+fn print_rows<'a>(
+    res: &Results,
+    keys: impl IntoIterator<Item = &'a String>,
+    total: Option<Measure>,
+    display: metrics::DisplayOptions,
+) {
+    let names = crate::results::metric_names([res]);
+    let mut table = prettytable::Table::new();
+    let mut header = vec![prettytable::Cell::new("File")];
+    header.extend(
+        names
+            .iter()
+            .map(|name| prettytable::Cell::new(&metrics::header(name, display))),
+    );
+    header.push(prettytable::Cell::new("Load avg"));
+    header.push(prettytable::Cell::new("Interference"));
+    let notes = res.values().any(|meas| meas.note.is_some());
+    if notes {
+        header.push(prettytable::Cell::new("Note"));
+    }
+    table.add_row(prettytable::Row::new(header));
+    for (input_file, meas) in keys
+        .into_iter()
+        .filter_map(|key| Some((key.as_str(), res.get(key)?)))
+        .chain(total.as_ref().map(|total| (group::TOTAL, total)))
+    {
+        let mut row = vec![if meas.incorrect {
+            let mut cell = prettytable::Cell::new(&format!("{input_file} (incorrect)"));
+            cell.style(prettytable::Attr::ForegroundColor(prettytable::color::RED));
+            cell
+        } else if meas.status.is_failure() {
+            let mut cell = prettytable::Cell::new(&format!("{input_file} ({})", meas.status));
+            cell.style(prettytable::Attr::ForegroundColor(prettytable::color::RED));
+            cell
+        } else if meas.skipped {
+            prettytable::Cell::new(&format!("{input_file} (skipped)"))
+        } else {
+            prettytable::Cell::new(input_file)
+        }];
+        row.extend(names.iter().map(|name| match meas.metrics.get(name) {
+            Some(&value) => prettytable::Cell::new(&metrics::display(name, value, display)),
+            None => prettytable::Cell::new("-"),
+        }));
+        row.push(prettytable::Cell::new(&format!("{:.2}", meas.load_avg)));
+        row.push(prettytable::Cell::new(&format!(
+            "{:.1}%",
+            meas.interference * 100.
+        )));
+        if notes {
+            row.push(prettytable::Cell::new(meas.note.as_deref().unwrap_or("")));
+        }
+        table.add_row(prettytable::Row::new(row));
+    }
+    table.printstd();
+}
+
+/// Prints how the metrics of the benchmark `key` distribute across its `tasks`, the busiest
+/// first.
+fn print_breakdown(
+    key: &str,
+    kind: &str,
+    tasks: &BTreeMap<String, Metrics>,
+    display: metrics::DisplayOptions,
+) {
+    let mut names: Vec<String> = tasks
+        .values()
+        .flat_map(|metrics| metrics.keys().cloned())
+        .collect();
+    names.sort();
+    names.dedup();
+    crate::results::sort_metric_names(&mut names);
+    let totals: Vec<f64> = names
+        .iter()
+        .map(|name| tasks.values().filter_map(|m| m.get(name)).sum())
+        .collect();
+    let mut tasks: Vec<(&String, &Metrics)> = tasks.iter().collect();
+    tasks.sort_by(|a, b| {
+        let value = |metrics: &Metrics| metrics.get(&names[0]).copied().unwrap_or(0.);
+        value(b.1).total_cmp(&value(a.1))
+    });
+    println!("{key}: {} {}(s)", tasks.len(), kind.to_lowercase());
+    let mut table = prettytable::Table::new();
+    let mut header = vec![prettytable::Cell::new(kind)];
+    for name in &names {
+        header.push(prettytable::Cell::new(&metrics::header(name, display)));
+        header.push(prettytable::Cell::new("Share"));
+    }
+    table.add_row(prettytable::Row::new(header));
+    for (task, metrics) in tasks {
+        let mut row = vec![prettytable::Cell::new(task)];
+        for (name, total) in names.iter().zip(&totals) {
+            match metrics.get(name) {
+                Some(&value) => {
+                    row.push(prettytable::Cell::new(&metrics::display(
+                        name, value, display,
+                    )));
+                    row.push(prettytable::Cell::new(&format!(
+                        "{:.1}%",
+                        value / total * 100.
+                    )));
+                }
+                None => row.extend([prettytable::Cell::new("-"), prettytable::Cell::new("-")]),
+            }
+        }
+        table.add_row(prettytable::Row::new(row));
+    }
+    table.printstd();
+}
+
+/// Prints how the metrics of each benchmark measured with `--per-process` or `--per-thread`
+/// distribute across its processes and threads.
+pub fn print_breakdowns(res: &Results, display: metrics::DisplayOptions) {
+    for (key, meas) in res {
+        if !meas.processes.is_empty() {
+            print_breakdown(key, "Process", &meas.processes, display);
+        }
+        if !meas.threads.is_empty() {
+            print_breakdown(key, "Thread", &meas.threads, display);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Recording on other machines over SSH: the inputs are copied to a temporary directory of
+//! each, where the orchestrator installed there measures the benchmarks, and the results are
+//! copied back. Several machines share the benchmarks out and run in parallel.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    command, hash,
+    record::Benchmark,
+    results::{self, ResultsFile},
+    temp::TempDir,
+    RunOptions,
+};
+
+pub struct Remote {
+    /// Destination of ssh and scp, e.g. `user@host`.
+    pub host: String,
+    /// Orchestrator program on the remote machine.
+    pub program: String,
+    /// Directory of the remote machine holding the inputs at their relative paths, which are
+    /// then not copied.
+    pub corpus: Option<PathBuf>,
+}
+
+fn quote(word: &str) -> String {
+    command::join(&[word.to_owned()])
+}
+
+fn check(command: &mut Command, what: &str) -> anyhow::Result<()> {
+    let status = command
+        .status()
+        .map_err(|e| anyhow::anyhow!("cannot {what}: {e}"))?;
+    if !status.success() {
+        anyhow::bail!("cannot {what}: {status}");
+    }
+    Ok(())
+}
+
+/// The flags of the remote `record` command measuring as `opts`.
+fn record_args(opts: &RunOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if !opts.metrics.is_empty() {
+        args.push(format!("--metrics={}", opts.metrics.join(",")));
+    }
+    if let Some(backend) = opts.backend {
+        args.push(format!("--backend={}", backend.name()));
+    }
+    if let Some(qemu) = &opts.qemu {
+        args.push(format!("--qemu={qemu}"));
+    }
+    if let Some(plugin) = &opts.qemu_plugin {
+        args.push(format!("--qemu-plugin={}", plugin.display()));
+    }
+    if let Some(iterations) = opts.iterations {
+        args.push(format!("--iterations={iterations}"));
+    }
+    if let Some(warmup) = opts.warmup {
+        args.push(format!("--warmup={warmup}"));
+    }
+    if let Some(node) = opts.numa_node {
+        args.push(format!("--numa-node={node}"));
+    }
+    if let Some(core_type) = opts.core_type {
+        args.push(format!("--core-type={}", core_type.name()));
+    }
+    if !opts.pin.is_empty() {
+        let cpus: Vec<String> = opts.pin.iter().map(usize::to_string).collect();
+        args.push(format!("--pin={}", cpus.join(",")));
+    }
+    for (set, flag) in [
+        (opts.cold, "--cold"),
+        (opts.precompile, "--precompile"),
+        (opts.shell, "--shell"),
+        (opts.per_thread, "--per-thread"),
+        (opts.per_process, "--per-process"),
+        (opts.hot_functions, "--hot-functions"),
+        (opts.roi, "--roi"),
+        (opts.exclusive_core, "--exclusive-core"),
+        (opts.dedup, "--dedup"),
+    ] {
+        // Unset flags are passed too, for the configuration of the remote machine not to set
+        // them.
+        if set {
+            args.push(flag.into());
+        } else {
+            args.push(flag.replacen("--", "--no-", 1));
+        }
+    }
+    if let Some(skip_start) = opts.skip_start {
+        args.push(format!("--skip-start={}us", skip_start.as_micros()));
+    }
+    if let Some(max_total_time) = opts.max_total_time {
+        args.push(format!("--max-total-time={}us", max_total_time.as_micros()));
+    }
+    if let Some(compile_cmd) = &opts.compile_cmd {
+        args.push(format!("--compile-cmd={compile_cmd}"));
+    }
+    if let Some(limit) = opts.memory_limit {
+        args.push(format!("--memory-limit={limit}"));
+    }
+    for rlimit in &opts.rlimit {
+        args.push(format!("--rlimit={rlimit}"));
+    }
+    if let Some(timeout) = opts.timeout {
+        args.push(format!("--timeout={}us", timeout.as_micros()));
+    }
+    for metric in &opts.extra_metric {
+        args.push(format!("--extra-metric={}={}", metric.name, metric.command));
+    }
+    for breakpoint in &opts.breakpoint {
+        args.push(format!("--breakpoint={breakpoint}"));
+    }
+    for call in &opts.count_calls {
+        args.push(format!("--count-calls={call}"));
+    }
+    if let Some(format) = opts.parse_output {
+        let format = clap::ValueEnum::to_possible_value(&format).expect("no skipped variant");
+        args.push(format!("--parse-output={}", format.get_name()));
+    }
+    if let Some(image) = &opts.container {
+        args.push(format!("--container={image}"));
+    }
+    if let Some(runtime) = &opts.container_runtime {
+        args.push(format!("--container-runtime={runtime}"));
+    }
+    args
+}
+
+impl Remote {
+    /// Runs the shell command `script` on the remote machine.
+    fn ssh(&self, script: &str) -> Command {
+        let mut ssh = Command::new("ssh");
+        ssh.arg(&self.host).arg("--").arg(script);
+        ssh
+    }
+
+    fn location(&self, path: &str) -> String {
+        format!("{}:{path}", self.host)
+    }
+
+    /// Where the remote recording finds the input of each benchmark, relative to `dir`,
+    /// copying it there unless it is in the remote corpus.
+    fn stage_inputs(&self, benchmarks: &[Benchmark], dir: &str) -> anyhow::Result<Vec<String>> {
+        let mut inputs = Vec::new();
+        // Copies are grouped into batches of distinct file names, each copied by a single scp.
+        let mut batches: Vec<(HashSet<&std::ffi::OsStr>, Vec<&Path>)> = Vec::new();
+        for benchmark in benchmarks {
+            let Some(input) = &benchmark.input else {
+                inputs.push(String::new());
+                continue;
+            };
+            if let Some(corpus) = &self.corpus {
+                if input.is_absolute() {
+                    anyhow::bail!("{} is not relative to the remote corpus", input.display());
+                }
+                inputs.push(corpus.join(input).display().to_string());
+                continue;
+            }
+            let name = input
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("{} is not a file", input.display()))?;
+            let batch = match batches.iter().position(|(names, _)| !names.contains(name)) {
+                Some(batch) => batch,
+                None => {
+                    batches.push(Default::default());
+                    batches.len() - 1
+                }
+            };
+            batches[batch].0.insert(name);
+            batches[batch].1.push(input);
+            inputs.push(format!("inputs/{batch}/{}", name.to_string_lossy()));
+        }
+        for (batch, (_, files)) in batches.iter().enumerate() {
+            let target = format!("{dir}/inputs/{batch}");
+            check(
+                &mut self.ssh(&format!("mkdir -p {}", quote(&target))),
+                &format!("create {target} on {}", self.host),
+            )?;
+            tracing::info!("copying {} input(s) to {}", files.len(), self.host);
+            check(
+                Command::new("scp")
+                    .arg("-q")
+                    .args(files)
+                    .arg(self.location(&format!("{target}/"))),
+                &format!("copy the inputs to {}", self.host),
+            )?;
+        }
+        Ok(inputs)
+    }
+
+    fn record_in(
+        &self,
+        benchmarks: &[Benchmark],
+        opts: &RunOptions,
+        dir: &str,
+    ) -> anyhow::Result<ResultsFile> {
+        let inputs = self.stage_inputs(benchmarks, dir)?;
+        let entries: Vec<serde_json::Value> = benchmarks
+            .iter()
+            .zip(inputs)
+            .map(|(benchmark, input)| {
+                let mut entry = serde_json::json!({
+                    "name": benchmark.key,
+                    "command": benchmark.command,
+                });
+                if benchmark.input.is_some() {
+                    entry["input"] = input.into();
+                }
+                if let Some(expected) = &benchmark.expected_stdout {
+                    entry["expected_stdout_sha256"] = expected.as_str().into();
+                }
+                if let Some(note) = &benchmark.note {
+                    entry["note"] = note.as_str().into();
+                }
+                if let Some(reason) = &benchmark.skip {
+                    entry["skip"] = reason.as_str().into();
+                }
+                let repetitions = benchmark.repetitions;
+                if let Some(iterations) = repetitions.iterations {
+                    entry["iterations"] = iterations.into();
+                }
+                if let Some(warmup) = repetitions.warmup {
+                    entry["warmup"] = warmup.into();
+                }
+                if let Some(max_time) = repetitions.max_time {
+                    entry["max_time"] = format!("{}us", max_time.as_micros()).into();
+                }
+                entry
+            })
+            .collect();
+        let local = TempDir::new("perf-bench-remote")?;
+        let manifest = local.path().join("manifest.json");
+        std::fs::write(
+            &manifest,
+            serde_json::to_string_pretty(&serde_json::json!({ "benchmarks": entries }))?,
+        )?;
+        check(
+            Command::new("scp")
+                .arg("-q")
+                .arg(&manifest)
+                .arg(self.location(&format!("{dir}/manifest.json"))),
+            &format!("copy the manifest to {}", self.host),
+        )?;
+        tracing::info!(
+            "recording {} benchmark(s) on {}",
+            benchmarks.len(),
+            self.host
+        );
+        let mut record = vec![
+            self.program.clone(),
+            "record".into(),
+            "--manifest=manifest.json".into(),
+            "--output=results.json".into(),
+        ];
+        record.extend(record_args(opts));
+        let script = format!("cd {} && {}", quote(dir), command::join(&record));
+        // The remote output goes to the standard error, to leave the standard output to
+        // the results printed here.
+        check(
+            self.ssh(&script).stdout(std::io::stderr()),
+            &format!("record on {}", self.host),
+        )?;
+        let results = local.path().join("results.json");
+        check(
+            Command::new("scp")
+                .arg("-q")
+                .arg(self.location(&format!("{dir}/results.json")))
+                .arg(&results),
+            &format!("copy the results from {}", self.host),
+        )?;
+        results::read(&results)
+    }
+
+    /// Records `benchmarks` on the remote machine, measured as `opts` there.
+    pub fn record(
+        &self,
+        benchmarks: &[Benchmark],
+        opts: &RunOptions,
+    ) -> anyhow::Result<ResultsFile> {
+        let output = self
+            .ssh("mktemp -d -t perf-bench-XXXXXX")
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| anyhow::anyhow!("cannot run ssh: {e}"))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "cannot create a directory on {}: {}",
+                self.host,
+                output.status
+            );
+        }
+        let dir = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        let result = self.record_in(benchmarks, opts, &dir);
+        if let Err(e) = check(
+            &mut self.ssh(&format!("rm -rf {}", quote(&dir))),
+            &format!("remove {dir} from {}", self.host),
+        ) {
+            tracing::warn!("{e}");
+        }
+        result
+    }
+}
+
+/// The index of the machine among `n` that measures the benchmark `key`, the same in every
+/// recording so that comparisons are within a machine.
+fn shard(key: &str, n: usize) -> usize {
+    let hash = u64::from_str_radix(&hash::sha256(key.as_bytes())[..16], 16).expect("hex");
+    (hash % n as u64) as usize
+}
+
+/// Records `benchmarks` shared out between the `remotes`, in parallel, merging their results
+/// with the machine each benchmark was measured on.
+pub fn record_sharded(
+    remotes: &[Remote],
+    benchmarks: &[Benchmark],
+    opts: &RunOptions,
+) -> anyhow::Result<ResultsFile> {
+    if let [remote] = remotes {
+        return remote.record(benchmarks, opts);
+    }
+    let mut shards = vec![Vec::new(); remotes.len()];
+    for benchmark in benchmarks {
+        shards[shard(&benchmark.key, remotes.len())].push(benchmark.clone());
+    }
+    let recorded: Vec<anyhow::Result<ResultsFile>> = std::thread::scope(|scope| {
+        let threads: Vec<_> = remotes
+            .iter()
+            .zip(&shards)
+            .filter(|(_, shard)| !shard.is_empty())
+            .map(|(remote, shard)| scope.spawn(|| remote.record(shard, opts)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().expect("a recording thread panicked"))
+            .collect()
+    });
+    let mut merged: Option<ResultsFile> = None;
+    let remotes = remotes
+        .iter()
+        .zip(&shards)
+        .filter(|(_, shard)| !shard.is_empty());
+    for ((remote, _), file) in remotes.zip(recorded) {
+        let mut file = file?;
+        for measure in file.results.values_mut() {
+            measure.host = Some(remote.host.clone());
+        }
+        let merged = merged.get_or_insert_with(|| ResultsFile {
+            metadata: file.metadata.clone(),
+            results: Default::default(),
+        });
+        if let (Some(metadata), Some(host)) = (&mut merged.metadata, file.metadata) {
+            metadata.hosts.insert(remote.host.clone(), host);
+        }
+        merged.results.append(&mut file.results);
+    }
+    merged.ok_or_else(|| anyhow::anyhow!("no benchmarks to record"))
+}
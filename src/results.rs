@@ -0,0 +1,513 @@
+//! Results files: the measures of a set of benchmarks, by key.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    child,
+    metadata::{Git, Metadata},
+    metrics::{self, Metrics},
+    monitor,
+};
+
+/// How the measurement of a benchmark went, the first failure among its runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Status {
+    #[default]
+    Ok,
+    NonzeroExit {
+        code: i32,
+    },
+    /// Killed on reaching its CPU time limit.
+    Timeout,
+    Signal {
+        signal: i32,
+        /// Whether the kernel wrote a core dump of the process.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        core_dumped: bool,
+    },
+    /// Some requested metrics are missing for lack of counters.
+    CountersUnavailable,
+    Skipped,
+}
+
+impl Status {
+    pub fn is_ok(&self) -> bool {
+        *self == Status::Ok
+    }
+
+    /// Whether a run of the benchmark did not complete successfully.
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Status::NonzeroExit { .. } | Status::Timeout | Status::Signal { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::NonzeroExit { code } => write!(f, "exited with code {code}"),
+            Status::Timeout => write!(f, "timed out"),
+            Status::Signal {
+                signal,
+                core_dumped,
+            } => {
+                write!(f, "killed by {}", child::signal_name(*signal))?;
+                if *core_dumped {
+                    write!(f, " (core dumped)")?;
+                }
+                Ok(())
+            }
+            Status::CountersUnavailable => write!(f, "counters unavailable"),
+            Status::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// Version of the format of results files, incremented on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Measure {
+    /// Median over the iterations of each metric.
+    #[serde(
+        flatten,
+        serialize_with = "metrics::serialize_metrics",
+        deserialize_with = "metrics::deserialize_metrics"
+    )]
+    pub metrics: Metrics,
+    /// Command line that was measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Path the benchmarked program resolved to, and the SHA-256 of that binary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_sha256: Option<String>,
+    /// Key of the benchmark with the same command and input content whose measurement this
+    /// is a copy of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    /// Annotation of the benchmark, from its manifest entry or the notes file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub load_avg: f64,
+    #[serde(default)]
+    pub interference: f64,
+    /// Frequency of the CPUs it ran on, over its runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<monitor::Frequency>,
+    /// CPUs its threads were seen running on, over its runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpus: Vec<usize>,
+    /// Resource limit that most likely made the run fail.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_hit: Option<String>,
+    #[serde(default, skip_serializing_if = "Status::is_ok")]
+    pub status: Status,
+    /// Whether the benchmark was not measured, and why.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    /// Whether some counters were multiplexed, and their values extrapolated.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub multiplexed: bool,
+    /// Whether the benchmark was to run on exclusive cores but their SMT siblings stayed
+    /// online, possibly running other tasks.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub smt_shared: bool,
+    /// Whether some runs printed another output than the expected one, making their
+    /// measures meaningless.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incorrect: bool,
+    /// Requested metrics that could not be measured on this machine.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unavailable: Vec<String>,
+    /// Instructions executed in each function, with the callgrind backend.
+    #[serde(
+        default,
+        skip_serializing_if = "Metrics::is_empty",
+        serialize_with = "metrics::serialize_metrics"
+    )]
+    pub functions: Metrics,
+    /// Metrics of each thread, with `--per-thread`.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "metrics::serialize_grouped_metrics"
+    )]
+    pub threads: BTreeMap<String, Metrics>,
+    /// Metrics of each process of the process tree, with `--per-process`.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "metrics::serialize_grouped_metrics"
+    )]
+    pub processes: BTreeMap<String, Metrics>,
+    /// Self cycles of each function, or its self CPU clock, from samples with
+    /// `--hot-functions`.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "metrics::serialize_grouped_metrics"
+    )]
+    pub hot_functions: BTreeMap<String, Metrics>,
+    /// Metrics of every iteration, when there were several.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<Sample>,
+    /// Metrics of a second recording of the benchmark in the same session, whose difference
+    /// with the first one estimates its noise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_run: Option<Sample>,
+    /// Machine it was measured on, in a recording sharded over SSH.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Sample {
+    #[serde(
+        flatten,
+        serialize_with = "metrics::serialize_metrics",
+        deserialize_with = "metrics::deserialize_metrics"
+    )]
+    pub metrics: Metrics,
+    /// CPUs the threads of the run were seen running on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpus: Vec<usize>,
+}
+
+impl Measure {
+    /// Relative standard deviation of the iterations of each metric, if there are several.
+    pub fn spreads(&self) -> BTreeMap<String, f64> {
+        let mut values: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+        for sample in &self.samples {
+            for (name, &value) in &sample.metrics {
+                values.entry(name).or_default().push(value);
+            }
+        }
+        values
+            .into_iter()
+            .filter(|(_, values)| values.len() > 1)
+            .map(|(name, values)| {
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                let variance =
+                    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+                let spread = if mean == 0. {
+                    0.
+                } else {
+                    variance.sqrt() / mean
+                };
+                (name.to_owned(), spread)
+            })
+            .collect()
+    }
+
+    pub fn is_noisy(&self) -> bool {
+        self.interference > monitor::INTERFERENCE_THRESHOLD
+    }
+
+    /// Whether some requested metrics are missing for lack of counters.
+    pub fn is_degraded(&self) -> bool {
+        !self.unavailable.is_empty()
+    }
+}
+
+pub type Results = BTreeMap<String, Measure>;
+
+/// Stores the metrics of `again`, a second recording of the benchmarks of `res`, as their
+/// noise run.
+pub fn add_noise_run(res: &mut Results, again: &Results) {
+    for (key, measure) in res.iter_mut() {
+        match again.get(key) {
+            Some(again) if !measure.skipped && !again.skipped => {
+                measure.noise_run = Some(Sample {
+                    metrics: again.metrics.clone(),
+                    cpus: again.cpus.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The second recording of the benchmarks of `res` that have a noise estimate, none if
+/// none has.
+pub fn noise_run(res: &Results) -> Option<Results> {
+    let noise: Results = res
+        .iter()
+        .filter_map(|(key, measure)| {
+            let metrics = measure.noise_run.as_ref()?.metrics.clone();
+            Some((
+                key.clone(),
+                Measure {
+                    metrics,
+                    ..Default::default()
+                },
+            ))
+        })
+        .collect();
+    (!noise.is_empty()).then_some(noise)
+}
+
+/// Removes the benchmarks `res` skipped, returning them.
+pub fn remove_skipped(res: &mut Results) -> Results {
+    let skipped: Vec<String> = res
+        .iter()
+        .filter(|(_, measure)| measure.skipped)
+        .map(|(key, _)| key.clone())
+        .collect();
+    skipped
+        .into_iter()
+        .filter_map(|key| res.remove_entry(&key))
+        .collect()
+}
+
+#[derive(serde::Deserialize)]
+#[serde(try_from = "serde_json::Value")]
+#[non_exhaustive]
+pub struct ResultsFile {
+    /// Absent from the files written before metadata was recorded.
+    pub metadata: Option<Metadata>,
+    pub results: Results,
+}
+
+/// What is read of a [`ResultsFile`] of a supported version.
+#[derive(serde::Deserialize)]
+struct Read {
+    metadata: Option<Box<Metadata>>,
+    results: Results,
+}
+
+impl TryFrom<serde_json::Value> for ResultsFile {
+    type Error = String;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let version = match value.get("schema_version") {
+            Some(version) => Some(
+                version
+                    .as_u64()
+                    .ok_or_else(|| format!("invalid schema_version {version}"))?,
+            ),
+            None => None,
+        };
+        if let Some(version) = version.filter(|&version| version > SCHEMA_VERSION.into()) {
+            return Err(format!(
+                "written in version {version} of the format, newer than the supported \
+                 {SCHEMA_VERSION}"
+            ));
+        }
+        // The files written before the format was versioned, which are of its first version,
+        // hold either the fields of a results file or a bare map of measures.
+        let bare = version.is_none()
+            && !value.as_object().is_some_and(|fields| {
+                fields.contains_key("results")
+                    && fields
+                        .keys()
+                        .all(|name| matches!(name.as_str(), "metadata" | "units" | "results"))
+            });
+        if bare {
+            let results = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            return Ok(ResultsFile {
+                metadata: None,
+                results,
+            });
+        }
+        let read: Read = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        Ok(ResultsFile {
+            metadata: read.metadata.map(|metadata| *metadata),
+            results: read.results,
+        })
+    }
+}
+
+/// What is written of a [`ResultsFile`], with the units of its metrics for other tools.
+#[derive(serde::Serialize)]
+struct Written<'a> {
+    schema_version: u32,
+    metadata: &'a Option<Metadata>,
+    units: BTreeMap<String, &'static str>,
+    results: &'a Results,
+}
+
+impl serde::Serialize for ResultsFile {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Written {
+            schema_version: SCHEMA_VERSION,
+            metadata: &self.metadata,
+            units: metric_names([&self.results])
+                .into_iter()
+                .map(|name| {
+                    let unit = metrics::unit(&name).name();
+                    (name, unit)
+                })
+                .collect(),
+            results: &self.results,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl ResultsFile {
+    /// Results recorded on the current machine.
+    pub fn new(results: Results) -> Self {
+        let mut file = ResultsFile {
+            metadata: Some(Metadata::collect()),
+            results,
+        };
+        file.detect_git();
+        file
+    }
+
+    /// Records in the metadata the git revision the benchmarked binaries were built from.
+    pub fn detect_git(&mut self) {
+        if let Some(metadata) = &mut self.metadata {
+            metadata.git = Git::detect(self.results.values().filter_map(|m| m.binary.as_deref()));
+        }
+    }
+}
+
+/// `res` with the metrics of each measure and sample divided by those of the measure
+/// `reference`, leaving out the metrics it lacks or that are zero in it.
+pub fn normalize(res: &Results, reference: &str) -> anyhow::Result<Results> {
+    let base = &res
+        .get(reference)
+        .ok_or_else(|| anyhow::anyhow!("no benchmark {reference:?} to normalize to"))?
+        .metrics;
+    let divide = |metrics: &Metrics| -> Metrics {
+        metrics
+            .iter()
+            .filter_map(|(name, value)| {
+                let base = base.get(name).filter(|&&base| base != 0.)?;
+                Some((name.clone(), value / base))
+            })
+            .collect()
+    };
+    Ok(res
+        .iter()
+        .map(|(key, measure)| {
+            let mut measure = measure.clone();
+            measure.metrics = divide(&measure.metrics);
+            for sample in &mut measure.samples {
+                sample.metrics = divide(&sample.metrics);
+            }
+            (key.clone(), measure)
+        })
+        .collect())
+}
+
+/// Names of the metrics present in `results`, known metrics first in their usual order.
+pub fn metric_names<'a>(results: impl IntoIterator<Item = &'a Results>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for res in results {
+        for measure in res.values() {
+            for name in measure.metrics.keys() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+    }
+    sort_metric_names(&mut names);
+    names
+}
+
+/// Sorts known metrics first in their usual order, then the others alphabetically.
+pub fn sort_metric_names(names: &mut [String]) {
+    // Metrics outside phases first, then grouped by phase.
+    let rank = |name: &String| {
+        let (phase, metric) = metrics::split_phase(name);
+        let phase = phase.map(|phase| metrics::PHASES.iter().position(|p| *p == phase));
+        let metric = metrics::METRICS
+            .iter()
+            .position(|m| m.name == metric)
+            .unwrap_or(usize::MAX);
+        (phase, metric)
+    };
+    names.sort_by(|a, b| rank(a).cmp(&rank(b)).then(a.cmp(b)));
+}
+
+pub fn read(path: &Path) -> anyhow::Result<ResultsFile> {
+    let file = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+    serde_json::from_str(&file)
+        .map_err(|e| anyhow::anyhow!("invalid results file {}: {e}", path.display()))
+}
+
+/// Writes `res` to `path`, failing if it already exists.
+pub fn write_new(path: &Path, res: &ResultsFile) -> anyhow::Result<()> {
+    let output = std::fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(path)?;
+    serde_json::to_writer_pretty(output, res)?;
+    Ok(())
+}
+
+pub fn write_atomically(path: &Path, res: &ResultsFile) -> anyhow::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    serde_json::to_writer_pretty(std::fs::File::create(&tmp)?, res)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<ResultsFile, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn reads_versions() {
+        let file = parse(r#"{"schema_version": 1, "results": {"a": {"cycles": 10}}}"#).unwrap();
+        assert_eq!(file.results["a"].metrics["cycles"], 10.);
+        let file = parse(r#"{"metadata": null, "results": {"a": {"cycles": 10}}}"#).unwrap();
+        assert_eq!(file.results["a"].metrics["cycles"], 10.);
+        let file = parse(r#"{"a": {"cycles": 10}, "results": {"cycles": 5}}"#).unwrap();
+        assert_eq!(file.results["results"].metrics["cycles"], 5.);
+        assert!(file.metadata.is_none());
+        let error = parse(r#"{"schema_version": 2, "results": {}}"#)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("newer than the supported"));
+        assert!(parse(r#"{"schema_version": "1", "results": {}}"#).is_err());
+    }
+
+    #[test]
+    fn skips_unknown_fields() {
+        let file = parse(
+            r#"{"schema_version": 1, "results": {"a": {
+                "cycles": 10, "runner": "x86", "flags": [1], "command": "prog",
+                "samples": [{"cycles": 9, "note": "warm"}]
+            }}}"#,
+        )
+        .unwrap();
+        let measure = &file.results["a"];
+        assert_eq!(measure.metrics, Metrics::from([("cycles".to_owned(), 10.)]));
+        assert_eq!(measure.command.as_deref(), Some("prog"));
+        assert_eq!(
+            measure.samples[0].metrics,
+            Metrics::from([("cycles".to_owned(), 9.)])
+        );
+    }
+
+    #[test]
+    fn reports_invalid_fields() {
+        let error = parse(r#"{"schema_version": 1, "results": {"a": {"load_avg": "high"}}}"#)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("invalid type"));
+    }
+}
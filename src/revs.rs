@@ -0,0 +1,155 @@
+//! Checking out, building and benchmarking git revisions, for `compare-revs` and `bisect`.
+
+use std::{path::PathBuf, process::Command};
+
+use crate::{command, temp::TempDir};
+
+fn git(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .stderr(std::process::Stdio::inherit())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed ({}){}",
+            args.join(" "),
+            output.status,
+            if stdout.is_empty() {
+                String::new()
+            } else {
+                format!(":\n{stdout}")
+            }
+        );
+    }
+    Ok(stdout)
+}
+
+/// The commit `rev` names in the repository of the current directory.
+pub fn resolve(rev: &str) -> anyhow::Result<String> {
+    git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("{rev}^{{commit}}"),
+    ])
+    .map_err(|_| anyhow::anyhow!("unknown git revision {rev:?}"))
+}
+
+/// Where a bisection stands after marking a commit.
+pub enum Bisection {
+    /// The commit now checked out is to be tested.
+    Test(String),
+    FirstBad(String),
+}
+
+/// The path of a worktree in the private directory `dir`.
+fn checkout(dir: &TempDir) -> PathBuf {
+    dir.path().join("checkout")
+}
+
+/// A temporary checkout of a revision, removed when dropped.
+pub struct Worktree {
+    pub rev: String,
+    /// Private directory of the checkout.
+    dir: TempDir,
+}
+
+impl Worktree {
+    /// Checks `rev` out in a new detached worktree of the repository of the current directory.
+    pub fn add(rev: &str, index: usize) -> anyhow::Result<Self> {
+        let commit = resolve(rev)?;
+        let dir = TempDir::new(&format!("perf-bench-rev-{index}"))?;
+        let path = checkout(&dir);
+        tracing::info!("checking {rev} out in {}", path.display());
+        git(&[
+            "worktree",
+            "add",
+            "--detach",
+            &path.display().to_string(),
+            &commit,
+        ])?;
+        Ok(Self {
+            rev: rev.to_owned(),
+            dir,
+        })
+    }
+
+    fn path(&self) -> PathBuf {
+        checkout(&self.dir)
+    }
+
+    fn git(&self, args: &[&str]) -> anyhow::Result<String> {
+        let path = self.path().display().to_string();
+        let mut full = vec!["-C", &path];
+        full.extend(args);
+        git(&full)
+    }
+
+    /// Checks `rev` out in place of the current revision.
+    pub fn checkout(&mut self, rev: &str) -> anyhow::Result<()> {
+        self.git(&["checkout", "--quiet", "--detach", rev])?;
+        self.rev = rev.to_owned();
+        Ok(())
+    }
+
+    /// Starts a `git bisect` in the worktree between the revisions `bad` and `good`.
+    pub fn bisect_start(&mut self, bad: &str, good: &str) -> anyhow::Result<Bisection> {
+        let output = self.git(&["bisect", "start", bad, good])?;
+        self.bisection(&output)
+    }
+
+    /// Marks the checked out commit as `good`, `bad` or `skip`.
+    pub fn bisect_mark(&mut self, mark: &str) -> anyhow::Result<Bisection> {
+        let output = self.git(&["bisect", mark])?;
+        self.bisection(&output)
+    }
+
+    fn bisection(&mut self, output: &str) -> anyhow::Result<Bisection> {
+        if let Some(line) = output
+            .lines()
+            .find(|line| line.ends_with("is the first bad commit"))
+        {
+            let commit = line.split_whitespace().next().unwrap_or_default();
+            return Ok(Bisection::FirstBad(commit.to_owned()));
+        }
+        self.rev = self.git(&["rev-parse", "HEAD"])?;
+        Ok(Bisection::Test(self.rev.clone()))
+    }
+
+    /// One-line description of `commit`.
+    pub fn describe(&self, commit: &str) -> anyhow::Result<String> {
+        self.git(&["log", "-1", "--format=%h %s (%an, %as)", commit])
+    }
+
+    /// Runs the shell command `build` in the worktree.
+    pub fn build(&self, build: &str) -> anyhow::Result<()> {
+        tracing::info!("building {}: {build}", self.rev);
+        command::run_build(build, &self.path())
+            .map_err(|e| anyhow::anyhow!("building {} failed: {e}", self.rev))
+    }
+
+    /// Runs `f` from the worktree, so that relative paths in the benchmarked command refer to
+    /// its files.
+    pub fn run_in<T>(&self, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let cwd = std::env::current_dir()?;
+        std::env::set_current_dir(self.path())?;
+        let result = f();
+        std::env::set_current_dir(cwd)?;
+        result
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let removed = git(&[
+            "worktree",
+            "remove",
+            "--force",
+            &self.path().display().to_string(),
+        ]);
+        if let Err(e) = removed {
+            tracing::warn!("cannot remove the worktree {}: {e}", self.path().display());
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Regions of interest: the benchmarked program enables and disables the counters by writing
+//! `enable` and `disable` lines to a FIFO, as with `perf stat --control`, or they are enabled
+//! after it has run for a while, so that its setup is left out of the measures.
+
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::{fd::RawFd, unix::fs::OpenOptionsExt},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "linux")]
+use crate::temp::TempDir;
+
+/// Environment variable giving the benchmarked program the path of the FIFO.
+pub const ENV: &str = "PERF_BENCH_CONTROL";
+
+/// Line the orchestrator writes to the FIFO once the benchmark has exited.
+#[cfg(target_os = "linux")]
+const FINISHED: &str = "perf-bench-finished";
+
+#[cfg(target_os = "linux")]
+// _IO('$', 0) and _IO('$', 1) of linux/perf_event.h.
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+#[cfg(target_os = "linux")]
+const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+
+/// Creates a FIFO at `path`, failing if it exists.
+pub fn make_fifo(path: &Path) -> anyhow::Result<()> {
+    let raw = std::ffi::CString::new(path.as_os_str().as_encoded_bytes())?;
+    if unsafe { libc::mkfifo(raw.as_ptr(), 0o600) } != 0 {
+        anyhow::bail!(
+            "cannot create the FIFO {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+/// The regions of a run.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Regions {
+    pub count: usize,
+    /// Wall-clock time spent in them.
+    pub wall_time: Duration,
+}
+
+#[cfg(target_os = "linux")]
+/// A FIFO whose lines toggle perf event counters, read by a thread started before the
+/// counters are opened so that they don't count it.
+pub struct Control {
+    /// Private directory of the FIFO.
+    dir: TempDir,
+    /// Kept open for writing so that the FIFO never reaches its end, and to write
+    /// [`FINISHED`].
+    fifo: File,
+    fds: Arc<Mutex<Vec<RawFd>>>,
+    finished: mpsc::Receiver<Regions>,
+}
+
+#[cfg(target_os = "linux")]
+fn toggle(fds: &[RawFd], request: libc::c_ulong) {
+    for &fd in fds {
+        if unsafe { libc::ioctl(fd, request as _, 0) } != 0 {
+            tracing::warn!(
+                "cannot toggle a counter: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn listen(fifo: File, fds: &Mutex<Vec<RawFd>>, finished: mpsc::Sender<Regions>) {
+    let mut regions = Regions::default();
+    let mut entered = None;
+    for line in BufReader::new(fifo).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        match line.trim() {
+            "enable" if entered.is_none() => {
+                toggle(&fds.lock().expect("not poisoned"), PERF_EVENT_IOC_ENABLE);
+                entered = Some(Instant::now());
+                regions.count += 1;
+            }
+            "disable" => {
+                toggle(&fds.lock().expect("not poisoned"), PERF_EVENT_IOC_DISABLE);
+                if let Some(entered) = entered.take() {
+                    regions.wall_time += entered.elapsed();
+                }
+            }
+            FINISHED => {
+                if let Some(entered) = entered.take() {
+                    regions.wall_time += entered.elapsed();
+                }
+                if finished.send(std::mem::take(&mut regions)).is_err() {
+                    return;
+                }
+            }
+            "enable" | "" => {}
+            line => tracing::warn!("ignoring {line:?} written to {ENV}"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Control {
+    pub fn new() -> anyhow::Result<Self> {
+        let dir = TempDir::new("perf-bench-control")?;
+        let path = dir.path().join("control");
+        make_fifo(&path)?;
+        // Opening a FIFO for both reading and writing doesn't wait for a writer.
+        let fifo = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(&path)?;
+        let fds = Arc::new(Mutex::new(Vec::new()));
+        let (sender, finished) = mpsc::channel();
+        std::thread::spawn({
+            let (fifo, fds) = (fifo.try_clone()?, fds.clone());
+            move || listen(fifo, &fds, sender)
+        });
+        Ok(Self {
+            dir,
+            fifo,
+            fds,
+            finished,
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.path().join("control")
+    }
+
+    /// Makes the lines toggle the counters of `fds`.
+    pub fn attach(&mut self, fds: Vec<RawFd>) {
+        *self.fds.lock().expect("not poisoned") = fds;
+    }
+
+    /// The regions of the run that just finished, whose lines are all in the FIFO once the
+    /// benchmark has exited.
+    pub fn finish(&mut self) -> std::io::Result<Regions> {
+        writeln!(self.fifo, "\n{FINISHED}")?;
+        self.finished
+            .recv()
+            .map_err(|_| std::io::Error::other("the thread reading the control FIFO stopped"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+enum Signal {
+    Started,
+    Finished,
+}
+
+#[cfg(target_os = "linux")]
+/// A timer enabling perf event counters some time after each benchmark starts, run by a
+/// thread started before the counters are opened so that they don't count it.
+pub struct Delay {
+    fds: Arc<Mutex<Vec<RawFd>>>,
+    signals: mpsc::Sender<Signal>,
+    finished: mpsc::Receiver<Regions>,
+}
+
+#[cfg(target_os = "linux")]
+fn time(
+    delay: Duration,
+    fds: &Mutex<Vec<RawFd>>,
+    signals: mpsc::Receiver<Signal>,
+    finished: mpsc::Sender<Regions>,
+) {
+    while let Ok(signal) = signals.recv() {
+        let regions = match signal {
+            Signal::Finished => Regions::default(),
+            Signal::Started => match signals.recv_timeout(delay) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    toggle(&fds.lock().expect("not poisoned"), PERF_EVENT_IOC_ENABLE);
+                    let entered = Instant::now();
+                    if signals.recv().is_err() {
+                        return;
+                    }
+                    Regions {
+                        count: 1,
+                        wall_time: entered.elapsed(),
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                Ok(_) => Regions::default(),
+            },
+        };
+        if finished.send(regions).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Delay {
+    pub fn new(delay: Duration) -> Self {
+        let fds = Arc::new(Mutex::new(Vec::new()));
+        let (signals, received) = mpsc::channel();
+        let (sender, finished) = mpsc::channel();
+        std::thread::spawn({
+            let fds = fds.clone();
+            move || time(delay, &fds, received, sender)
+        });
+        Self {
+            fds,
+            signals,
+            finished,
+        }
+    }
+
+    /// Makes the timer enable the counters of `fds`.
+    pub fn attach(&mut self, fds: Vec<RawFd>) {
+        *self.fds.lock().expect("not poisoned") = fds;
+    }
+
+    /// Starts the timer, right before the benchmark is spawned.
+    pub fn start(&mut self) {
+        let _ = self.signals.send(Signal::Started);
+    }
+
+    /// The region of the run that just finished, none if it ended before the delay.
+    pub fn finish(&mut self) -> std::io::Result<Regions> {
+        let stopped = || std::io::Error::other("the thread of the delay stopped");
+        self.signals.send(Signal::Finished).map_err(|_| stopped())?;
+        self.finished.recv().map_err(|_| stopped())
+    }
+}
@@ -0,0 +1,41 @@
+//! Export of the metrics of every iteration, as a long-format table for statistical tools.
+
+use crate::{query::csv_field, results::Results};
+
+/// The benchmark, iteration, numbered from 1, metric and value of each metric of each
+/// iteration, in the units of the results file. A benchmark measured once has its measure as
+/// only iteration.
+pub fn rows(res: &Results) -> impl Iterator<Item = (&String, usize, &String, f64)> {
+    res.iter().flat_map(|(key, measure)| {
+        let iterations: Vec<_> = if measure.samples.is_empty() {
+            vec![&measure.metrics]
+        } else {
+            measure
+                .samples
+                .iter()
+                .map(|sample| &sample.metrics)
+                .collect()
+        };
+        iterations
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(i, metrics)| {
+                metrics
+                    .iter()
+                    .map(move |(name, &value)| (key, i + 1, name, value))
+            })
+    })
+}
+
+/// A CSV row per benchmark, iteration and metric.
+pub fn csv(res: &Results) -> String {
+    let mut csv = String::from("file,iteration,metric,value\n");
+    for (key, iteration, name, value) in rows(res) {
+        csv.push_str(&format!(
+            "{},{iteration},{},{value}\n",
+            csv_field(key),
+            csv_field(name)
+        ));
+    }
+    csv
+}
@@ -0,0 +1,106 @@
+//! Deterministic subsets of the corpus for quick runs: each benchmark is ranked by a hash of
+//! its key and a seed, and the first of each stratum are kept, so that the same seed always
+//! selects the same benchmarks.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    group::{self, GroupBy},
+    hash,
+    record::Benchmark,
+};
+
+/// What the corpus is divided by, each part being sampled in the same proportion.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum StratifyBy {
+    /// Directory of their keys
+    Dir,
+    /// Size of their input, in powers of two
+    Size,
+}
+
+/// How the benchmarks of a recording were sampled.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Sample {
+    /// Share of the benchmarks of each stratum that were kept.
+    pub fraction: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stratify_by: Option<StratifyBy>,
+    pub seed: u64,
+}
+
+impl std::fmt::Display for Sample {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}%", (self.fraction * 1e4).round() / 100.)?;
+        match self.stratify_by {
+            Some(StratifyBy::Dir) => write!(f, " by directory")?,
+            Some(StratifyBy::Size) => write!(f, " by input size")?,
+            None => {}
+        }
+        write!(f, ", seed {}", self.seed)
+    }
+}
+
+/// Parses a share of the corpus, as a percentage such as `10%` or a fraction such as `0.1`.
+pub fn parse_fraction(text: &str) -> Result<f64, String> {
+    let fraction = match text.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.),
+        None => text.parse::<f64>(),
+    }
+    .map_err(|e| format!("invalid share {text:?}: {e}"))?;
+    if !(fraction > 0. && fraction <= 1.) {
+        return Err(format!("{text:?} is not a share between 0% and 100%"));
+    }
+    Ok(fraction)
+}
+
+/// The stratum of `benchmark`.
+fn stratum(benchmark: &Benchmark, by: Option<StratifyBy>) -> String {
+    match by {
+        None => String::new(),
+        Some(StratifyBy::Dir) => group::of(&benchmark.key, GroupBy::Dir),
+        Some(StratifyBy::Size) => match benchmark
+            .input
+            .as_ref()
+            .and_then(|input| std::fs::metadata(input).ok())
+        {
+            Some(metadata) => format!("{:02}", u64::BITS - metadata.len().leading_zeros()),
+            None => "none".to_owned(),
+        },
+    }
+}
+
+/// The benchmarks of the `sample` of `benchmarks`, in their order: a share of each stratum,
+/// rounded but at least one benchmark.
+pub fn select(benchmarks: Vec<Benchmark>, sample: &Sample) -> Vec<Benchmark> {
+    let rank = |key: &str| hash::sha256(format!("{}:{key}", sample.seed).as_bytes());
+    let mut strata: BTreeMap<String, Vec<(String, usize)>> = BTreeMap::new();
+    for (i, benchmark) in benchmarks.iter().enumerate() {
+        strata
+            .entry(stratum(benchmark, sample.stratify_by))
+            .or_default()
+            .push((rank(&benchmark.key), i));
+    }
+    let mut kept = vec![false; benchmarks.len()];
+    for members in strata.values_mut() {
+        members.sort();
+        let n = ((members.len() as f64 * sample.fraction).round() as usize).max(1);
+        for (_, i) in &members[..n.min(members.len())] {
+            kept[*i] = true;
+        }
+    }
+    let total = benchmarks.len();
+    let selected: Vec<Benchmark> = benchmarks
+        .into_iter()
+        .zip(kept)
+        .filter_map(|(benchmark, kept)| kept.then_some(benchmark))
+        .collect();
+    tracing::info!(
+        "sampling {} of {total} benchmark(s), {sample}",
+        selected.len()
+    );
+    selected
+}
@@ -0,0 +1,276 @@
+//! JSON Schema of results files, for the tools parsing them.
+
+use serde_json::json;
+
+use crate::{metrics, results::SCHEMA_VERSION};
+
+/// The metrics of a measure or an iteration: the known ones, in the unit of their description,
+/// and any other number.
+fn metric_properties() -> serde_json::Map<String, serde_json::Value> {
+    metrics::METRICS
+        .iter()
+        .map(|metric| {
+            let description = metric.description.split(", in ").next().unwrap_or_default();
+            let description = format!("{description}, in {}", metric.unit.name());
+            (
+                metric.name.to_owned(),
+                json!({ "type": "number", "description": description }),
+            )
+        })
+        .collect()
+}
+
+pub fn results_file() -> serde_json::Value {
+    let nullable_string = json!({ "type": ["string", "null"] });
+    let cpus = json!({
+        "type": "array",
+        "items": { "type": "integer" },
+        "description": "CPUs the threads of the benchmark were seen running on"
+    });
+    let mut sample_properties = metric_properties();
+    sample_properties.insert("cpus".to_owned(), cpus.clone());
+    let mut measure_properties = metric_properties();
+    measure_properties.extend(
+        json!({
+            "command": { "type": "string", "description": "Command line that was measured" },
+            "binary": {
+                "type": "string",
+                "description": "Path the benchmarked program resolved to"
+            },
+            "binary_sha256": { "type": "string" },
+            "status": {
+                "type": "object",
+                "required": ["kind"],
+                "properties": {
+                    "kind": {
+                        "enum": [
+                            "ok",
+                            "nonzero-exit",
+                            "timeout",
+                            "signal",
+                            "counters-unavailable",
+                            "skipped"
+                        ]
+                    },
+                    "code": { "type": "integer" },
+                    "signal": { "type": "integer" },
+                    "core_dumped": { "type": "boolean" }
+                },
+                "description": "How the measurement went, ok if absent"
+            },
+            "skipped": {
+                "type": "boolean",
+                "description": "Whether the benchmark was not measured"
+            },
+            "skip_reason": {
+                "type": "string",
+                "description": "Why the benchmark was not measured"
+            },
+            "duplicate_of": {
+                "type": "string",
+                "description": "Key of the identical benchmark whose measurement this is a copy of"
+            },
+            "note": {
+                "type": "string",
+                "description": "Annotation of the benchmark, e.g. \"known flaky\""
+            },
+            "noise_run": {
+                "$ref": "#/$defs/sample",
+                "description": "Metrics of a second recording in the same session, estimating the noise"
+            },
+            "load_avg": { "type": "number" },
+            "interference": {
+                "type": "number",
+                "description": "Share of the machine used by other processes during the runs"
+            },
+            "frequency": {
+                "type": "object",
+                "properties": {
+                    "min": { "type": "number" },
+                    "mean": { "type": "number" },
+                    "max": { "type": "number" }
+                },
+                "description": "Frequency of the CPUs the benchmark ran on, in MHz"
+            },
+            "cpus": cpus.clone(),
+            "limit_hit": {
+                "type": "string",
+                "description": "Resource limit that most likely made the run fail"
+            },
+            "multiplexed": {
+                "type": "boolean",
+                "description": "Whether some counters were multiplexed, and their values \
+                                extrapolated"
+            },
+            "smt_shared": {
+                "type": "boolean",
+                "description": "Whether the SMT siblings of the cores the benchmark was to have \
+                                exclusively stayed online"
+            },
+            "incorrect": {
+                "type": "boolean",
+                "description": "Whether some runs printed another output than the expected one"
+            },
+            "unavailable": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Requested metrics that could not be measured on the machine"
+            },
+            "functions": {
+                "type": "object",
+                "additionalProperties": { "type": "number" },
+                "description": "Instructions executed in each function"
+            },
+            "threads": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": { "type": "number" }
+                },
+                "description": "Metrics of each thread, by name, numbered when several share one"
+            },
+            "processes": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": { "type": "number" }
+                },
+                "description": "Metrics of each process of the process tree, by name, numbered \
+                                when several share one"
+            },
+            "hot_functions": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": { "type": "number" }
+                },
+                "description": "Self cycles or CPU clock of each function, from samples"
+            },
+            "samples": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/sample" },
+                "description": "Metrics of every iteration, when there were several"
+            },
+            "host": {
+                "type": "string",
+                "description": "Machine it was measured on, in a recording sharded over SSH"
+            },
+        })
+        .as_object()
+        .expect("an object")
+        .clone(),
+    );
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("urn:perf-bench-orchestrator:results:v{SCHEMA_VERSION}"),
+        "title": "perf-bench-orchestrator results",
+        "type": "object",
+        "required": ["results"],
+        "properties": {
+            "schema_version": {
+                "type": "integer",
+                "const": SCHEMA_VERSION,
+                "description": "Version of the format, absent from the files of its first version"
+            },
+            "metadata": {
+                "oneOf": [{ "type": "null" }, { "$ref": "#/$defs/metadata" }]
+            },
+            "units": {
+                "type": "object",
+                "additionalProperties": { "enum": ["count", "ns", "bytes"] },
+                "description": "Unit of each metric of the results"
+            },
+            "results": {
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/measure" },
+                "description": "Measure of each benchmark, by key"
+            }
+        },
+        "$defs": {
+            "metadata": {
+                "type": "object",
+                "required": ["date", "version"],
+                "properties": {
+                    "hostname": nullable_string,
+                    "cpu_model": nullable_string,
+                    "cores": { "type": ["integer", "null"] },
+                    "kernel": nullable_string,
+                    "governor": nullable_string,
+                    "aslr": nullable_string,
+                    "numa": {
+                        "type": "string",
+                        "description": "CPUs of each NUMA node, as node0: 0-15, node1: 16-31"
+                    },
+                    "numa_node": {
+                        "type": "integer",
+                        "description": "NUMA node the benchmarks were bound to"
+                    },
+                    "nominal_mhz": {
+                        "type": "number",
+                        "description": "Nominal frequency of the CPU, that of its reference \
+                                        cycles, in MHz"
+                    },
+                    "efficiency_cpus": {
+                        "type": "string",
+                        "description": "Efficiency CPUs of a hybrid CPU, as 8-15"
+                    },
+                    "core_type": {
+                        "enum": ["p", "e"],
+                        "description": "Type of the cores of a hybrid CPU the benchmarks were \
+                                        pinned to"
+                    },
+                    "date": {
+                        "type": "string",
+                        "description": "UTC time, as 2024-01-31T12:00:00Z"
+                    },
+                    "version": { "type": "string", "description": "Version of the orchestrator" },
+                    "git": {
+                        "type": "object",
+                        "required": ["commit"],
+                        "properties": {
+                            "commit": { "type": "string" },
+                            "branch": { "type": "string" },
+                            "dirty": { "type": "boolean" }
+                        }
+                    },
+                    "build": {
+                        "type": "object",
+                        "required": ["command", "binaries"],
+                        "properties": {
+                            "command": { "type": "string" },
+                            "binaries": {
+                                "type": "object",
+                                "additionalProperties": { "type": "string" },
+                                "description": "SHA-256 of each benchmarked program, by path"
+                            }
+                        }
+                    },
+                    "tags": { "type": "object", "additionalProperties": { "type": "string" } },
+                    "note": { "type": "string" },
+                    "aggregate_of": {
+                        "type": "integer",
+                        "description": "Number of runs of the day whose medians the results are"
+                    },
+                    "hosts": {
+                        "type": "object",
+                        "additionalProperties": { "$ref": "#/$defs/metadata" },
+                        "description": "Metadata of each machine of a recording sharded over \
+                                        SSH, by destination"
+                    }
+                }
+            },
+            "measure": {
+                "type": "object",
+                "description": "Median over the iterations of each metric, with how it was \
+                                measured",
+                "properties": measure_properties,
+                "additionalProperties": { "type": "number" }
+            },
+            "sample": {
+                "type": "object",
+                "properties": sample_properties,
+                "additionalProperties": { "type": "number" }
+            }
+        }
+    })
+}
@@ -0,0 +1,85 @@
+//! Measurement of the orchestrator itself: a process doing nothing shows the floor of the
+//! counts, from the exec enabling the counters to the exit, and a fixed workload the run-to-run
+//! variation the measurement adds.
+
+use crate::{
+    metrics::{self, DisplayOptions},
+    record::Benchmark,
+    results::{self, Results},
+};
+
+/// Iterations of the workloads when the options don't set them.
+pub const DEFAULT_ITERATIONS: u32 = 30;
+
+/// The no-op and the fixed workload.
+pub fn benchmarks() -> Vec<Benchmark> {
+    [
+        ("no-op", "sh -c :"),
+        (
+            "fixed",
+            "sh -c 'i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done'",
+        ),
+    ]
+    .into_iter()
+    .map(|(key, command)| Benchmark {
+        key: key.to_owned(),
+        command: command.to_owned(),
+        input: None,
+        expected_stdout: None,
+        note: None,
+        repetitions: Default::default(),
+        skip: None,
+    })
+    .collect()
+}
+
+/// Relative standard deviation of `values`.
+fn spread(values: &[f64]) -> f64 {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    if mean == 0. {
+        return 0.;
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    variance.sqrt() / mean
+}
+
+/// Prints the median, range and spread of each metric of the workloads.
+pub fn print(res: &Results, display: DisplayOptions) {
+    let mut table = prettytable::Table::new();
+    table.add_row(prettytable::row![
+        "Workload", "Metric", "Median", "Min", "Max", "Spread"
+    ]);
+    for (key, measure) in res {
+        let mut names: Vec<String> = measure.metrics.keys().cloned().collect();
+        results::sort_metric_names(&mut names);
+        for name in &names {
+            let median = measure.metrics[name];
+            let mut values: Vec<f64> = measure
+                .samples
+                .iter()
+                .filter_map(|sample| sample.metrics.get(name).copied())
+                .collect();
+            if values.is_empty() {
+                values.push(median);
+            }
+            let (min, max) = values
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                });
+            table.add_row(prettytable::row![
+                key,
+                metrics::header(name, display),
+                metrics::display(name, median, display),
+                metrics::display(name, min, display),
+                metrics::display(name, max, display),
+                format!("{:.1}%", spread(&values) * 100.),
+            ]);
+        }
+    }
+    table.printstd();
+    println!(
+        "The no-op counts are the floor of every measure; changes of benchmarks within the \
+         spread of the fixed workload are noise of the measurement."
+    );
+}
@@ -0,0 +1,103 @@
+//! Benchmarks left out of recordings: those matching the patterns of a skip file, and those
+//! quarantined for having been noisy in each of the last runs of the history.
+
+use std::{collections::BTreeSet, path::Path};
+
+use crate::{history, results::Measure};
+
+/// Relative standard deviation of the iterations of a metric beyond which a measurement is
+/// noisy.
+pub const NOISE_THRESHOLD: f64 = 0.05;
+
+/// Number of last runs of the history a benchmark must have been noisy in to be quarantined.
+pub const QUARANTINE_RUNS: usize = 3;
+
+/// Whether `text` matches the glob `pattern`, where `*` matches any text, `/` included, and
+/// `?` any character.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it was matched up to, to backtrack to.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Patterns of the keys of benchmarks not to record, with the reason why.
+pub struct SkipList {
+    patterns: Vec<(String, String)>,
+}
+
+impl SkipList {
+    /// Reads a skip file, a JSON object of reasons by key pattern, e.g.
+    /// `{"big/*": "too slow for CI"}`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        let patterns: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&file)
+            .map_err(|e| anyhow::anyhow!("invalid skip file {}: {e}", path.display()))?;
+        let patterns = patterns
+            .into_iter()
+            .map(|(pattern, reason)| match reason {
+                serde_json::Value::String(reason) => Ok((pattern, reason)),
+                _ => anyhow::bail!("the reason to skip {pattern:?} is not a string"),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// The reason to skip the benchmark `key`, that of the first pattern matching it.
+    pub fn reason(&self, key: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| glob_matches(pattern, key))
+            .map(|(_, reason)| reason.as_str())
+    }
+}
+
+/// Whether the iterations of some metric of `measure` spread beyond the noise threshold.
+pub fn is_noisy(measure: &Measure) -> bool {
+    measure
+        .spreads()
+        .values()
+        .any(|&spread| spread > NOISE_THRESHOLD)
+}
+
+/// The benchmarks noisy in each of the last runs of `runs` that measured them, if they are
+/// at least [`QUARANTINE_RUNS`].
+pub fn quarantined(runs: &[history::Run]) -> BTreeSet<String> {
+    let mut noisy: std::collections::BTreeMap<&String, Vec<bool>> = Default::default();
+    for run in runs.iter().rev() {
+        for (key, measure) in &run.file.results {
+            let verdicts = noisy.entry(key).or_default();
+            if verdicts.len() < QUARANTINE_RUNS && !measure.skipped {
+                verdicts.push(is_noisy(measure));
+            }
+        }
+    }
+    noisy
+        .into_iter()
+        .filter(|(_, verdicts)| verdicts.len() == QUARANTINE_RUNS && verdicts.iter().all(|&v| v))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
@@ -0,0 +1,80 @@
+//! Isolation of benchmarks from the SMT siblings of their cores, whose other hardware threads
+//! share the execution units and caches of the core and perturb its cycle counts.
+
+use crate::cores;
+
+const CPUS: &str = "/sys/devices/system/cpu";
+
+/// The SMT siblings of `cpus` that aren't among them.
+fn siblings(cpus: &[usize]) -> Vec<usize> {
+    let mut siblings: Vec<usize> = cpus
+        .iter()
+        .filter_map(|cpu| {
+            let path = format!("{CPUS}/cpu{cpu}/topology/thread_siblings_list");
+            cores::parse_list(&std::fs::read_to_string(path).ok()?)
+        })
+        .flatten()
+        .filter(|sibling| !cpus.contains(sibling))
+        .collect();
+    siblings.sort();
+    siblings.dedup();
+    siblings
+}
+
+fn set_online(cpu: usize, online: bool) -> std::io::Result<()> {
+    std::fs::write(
+        format!("{CPUS}/cpu{cpu}/online"),
+        if online { "1" } else { "0" },
+    )
+}
+
+/// The SMT siblings of the CPUs the benchmarks are pinned to, taken offline for as long as it
+/// lives so that nothing runs on them.
+pub struct Isolation {
+    offlined: Vec<usize>,
+    /// Whether some siblings could not be taken offline.
+    shared: bool,
+}
+
+impl Isolation {
+    pub fn new(cpus: &[usize]) -> Self {
+        let mut isolation = Self {
+            offlined: Vec::new(),
+            shared: false,
+        };
+        for sibling in siblings(cpus) {
+            match set_online(sibling, false) {
+                Ok(()) => isolation.offlined.push(sibling),
+                Err(e) => {
+                    tracing::warn!(
+                        "cannot take CPU {sibling}, an SMT sibling of the benchmarked cores, \
+                         offline ({e}); what runs on it may interfere with the measures"
+                    );
+                    isolation.shared = true;
+                }
+            }
+        }
+        if !isolation.offlined.is_empty() {
+            tracing::info!(
+                "took the SMT sibling(s) {:?} offline while benchmarking",
+                isolation.offlined
+            );
+        }
+        isolation
+    }
+
+    /// Whether some siblings stayed online.
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+}
+
+impl Drop for Isolation {
+    fn drop(&mut self) {
+        for &cpu in &self.offlined {
+            if let Err(e) = set_online(cpu, true) {
+                tracing::warn!("cannot bring CPU {cpu} back online: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Breakdown of the cycles of the benchmarks by thread and by process, from the counts the
+//! kernel reports as each of their threads exits, named by tracking forks and executions.
+
+use std::collections::BTreeMap;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+
+#[cfg(target_os = "linux")]
+use perf_event as prf;
+#[cfg(target_os = "linux")]
+use prf::{
+    data::Record,
+    events::{Hardware, Software},
+    ReadFormat,
+};
+
+use crate::metrics::Metrics;
+
+/// Size of the buffer of the records of a run on each CPU, enough for thousands of threads.
+#[cfg(target_os = "linux")]
+const BUFFER_SIZE: usize = 1 << 18;
+
+#[cfg(target_os = "linux")]
+pub struct Tasks {
+    /// A counter per CPU, the kernel refusing to map the buffer of inherited counters that
+    /// follow a task on any CPU.
+    samplers: Vec<prf::Sampler>,
+    /// Metric counted by thread: cycles, or the CPU clock without hardware counters.
+    metric: &'static str,
+}
+
+#[cfg(target_os = "linux")]
+fn open(event: impl Fn() -> prf::Builder<'static>) -> std::io::Result<Vec<prf::Sampler>> {
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as usize;
+    (0..cpus)
+        .map(|cpu| {
+            event()
+                .one_cpu(cpu)
+                .inherit(true)
+                .inherit_stat(true)
+                .enable_on_exec(true)
+                .comm(true)
+                .task(true)
+                .read_format(ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::TOTAL_TIME_RUNNING)
+                .build()?
+                .sampled(BUFFER_SIZE)
+        })
+        .collect()
+}
+
+/// The metrics of the tasks of a run, by name, numbered in the order they were created when
+/// several share one.
+#[derive(Default)]
+pub struct Breakdown {
+    pub threads: BTreeMap<String, Metrics>,
+    pub processes: BTreeMap<String, Metrics>,
+}
+
+#[cfg(target_os = "linux")]
+impl Tasks {
+    pub fn open() -> anyhow::Result<Self> {
+        match open(|| prf::Builder::new(Hardware::CPU_CYCLES)) {
+            Ok(samplers) => Ok(Self {
+                samplers,
+                metric: "cycles",
+            }),
+            Err(e) => {
+                tracing::warn!("cannot count cycles by task ({e}), counting cpu_clock instead");
+                let samplers = open(|| prf::Builder::new(Software::CPU_CLOCK))
+                    .map_err(|e| anyhow::anyhow!("cannot count by task: {e}"))?;
+                Ok(Self {
+                    samplers,
+                    metric: "cpu_clock",
+                })
+            }
+        }
+    }
+
+    /// Discards the records of the processes spawned since the last run.
+    pub fn start(&mut self) {
+        for sampler in &mut self.samplers {
+            while sampler.next_record().is_some() {}
+        }
+    }
+
+    /// The counts of the tasks that exited since [`Tasks::start`].
+    pub fn read(&mut self) -> Breakdown {
+        let mut names: HashMap<u32, String> = HashMap::new();
+        let mut parents: HashMap<u32, u32> = HashMap::new();
+        let mut counts: BTreeMap<u32, f64> = BTreeMap::new();
+        let mut processes: BTreeMap<u32, f64> = BTreeMap::new();
+        let mut lost = false;
+        for sampler in &mut self.samplers {
+            while let Some(record) = sampler.next_record() {
+                match record.parse_record() {
+                    Ok(Record::Comm(comm)) => {
+                        let name = String::from_utf8_lossy(&comm.comm);
+                        names.insert(comm.tid, name.trim_end_matches('\0').to_owned());
+                    }
+                    Ok(Record::Fork(fork)) => {
+                        parents.insert(fork.tid, fork.ptid);
+                    }
+                    Ok(Record::Read(read)) => {
+                        let value = read.values.value() as f64;
+                        let value = match (read.values.time_enabled(), read.values.time_running()) {
+                            (Some(enabled), Some(running)) if running > 0 && running < enabled => {
+                                value * enabled as f64 / running as f64
+                            }
+                            _ => value,
+                        };
+                        *counts.entry(read.tid).or_default() += value;
+                        *processes.entry(read.pid).or_default() += value;
+                    }
+                    Ok(Record::Lost(_)) => lost = true,
+                    _ => {}
+                }
+            }
+        }
+        if lost {
+            tracing::warn!("some threads were lost from the breakdown, the buffer being full");
+        }
+        // Threads of the orchestrator itself inherit the counters, disabled.
+        counts.retain(|_, value| *value > 0.);
+        processes.retain(|_, value| *value > 0.);
+        // Threads are named after the thread that created them until they rename themselves.
+        let name = |mut tid: u32| loop {
+            match (names.get(&tid), parents.get(&tid)) {
+                (Some(name), _) => return name.clone(),
+                (None, Some(&parent)) if parent != tid => tid = parent,
+                _ => return "unknown".to_owned(),
+            }
+        };
+        // A process is named after its main thread, whose ID is the process ID.
+        Breakdown {
+            threads: label(counts, name, self.metric),
+            processes: label(processes, name, self.metric),
+        }
+    }
+}
+
+/// Counting by task relies on perf events, only available on Linux.
+#[cfg(not(target_os = "linux"))]
+pub enum Tasks {}
+
+#[cfg(not(target_os = "linux"))]
+impl Tasks {
+    pub fn open() -> anyhow::Result<Self> {
+        anyhow::bail!(
+            "--per-thread and --per-process count with perf events, only available on Linux"
+        )
+    }
+
+    pub fn start(&mut self) {
+        match *self {}
+    }
+
+    pub fn read(&mut self) -> Breakdown {
+        match *self {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// The `counts` of tasks by ID as metrics by the name of each task, numbered in the order of
+/// their IDs when several share one.
+fn label(
+    counts: BTreeMap<u32, f64>,
+    name: impl Fn(u32) -> String,
+    metric: &str,
+) -> BTreeMap<String, Metrics> {
+    let mut total: HashMap<String, usize> = HashMap::new();
+    for &id in counts.keys() {
+        *total.entry(name(id)).or_default() += 1;
+    }
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut labeled = BTreeMap::new();
+    for (id, value) in counts {
+        let name = name(id);
+        let label = if total[&name] > 1 {
+            let n = seen.entry(name.clone()).or_default();
+            *n += 1;
+            format!("{name}#{n}")
+        } else {
+            name
+        };
+        labeled.insert(label, Metrics::from([(metric.to_owned(), value)]));
+    }
+    labeled
+}
@@ -0,0 +1,40 @@
+//! Private temporary directories, which other users can neither predict nor write to.
+
+use std::{
+    ffi::OsString,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+};
+
+/// A directory of mode 0700 with a unique name, removed with its content when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a directory named `prefix` and random characters in the temporary directory.
+    pub fn new(prefix: &str) -> std::io::Result<Self> {
+        let template = std::env::temp_dir().join(format!("{prefix}-XXXXXX"));
+        let mut template = template.as_os_str().as_bytes().to_vec();
+        template.push(0);
+        if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+        template.pop();
+        Ok(TempDir {
+            path: PathBuf::from(OsString::from_vec(template)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            tracing::warn!("cannot remove {}: {e}", self.path.display());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Plots of a metric of a benchmark over the runs of the history.
+
+use crate::{
+    compare,
+    history::Run,
+    metrics::{self, DisplayOptions},
+};
+
+/// Width of the longest bar of the terminal plot, in characters.
+const BAR_WIDTH: usize = 40;
+
+pub struct Point {
+    pub label: String,
+    pub date: String,
+    pub value: f64,
+}
+
+/// The values of `metric` of `benchmark` in the runs that measured it.
+pub fn points(runs: &[Run], benchmark: &str, metric: &str) -> Vec<Point> {
+    runs.iter()
+        .filter_map(|run| {
+            let value = *run.file.results.get(benchmark)?.metrics.get(metric)?;
+            Some(Point {
+                label: run.label(),
+                date: run
+                    .file
+                    .metadata
+                    .as_ref()
+                    .map_or_else(String::new, |m| m.date.clone()),
+                value,
+            })
+        })
+        .collect()
+}
+
+fn bounds(points: &[Point]) -> (f64, f64) {
+    let min = points.iter().map(|p| p.value).fold(f64::INFINITY, f64::min);
+    let max = points
+        .iter()
+        .map(|p| p.value)
+        .fold(f64::NEG_INFINITY, f64::max);
+    (min, max)
+}
+
+/// Prints a bar per point, scaled from the lowest value to the highest so that small drifts
+/// show, with its change over the first point.
+pub fn print(points: &[Point], metric: &str, display: DisplayOptions) {
+    let (min, max) = bounds(points);
+    let mut table = prettytable::Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+    table.add_row(prettytable::row![
+        "Date",
+        "Revision",
+        metrics::header(metric, display),
+        "Change",
+        ""
+    ]);
+    for point in points {
+        let width = if max > min {
+            1 + ((point.value - min) / (max - min) * (BAR_WIDTH - 1) as f64).round() as usize
+        } else {
+            BAR_WIDTH
+        };
+        table.add_row(prettytable::row![
+            point.date,
+            point.label,
+            r->metrics::display(metric, point.value, display),
+            r->format!("{:+.2}%", compare::rel_diff(points[0].value, point.value)),
+            "█".repeat(width),
+        ]);
+    }
+    table.printstd();
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A line chart of the points in SVG, with a tooltip for each.
+pub fn svg(points: &[Point], benchmark: &str, metric: &str, display: DisplayOptions) -> String {
+    const WIDTH: f64 = 800.;
+    const HEIGHT: f64 = 400.;
+    const MARGIN: f64 = 60.;
+    let (min, max) = bounds(points);
+    let x = |i: usize| {
+        if points.len() > 1 {
+            MARGIN + i as f64 * (WIDTH - 2. * MARGIN) / (points.len() - 1) as f64
+        } else {
+            WIDTH / 2.
+        }
+    };
+    let y = |value: f64| {
+        if max > min {
+            HEIGHT - MARGIN - (value - min) / (max - min) * (HEIGHT - 2. * MARGIN)
+        } else {
+            HEIGHT / 2.
+        }
+    };
+    let show = |value: f64| escape(&metrics::display(metric, value, display));
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+         font-family=\"sans-serif\" font-size=\"12\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+         <text x=\"{}\" y=\"24\" text-anchor=\"middle\" font-size=\"16\">{} of {}</text>\n\
+         <line x1=\"{MARGIN}\" y1=\"{MARGIN}\" x2=\"{MARGIN}\" y2=\"{}\" stroke=\"gray\"/>\n\
+         <line x1=\"{MARGIN}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"gray\"/>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+        WIDTH / 2.,
+        escape(metric),
+        escape(benchmark),
+        HEIGHT - MARGIN,
+        HEIGHT - MARGIN,
+        WIDTH - MARGIN,
+        HEIGHT - MARGIN,
+        MARGIN - 4.,
+        y(max) + 4.,
+        show(max),
+        MARGIN - 4.,
+        y(min) + 4.,
+        show(min),
+    );
+    let line: Vec<String> = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| format!("{:.1},{:.1}", x(i), y(point.value)))
+        .collect();
+    svg.push_str(&format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\"/>\n",
+        line.join(" ")
+    ));
+    for (i, point) in points.iter().enumerate() {
+        svg.push_str(&format!(
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"steelblue\"><title>{} {}: \
+             {}</title></circle>\n",
+            x(i),
+            y(point.value),
+            escape(&point.date),
+            escape(&point.label),
+            show(point.value),
+        ));
+    }
+    for (i, anchor) in [(0, "start"), (points.len() - 1, "end")] {
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"{anchor}\">{}</text>\n",
+            x(i),
+            HEIGHT - MARGIN + 18.,
+            escape(&points[i].label),
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,156 @@
+//! Uploading results files to continuous-benchmarking services.
+
+use crate::{
+    http::{self, required_env},
+    influx, openmetrics,
+    results::ResultsFile,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Service {
+    /// Bencher (bencher.dev or self-hosted), as a report in Bencher Metric Format
+    Bencher,
+    /// Prometheus Pushgateway, as OpenMetrics gauges grouped under the job perf_bench
+    Pushgateway,
+    /// InfluxDB write API, e.g. http://localhost:8086/api/v2/write?org=ORG&bucket=BUCKET, in
+    /// line protocol with the token in INFLUX_TOKEN
+    Influxdb,
+    /// Any HTTP endpoint, receiving the results file as written by `record`
+    Http,
+}
+
+/// Where a run is reported to Bencher.
+pub struct BencherReport<'a> {
+    pub host: Option<&'a str>,
+    pub project: Option<&'a str>,
+    pub branch: Option<&'a str>,
+    pub testbed: Option<&'a str>,
+}
+
+/// The measures of `file` in Bencher Metric Format, with the metrics as measure slugs.
+fn bencher_metric_format(file: &ResultsFile) -> serde_json::Value {
+    let benchmarks: serde_json::Map<_, _> = file
+        .results
+        .iter()
+        .map(|(key, measure)| {
+            let measures: serde_json::Map<_, _> = measure
+                .metrics
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.replace('_', "-"),
+                        serde_json::json!({ "value": value }),
+                    )
+                })
+                .collect();
+            (key.clone(), measures.into())
+        })
+        .collect();
+    benchmarks.into()
+}
+
+/// Creates a report of `file` in a Bencher project, with the token in `BENCHER_API_TOKEN`.
+///
+/// The branch defaults to the git branch recorded in the metadata, and the testbed to the
+/// hostname.
+pub fn bencher(file: &ResultsFile, report: &BencherReport) -> anyhow::Result<()> {
+    let token = required_env("BENCHER_API_TOKEN")?;
+    let host = match report.host {
+        Some(host) => host.to_owned(),
+        None => std::env::var("BENCHER_HOST").unwrap_or_else(|_| "https://api.bencher.dev".into()),
+    };
+    let project = match report.project {
+        Some(project) => project.to_owned(),
+        None => required_env("BENCHER_PROJECT")
+            .map_err(|_| anyhow::anyhow!("no Bencher project, pass --project"))?,
+    };
+    let metadata = file.metadata.as_ref();
+    let git = metadata.and_then(|m| m.git.as_ref());
+    let branch = report
+        .branch
+        .or(git.and_then(|git| git.branch.as_deref()))
+        .ok_or_else(|| anyhow::anyhow!("no git branch in the metadata, pass --branch"))?;
+    let testbed = report
+        .testbed
+        .or(metadata.and_then(|m| m.hostname.as_deref()))
+        .unwrap_or("localhost");
+    let date = metadata
+        .map(|m| m.date.clone())
+        .ok_or_else(|| anyhow::anyhow!("the results have no metadata to date the report with"))?;
+    let mut body = serde_json::json!({
+        "branch": branch,
+        "testbed": testbed,
+        "start_time": date,
+        "end_time": date,
+        "results": [bencher_metric_format(file).to_string()],
+        "settings": { "adapter": "json" },
+    });
+    if let Some(git) = git {
+        body["hash"] = git.commit.clone().into();
+    }
+    let authorization = format!("Bearer {token}");
+    let response = http::json_request(
+        "POST",
+        &format!(
+            "{}/v0/projects/{project}/reports",
+            host.trim_end_matches('/')
+        ),
+        &[("Authorization", &authorization)],
+        Some(&body),
+    )?;
+    match response["uuid"].as_str() {
+        Some(uuid) => tracing::info!("created the Bencher report {uuid} in {project}"),
+        None => tracing::info!("created a Bencher report in {project}"),
+    }
+    Ok(())
+}
+
+/// Posts `file` to `url` with the `headers`, given as `Name: value`.
+pub fn http(file: &ResultsFile, url: &str, headers: &[String]) -> anyhow::Result<()> {
+    let headers = headers
+        .iter()
+        .map(|header| {
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("expected Name: value, got {header:?}"))?;
+            Ok((name.trim(), value.trim()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    http::json_request("POST", url, &headers, Some(&serde_json::to_value(file)?))?;
+    tracing::info!("uploaded the results to {url}");
+    Ok(())
+}
+
+/// Replaces the metrics of the job `perf_bench` in the Pushgateway at `url` with those of
+/// `file`.
+pub fn pushgateway(file: &ResultsFile, url: &str) -> anyhow::Result<()> {
+    let url = format!("{}/metrics/job/perf_bench", url.trim_end_matches('/'));
+    http::request(
+        "PUT",
+        &url,
+        &[(
+            "Content-Type",
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        Some(openmetrics::results(file).as_bytes()),
+    )?;
+    tracing::info!("pushed the results to {url}");
+    Ok(())
+}
+
+/// Writes `file` in line protocol to the InfluxDB write API at `url`.
+pub fn influxdb(file: &ResultsFile, url: &str) -> anyhow::Result<()> {
+    let authorization = std::env::var("INFLUX_TOKEN")
+        .ok()
+        .map(|token| format!("Token {token}"));
+    let mut headers = vec![("Content-Type", "text/plain; charset=utf-8")];
+    headers.extend(authorization.as_deref().map(|a| ("Authorization", a)));
+    http::request(
+        "POST",
+        url,
+        &headers,
+        Some(influx::results(file).as_bytes()),
+    )?;
+    tracing::info!("wrote the results to {url}");
+    Ok(())
+}
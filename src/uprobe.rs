@@ -0,0 +1,221 @@
+//! Call counts of functions of the benchmarked binaries, such as the collections of a garbage
+//! collector or the entries of a trap handler, counted by uprobes placed on their first
+//! instruction.
+
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::{ffi::OsStrExt, process::CommandExt},
+    },
+    path::Path,
+    process::Command,
+    sync::Arc,
+    thread::JoinHandle,
+};
+
+#[cfg(target_os = "linux")]
+use perf_event as prf;
+#[cfg(target_os = "linux")]
+use perf_event_open_sys::bindings::perf_event_attr;
+
+#[cfg(target_os = "linux")]
+use crate::{child::Outcome, collector::Collector, command, elf::Elf, metrics::Metrics};
+
+/// A function whose calls are counted as the metric `{symbol}_calls`, given as
+/// `BINARY:SYMBOL`, e.g. `./interpreter:gc_collect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallCount {
+    /// The binary defining the function, looked up in the `PATH` unless it has a `/`.
+    pub binary: String,
+    pub symbol: String,
+}
+
+impl std::str::FromStr for CallCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once(':') {
+            Some((binary, symbol)) if !binary.is_empty() && !symbol.is_empty() => Ok(CallCount {
+                binary: binary.to_owned(),
+                symbol: symbol.to_owned(),
+            }),
+            _ => Err(format!("expected BINARY:SYMBOL, got {s:?}")),
+        }
+    }
+}
+
+impl std::fmt::Display for CallCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.binary, self.symbol)
+    }
+}
+
+/// A uprobe on the code at `offset` of the binary `path`. perf-event2 has a `UProbe` event
+/// that its `Builder` does not accept.
+#[cfg(target_os = "linux")]
+#[derive(Clone)]
+struct UProbe {
+    pmu: u32,
+    /// Shared by the clones, as the kernel reads it again when the benchmark inherits the
+    /// counter.
+    path: Arc<CString>,
+    offset: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl UProbe {
+    fn new(path: &Path, offset: u64) -> anyhow::Result<Self> {
+        let pmu = std::fs::read_to_string("/sys/bus/event_source/devices/uprobe/type")
+            .ok()
+            .and_then(|pmu| pmu.trim().parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("the kernel has no uprobe events"))?;
+        Ok(UProbe {
+            pmu,
+            path: Arc::new(CString::new(path.as_os_str().as_bytes())?),
+            offset,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl prf::events::Event for UProbe {
+    fn update_attrs(self, _attr: &mut perf_event_attr) {
+        unreachable!("the path must outlive the attributes")
+    }
+
+    fn update_attrs_with_data(
+        self,
+        attr: &mut perf_event_attr,
+    ) -> Option<Arc<dyn prf::events::EventData>> {
+        attr.type_ = self.pmu;
+        attr.config = 0;
+        attr.__bindgen_anon_3.uprobe_path = self.path.as_ptr() as u64;
+        attr.__bindgen_anon_4.probe_offset = self.offset;
+        Some(self.path)
+    }
+}
+
+impl CallCount {
+    pub fn metric(&self) -> String {
+        format!("{}_calls", self.symbol)
+    }
+
+    /// The uprobe on the entry of the function, at its offset in the binary.
+    #[cfg(target_os = "linux")]
+    fn probe(&self) -> anyhow::Result<UProbe> {
+        let path = command::resolve_program(&self.binary)
+            .ok_or_else(|| anyhow::anyhow!("cannot find the binary {}", self.binary))?;
+        let elf = Elf::read(&path)?;
+        let function = elf.function(&self.symbol).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} defines no function {}, or is stripped",
+                path.display(),
+                self.symbol
+            )
+        })?;
+        let offset = elf.file_offset(function.address).ok_or_else(|| {
+            anyhow::anyhow!("{} of {} is not loaded", self.symbol, path.display())
+        })?;
+        UProbe::new(&path, offset)
+    }
+}
+
+/// A pipe, both ends closed on exec.
+#[cfg(target_os = "linux")]
+fn pipe() -> std::io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Counts the calls of functions in the main thread of the benchmark process. Uprobe counters
+/// inherited from the orchestrator count nothing, so they are opened on the benchmark process
+/// itself: once forked, it sends its pid and waits for a thread to open them before it execs.
+/// They are not inherited in turn, as the kernel then reads the path of the binary again from
+/// the memory of the forking process, where it is not, and the fork fails.
+#[cfg(target_os = "linux")]
+pub struct CallCounters {
+    calls: Vec<(CallCount, UProbe)>,
+    opener: Option<JoinHandle<anyhow::Result<Vec<prf::Counter>>>>,
+    values: Metrics,
+}
+
+#[cfg(target_os = "linux")]
+impl CallCounters {
+    /// Resolves the functions of `calls` in their binaries.
+    pub fn new(calls: &[CallCount]) -> anyhow::Result<Self> {
+        let calls = calls
+            .iter()
+            .map(|call| Ok((call.clone(), call.probe()?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(CallCounters {
+            calls,
+            opener: None,
+            values: Metrics::new(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Collector for CallCounters {
+    fn metrics(&self) -> Vec<String> {
+        self.calls.iter().map(|(call, _)| call.metric()).collect()
+    }
+
+    fn start(&mut self, command: &mut Command) -> anyhow::Result<()> {
+        let (mut pid_reader, pid_writer) = pipe()?;
+        let (go_reader, mut go_writer) = pipe()?;
+        unsafe {
+            command.pre_exec(move || {
+                let pid = libc::getpid().to_ne_bytes();
+                let mut go = [0u8];
+                let sent = libc::write(pid_writer.as_raw_fd(), pid.as_ptr().cast(), pid.len());
+                if sent != pid.len() as isize
+                    || libc::read(go_reader.as_raw_fd(), go.as_mut_ptr().cast(), 1) != 1
+                {
+                    return Err(std::io::Error::from_raw_os_error(libc::EPIPE));
+                }
+                Ok(())
+            });
+        }
+        let calls = self.calls.clone();
+        self.opener = Some(std::thread::spawn(move || {
+            let mut pid = [0; 4];
+            pid_reader.read_exact(&mut pid)?;
+            let counters = calls
+                .into_iter()
+                .map(|(call, probe)| {
+                    prf::Builder::new(probe)
+                        .observe_pid(libc::pid_t::from_ne_bytes(pid))
+                        .enable_on_exec(true)
+                        .build()
+                        .map_err(|e| anyhow::anyhow!("cannot count the calls of {call}: {e}"))
+                })
+                .collect();
+            // The benchmark runs even if the counters could not be opened, to report why
+            // once it is done.
+            go_writer.write_all(&[1])?;
+            counters
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self, _outcome: &Outcome) -> anyhow::Result<()> {
+        let opener = self.opener.take().expect("stopped after starting");
+        let counters = opener.join().expect("the call counter thread panicked")?;
+        for ((call, _), mut counter) in self.calls.iter().zip(counters) {
+            self.values.insert(call.metric(), counter.read()? as f64);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self) -> anyhow::Result<Metrics> {
+        Ok(std::mem::take(&mut self.values))
+    }
+}
@@ -0,0 +1,187 @@
+//! Backends running the benchmarks under Valgrind tools, whose counts are deterministic.
+
+use std::{collections::HashMap, path::PathBuf, process::Command};
+
+use crate::{
+    backend::Backend,
+    child::Outcome,
+    command,
+    metrics::{Metric, Metrics, Reading},
+    temp::TempDir,
+};
+
+/// Reads the totals of the events of a Cachegrind or Callgrind output file.
+fn parse_totals(contents: &str) -> std::io::Result<HashMap<String, u64>> {
+    let invalid =
+        |message: &str| std::io::Error::other(format!("invalid Valgrind output: {message}"));
+    let mut events = None;
+    let mut totals = None;
+    for line in contents.lines() {
+        if let Some(names) = line.strip_prefix("events:") {
+            events = Some(names.split_whitespace().collect::<Vec<_>>());
+        } else if let Some(values) = line
+            .strip_prefix("summary:")
+            .or_else(|| line.strip_prefix("totals:"))
+        {
+            totals = Some(
+                values
+                    .split_whitespace()
+                    .map(|value| value.parse::<u64>().map_err(|_| invalid(line)))
+                    .collect::<std::io::Result<Vec<_>>>()?,
+            );
+        }
+    }
+    let (Some(events), Some(totals)) = (events, totals) else {
+        return Err(invalid("no events or summary line"));
+    };
+    // Trailing events that are zero may be left out of the summary.
+    Ok(events
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name.to_owned(), totals.get(i).copied().unwrap_or(0)))
+        .collect())
+}
+
+/// Self cost of the event at `event` in each function of a Callgrind output file.
+fn parse_functions(contents: &str, event: usize) -> Metrics {
+    let mut positions = 1;
+    let mut names: HashMap<String, String> = HashMap::new();
+    // Resolves the name compression of `fn=(id) name` and `fn=(id)` specifications.
+    let mut resolve = |spec: &str| -> String {
+        let spec = spec.trim();
+        let Some((id, name)) = spec.strip_prefix('(').and_then(|rest| rest.split_once(')')) else {
+            return spec.to_owned();
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            names.get(id).cloned().unwrap_or_else(|| id.to_owned())
+        } else {
+            names.insert(id.to_owned(), name.to_owned());
+            name.to_owned()
+        }
+    };
+    let mut functions = Metrics::new();
+    let mut current = None;
+    let mut after_calls = false;
+    for line in contents.lines() {
+        if let Some(spec) = line.strip_prefix("positions:") {
+            positions = spec.split_whitespace().count();
+        } else if let Some(spec) = line.strip_prefix("fn=") {
+            current = Some(resolve(spec));
+        } else if let Some(spec) = line.strip_prefix("cfn=") {
+            resolve(spec);
+        } else if line.starts_with("calls=") {
+            after_calls = true;
+        } else if line.starts_with(|c: char| c.is_ascii_digit() || "+-*".contains(c)) {
+            // The cost line following a call is the inclusive cost of the callee.
+            if std::mem::take(&mut after_calls) {
+                continue;
+            }
+            let (Some(function), Some(cost)) =
+                (&current, line.split_whitespace().nth(positions + event))
+            else {
+                continue;
+            };
+            if let Ok(cost) = cost.parse::<u64>() {
+                *functions.entry(function.clone()).or_default() += cost as f64;
+            }
+        }
+    }
+    functions.retain(|_, cost| *cost > 0.);
+    functions
+}
+
+fn find_valgrind() -> anyhow::Result<()> {
+    match command::resolve_program("valgrind") {
+        Some(_) => Ok(()),
+        None => anyhow::bail!("cannot find valgrind, which the backend runs the benchmarks under"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Cachegrind,
+    Callgrind,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Cachegrind => "cachegrind",
+            Tool::Callgrind => "callgrind",
+        }
+    }
+}
+
+pub struct Valgrind {
+    tool: Tool,
+    metrics: Vec<&'static Metric>,
+    /// Private directory of the output file of the tool.
+    dir: TempDir,
+}
+
+impl Valgrind {
+    pub fn new(tool: Tool, metrics: Vec<&'static Metric>) -> anyhow::Result<Self> {
+        find_valgrind()?;
+        Ok(Self {
+            tool,
+            metrics,
+            dir: TempDir::new(&format!("perf-bench-{}", tool.name()))?,
+        })
+    }
+
+    fn out_file(&self) -> PathBuf {
+        self.dir.path().join(format!("{}.out", self.tool.name()))
+    }
+}
+
+impl Backend for Valgrind {
+    fn wrap(&mut self, command: Command) -> Command {
+        // Only the instruction count is available without simulating the caches.
+        let cache_sim = self.metrics.iter().any(|m| m.valgrind_events() != ["Ir"]);
+        let options = [
+            format!("--tool={}", self.tool.name()),
+            "--quiet".to_owned(),
+            format!(
+                "--{}-out-file={}",
+                self.tool.name(),
+                self.out_file().display()
+            ),
+            format!("--cache-sim={}", if cache_sim { "yes" } else { "no" }),
+        ];
+        command::wrap(command, "valgrind", &options)
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, _outcome: &Outcome) -> std::io::Result<Reading> {
+        let contents = std::fs::read_to_string(self.out_file())?;
+        std::fs::remove_file(self.out_file())?;
+        let totals = parse_totals(&contents)?;
+        let mut reading = Reading::default();
+        for metric in &self.metrics {
+            let value: u64 = metric
+                .valgrind_events()
+                .iter()
+                .map(|event| totals.get(*event).copied().unwrap_or(0))
+                .sum();
+            reading.metrics.insert(metric.name.to_owned(), value as f64);
+        }
+        if self.tool == Tool::Callgrind {
+            let events = contents
+                .lines()
+                .find_map(|line| line.strip_prefix("events:"))
+                .unwrap_or_default();
+            if let Some(ir) = events.split_whitespace().position(|event| event == "Ir") {
+                reading.functions = parse_functions(&contents, ir);
+            }
+        }
+        Ok(reading)
+    }
+
+    fn unavailable(&self) -> &[&'static str] {
+        &[]
+    }
+}
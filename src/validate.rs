@@ -0,0 +1,183 @@
+//! Sanity checks of a results file before it is relied on as a baseline.
+
+use crate::{
+    metrics,
+    results::{self, ResultsFile},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The file should not be used as a baseline.
+    Error,
+    /// The file is usable, but some of its measurements deserve suspicion.
+    Warning,
+}
+
+pub struct Issue {
+    pub severity: Severity,
+    /// Entry the issue is about, if not the whole file.
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match &self.key {
+            Some(key) => write!(f, "{severity}: {key}: {}", self.message),
+            None => write!(f, "{severity}: {}", self.message),
+        }
+    }
+}
+
+/// Checks the units a results file declares against those of the metrics known to this
+/// version, given the raw JSON of the file.
+pub fn check_units(raw: &serde_json::Value) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let Some(units) = raw.get("units").and_then(|units| units.as_object()) else {
+        return issues;
+    };
+    for (name, unit) in units {
+        let Some(metric) = metrics::known(name) else {
+            continue;
+        };
+        if unit.as_str() != Some(metric.unit.name()) {
+            issues.push(Issue {
+                severity: Severity::Error,
+                key: None,
+                message: format!(
+                    "{name} is recorded in {unit}, but this version measures it in {}",
+                    metric.unit.name()
+                ),
+            });
+        }
+    }
+    issues
+}
+
+/// Looks for data that would make comparisons against `file` misleading.
+pub fn check(file: &ResultsFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut file_issue = |severity, message: String| {
+        issues.push(Issue {
+            severity,
+            key: None,
+            message,
+        })
+    };
+    if file.results.is_empty() {
+        file_issue(Severity::Error, "the file has no results".to_owned());
+    }
+    if file.metadata.is_none() {
+        file_issue(
+            Severity::Warning,
+            "no metadata, so differences in the recording environment cannot be detected"
+                .to_owned(),
+        );
+    }
+    let names = results::metric_names([&file.results]);
+    for name in &names {
+        if metrics::known(name).is_none() {
+            file_issue(
+                Severity::Warning,
+                format!("unknown metric {name:?}, recorded by another version?"),
+            );
+        }
+    }
+    for (key, measure) in &file.results {
+        let mut issue = |severity, message: String| {
+            issues.push(Issue {
+                severity,
+                key: Some(key.clone()),
+                message,
+            })
+        };
+        if measure.skipped {
+            let reason = measure.skip_reason.as_deref().unwrap_or("no reason given");
+            issue(Severity::Warning, format!("skipped: {reason}"));
+            continue;
+        }
+        for (name, &value) in &measure.metrics {
+            let may_be_zero = metrics::known(name).is_some_and(|m| m.may_be_zero());
+            if value == 0. && !may_be_zero {
+                issue(
+                    Severity::Error,
+                    format!("{name} is zero, so relative differences to it are undefined"),
+                );
+            } else if value < 0. || !value.is_finite() {
+                issue(Severity::Error, format!("{name} has invalid value {value}"));
+            }
+        }
+        let missing: Vec<&str> = names
+            .iter()
+            .filter(|name| !measure.metrics.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            issue(
+                Severity::Warning,
+                format!("missing {}, recorded for other entries", missing.join(", ")),
+            );
+        }
+        if measure.status.is_failure() {
+            issue(
+                Severity::Error,
+                format!("the runs failed: {}", measure.status),
+            );
+        }
+        if let Some(limit) = &measure.limit_hit {
+            issue(
+                Severity::Error,
+                format!("the run most likely failed on the {limit} limit"),
+            );
+        }
+        if measure.incorrect {
+            issue(
+                Severity::Error,
+                "some runs printed another output than the expected one".to_owned(),
+            );
+        }
+        if measure.is_degraded() {
+            issue(
+                Severity::Warning,
+                format!(
+                    "degraded measurement, without {} for lack of counters",
+                    measure.unavailable.join(", ")
+                ),
+            );
+        }
+        if measure.multiplexed {
+            issue(
+                Severity::Warning,
+                "counters were multiplexed, so their values are estimates".to_owned(),
+            );
+        }
+        if measure.smt_shared {
+            issue(
+                Severity::Warning,
+                "the SMT siblings of its cores stayed online and may have interfered".to_owned(),
+            );
+        }
+        if let Some(&faults) = measure.metrics.get("major_faults").filter(|&&n| n > 0.) {
+            issue(
+                Severity::Warning,
+                format!(
+                    "{faults} major page fault(s), so its inputs were not all in the page cache"
+                ),
+            );
+        }
+        if measure.is_noisy() {
+            issue(
+                Severity::Warning,
+                format!(
+                    "measured on a busy machine ({:.1}% interference)",
+                    measure.interference * 100.
+                ),
+            );
+        }
+    }
+    issues
+}
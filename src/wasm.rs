@@ -0,0 +1,34 @@
+//! Precompilation of the `.wat` inputs to binary `.wasm` modules, so that parsing the text
+//! format is left out of the measurements.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+fn is_wat(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "wat")
+}
+
+/// Compiles each `.wat` file among `inputs` into `dir`, returning the module of each.
+pub fn precompile<'a>(
+    inputs: impl IntoIterator<Item = &'a Path>,
+    dir: &Path,
+) -> anyhow::Result<HashMap<PathBuf, PathBuf>> {
+    let mut modules = HashMap::new();
+    for (i, wat_file) in inputs.into_iter().filter(|input| is_wat(input)).enumerate() {
+        if modules.contains_key(wat_file) {
+            continue;
+        }
+        let binary = wat::parse_file(wat_file)
+            .map_err(|e| anyhow::anyhow!("cannot compile {}: {e}", wat_file.display()))?;
+        let stem = wat_file.file_stem().unwrap_or_default().to_string_lossy();
+        let module = dir.join(format!("{i}-{stem}.wasm"));
+        std::fs::write(&module, binary)?;
+        modules.insert(wat_file.to_owned(), module);
+    }
+    if !modules.is_empty() {
+        tracing::info!("precompiled {} .wat input(s)", modules.len());
+    }
+    Ok(modules)
+}
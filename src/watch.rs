@@ -0,0 +1,145 @@
+//! inotify-based change detection for `watch`.
+
+#[cfg(target_os = "linux")]
+use std::{
+    collections::HashMap,
+    ffi::{CString, OsString},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+    path::Path,
+    time::Duration,
+};
+use std::{io, path::PathBuf};
+
+/// Events after the first one arriving within this delay are merged into the same change,
+/// so that e.g. a linker writing the binary in several steps triggers a single re-run.
+#[cfg(target_os = "linux")]
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[cfg(target_os = "linux")]
+pub struct Watcher {
+    fd: OwnedFd,
+    /// Files of interest, by watch descriptor of their parent directory.
+    ///
+    /// Watching the directories rather than the files themselves catches files replaced
+    /// through a rename, which is how most build tools update their outputs.
+    watches: HashMap<i32, (PathBuf, Vec<OsString>)>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    pub fn new(paths: &[PathBuf]) -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut by_dir: HashMap<PathBuf, Vec<OsString>> = HashMap::new();
+        for path in paths {
+            let path = std::path::absolute(path)?;
+            let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            by_dir
+                .entry(dir.to_owned())
+                .or_default()
+                .push(name.to_owned());
+        }
+        let mut watches = HashMap::new();
+        for (dir, names) in by_dir {
+            let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+            let wd = unsafe {
+                libc::inotify_add_watch(
+                    fd.as_raw_fd(),
+                    c_dir.as_ptr(),
+                    libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE,
+                )
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            watches.insert(wd, (dir, names));
+        }
+        Ok(Self { fd, watches })
+    }
+
+    /// Blocks until at least one watched file changed and returns the changed files.
+    pub fn wait(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        while changed.is_empty() {
+            self.read_events(None, &mut changed)?;
+        }
+        while self.read_events(Some(DEBOUNCE), &mut changed)? {}
+        changed.sort();
+        changed.dedup();
+        Ok(changed)
+    }
+
+    /// Reads one batch of events, returning `false` if `timeout` elapsed first.
+    fn read_events(
+        &self,
+        timeout: Option<Duration>,
+        changed: &mut Vec<PathBuf>,
+    ) -> io::Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis() as libc::c_int);
+        match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+            0 => return Ok(false),
+            r if r < 0 => {
+                let err = io::Error::last_os_error();
+                return if err.kind() == io::ErrorKind::Interrupted {
+                    Ok(true)
+                } else {
+                    Err(err)
+                };
+            }
+            _ => {}
+        }
+        let mut buf = [0u8; 4096];
+        let len = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut offset = 0;
+        while offset + header <= len as usize {
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+            let name = &buf[offset + header..offset + header + event.len as usize];
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            offset += header + event.len as usize;
+            let Some((dir, names)) = self.watches.get(&event.wd) else {
+                continue;
+            };
+            let name = OsString::from_vec(name.to_vec());
+            if names.contains(&name) {
+                changed.push(Path::new(dir).join(name));
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Watching for changes relies on inotify, only available on Linux.
+#[cfg(not(target_os = "linux"))]
+pub enum Watcher {}
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new(_paths: &[PathBuf]) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "watching files for changes is only available on Linux",
+        ))
+    }
+
+    pub fn wait(&mut self) -> io::Result<Vec<PathBuf>> {
+        match *self {}
+    }
+}